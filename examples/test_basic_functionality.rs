@@ -7,7 +7,6 @@
 //! ```
 //!
 use embedded_can::StandardId;
-use env_logger;
 use log::error;
 use uds_rs::{ResetType, UdsClient, UdsError};
 
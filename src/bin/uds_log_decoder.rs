@@ -0,0 +1,92 @@
+//! Decodes UDS communication captured in candump (`candump -l`) or Vector ASC log.
+//!
+//! ```text
+//! uds_log_decoder [--json] [--pair <request id>:<response id>]... <log file>
+//! ```
+//! CAN IDs are in hex, IDs longer than 3 digits are extended. Without `--pair` the OBD-II
+//! addressing is used - physical 7E0-7E7 to 7E8-7EF and functional 7DF.
+use std::fs::File;
+use std::io::BufReader;
+use std::process::ExitCode;
+use uds_rs::{decode_log, read_can_log, ExtendedId, Id, StandardId};
+
+const USAGE: &str =
+    "Usage: uds_log_decoder [--json] [--pair <request id>:<response id>]... <log file>";
+
+fn parse_id(raw: &str) -> Option<Id> {
+    let value = u32::from_str_radix(raw, 16).ok()?;
+    if raw.len() > 3 {
+        Some(Id::Extended(ExtendedId::new(value)?))
+    } else {
+        Some(Id::Standard(StandardId::new(value as u16)?))
+    }
+}
+
+fn parse_pair(raw: &str) -> Option<(Id, Id)> {
+    let (request_id, response_id) = raw.split_once(':')?;
+    Some((parse_id(request_id)?, parse_id(response_id)?))
+}
+
+fn obd_pairs() -> Vec<(Id, Id)> {
+    let standard = |id| Id::Standard(StandardId::new(id).unwrap());
+    (0..8)
+        .flat_map(|ecu| {
+            let response_id = standard(0x7e8 + ecu);
+            [
+                (standard(0x7e0 + ecu), response_id),
+                (standard(0x7df), response_id),
+            ]
+        })
+        .collect()
+}
+
+fn main() -> ExitCode {
+    let mut json = false;
+    let mut pairs = vec![];
+    let mut path = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--pair" => match args.next().as_deref().and_then(parse_pair) {
+                Some(pair) => pairs.push(pair),
+                None => {
+                    eprintln!("Invalid CAN ID pair\n{}", USAGE);
+                    return ExitCode::FAILURE;
+                }
+            },
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return ExitCode::SUCCESS;
+            }
+            _ if path.is_none() && !arg.starts_with('-') => path = Some(arg),
+            _ => {
+                eprintln!("Unexpected argument {}\n{}", arg, USAGE);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    let Some(path) = path else {
+        eprintln!("{}", USAGE);
+        return ExitCode::FAILURE;
+    };
+    if pairs.is_empty() {
+        pairs = obd_pairs();
+    }
+
+    let frames = match File::open(&path).and_then(|file| read_can_log(BufReader::new(file))) {
+        Ok(frames) => frames,
+        Err(e) => {
+            eprintln!("Reading {} failed: {}", path, e);
+            return ExitCode::FAILURE;
+        }
+    };
+    for entry in decode_log(&frames, &pairs) {
+        if json {
+            println!("{}", entry.to_json());
+        } else {
+            println!("{}", entry);
+        }
+    }
+    ExitCode::SUCCESS
+}
@@ -7,6 +7,9 @@
 //! For the correct behaviour, you need to have Linux kernel with applied patch:
 //! https://lore.kernel.org/linux-can/20230818114345.142983-1-lukas.magel@posteo.net/#r
 //!
+//! If the patched kernel ISO-TP module is not available, userspace ISO-TP implementation running
+//! over raw CAN socket can be used instead - see [UdsSocket::new_userspace].
+//!
 //!
 //! ## Hierarchy
 //!
@@ -15,7 +18,17 @@
 //! where structure of service module is described
 //!
 //! module __communication__ - basic communication framework. Purpose of this module is to provide send
-//! and receive functionality for UdsClient.
+//! and receive functionality for UdsClient. Any type implementing [UdsTransport] can be used as
+//! the communication backend - see [UdsClient::new_from_transport].
+//!
+//! module __functional__ - [FunctionalUdsClient] broadcasting requests on the functional ID and collecting
+//! responses of multiple ECUs.
+//!
+//! module __blocking__ - `BlockingUdsClient` with synchronous API, available with
+//! the `blocking` cargo feature.
+//!
+//! module __log_decoder__ - offline decoding of candump and ASC CAN logs into transcript of UDS
+//! requests and responses, see [decode_log].
 //!
 //! All communication was designed to be used primarily with ISO 14229-1:2013 definition of UDS.
 //!
@@ -26,13 +39,17 @@
 //! sudo ip l set dev can0 up type can bitrate 500000
 //! ```
 //!
-//! ```rust
-//! use uds_rs::{UdsClient, UdsError};
+//! ```rust,no_run
+//! use uds_rs::{StandardId, UdsClient, UdsError};
 //!
 //! #[tokio::main(flavor = "current_thread")]
 //! async fn main() -> Result<(), UdsError> {
 //!     // Create client
-//!     let c = UdsClient::new("can0", 0x774, 0x70A)?;
+//!     let c = UdsClient::new(
+//!         "can0",
+//!         StandardId::new(0x774).expect("Invalid src id"),
+//!         StandardId::new(0x70A).expect("Invalid dst id"),
+//!     )?;
 //!
 //!     // read ecu VIN
 //!     let read_data_result = c.read_data_by_identifier(&[0xf18a]).await;
@@ -62,11 +79,20 @@
 //! ```
 //! # Notes for development
 //! ## Communication architecture
-//! Current communication architecture is strictly bounded request-response together. It would be
-//! much better to have these two interactions separated into queues and adding one producer for writes and one consumer
-//! for reads.
+//! Requests are written to the transport directly by the calling service method, while reading is
+//! owned by background task spawned together with UdsClient. The task routes each response to the
+//! pending request by its SID (rejected SID for negative responses), so any number of NRC 0x78
+//! followed by the final response is delivered to the right caller.
 //!
-//! Without this functionality the services like ReadDataByPeriodicIdentifier cannot be implemented.
+//! Requests are serialized, even when sent from clones of UdsClient in different tasks, as the
+//! server processes only one request at a time. Cancelled request keeps its registration until
+//! its late response arrives, so the response can not be consumed by the following request.
+//!
+//! Messages which do not belong to any pending request - periodic data (0x6A) or responses
+//! triggered by ResponseOnEvent - are published as unsolicited responses, see
+//! [UdsClient::unsolicited_responses]. Periodic data of subscribed identifiers are available as
+//! stream by [UdsClient::subscribe_periodic_data], responses to subscribed events by
+//! [UdsClient::subscribe_response_on_event].
 //!
 //! ## Services implementation
 //! each service consists of three steps  
@@ -75,28 +101,78 @@
 //! __send and receive__ - passing composed vector as slice to the communication backend and returning raw response  
 //! __parse function__ - parsing received raw response &\[u8\] and serializing it into UdsMessage
 //!
+#[cfg(feature = "blocking")]
+mod blocking;
 mod communication;
+mod dispatcher;
+mod doip;
+mod functional;
+mod isotp;
+mod log_decoder;
+mod mock_transport;
+mod recording;
+mod stop_on_drop;
 
 mod clear_diagnostic_information;
+mod communication_control;
+mod control_dtc_setting;
+mod diagnostic_session_control;
+mod dynamically_define_data_identifier;
 mod ecu_reset;
+mod input_output_control_by_identifier;
 mod read_data_by_identifier;
+mod read_data_by_periodic_identifier;
 mod read_dtc_information;
 mod read_memory_by_address;
+mod read_scaling_data_by_identifier;
+mod request_download;
+mod request_upload;
+mod response_on_event;
+mod routine_control;
+mod security_access;
+mod tester_present;
+mod transfer_data;
 mod uds_definitions;
 mod write_data_by_identifier;
-mod diagnostic_session_control;
+mod write_memory_by_address;
 
+use dispatcher::{Dispatcher, PendingResponse};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
+use tokio::time::Instant;
 
-pub use crate::uds::clear_diagnostic_information::*;
+#[cfg(feature = "blocking")]
+pub use crate::uds::blocking::*;
 pub use crate::uds::communication::*;
+pub use crate::uds::communication_control::*;
+pub use crate::uds::control_dtc_setting::*;
+pub use crate::uds::diagnostic_session_control::*;
+pub use crate::uds::dispatcher::UnsolicitedResponses;
+pub use crate::uds::doip::*;
+pub use crate::uds::dynamically_define_data_identifier::*;
 pub use crate::uds::ecu_reset::*;
+pub use crate::uds::functional::*;
+pub use crate::uds::input_output_control_by_identifier::*;
+pub use crate::uds::isotp::*;
+pub use crate::uds::log_decoder::*;
+pub use crate::uds::mock_transport::*;
 pub use crate::uds::read_data_by_identifier::*;
+pub use crate::uds::read_data_by_periodic_identifier::*;
 pub use crate::uds::read_dtc_information::*;
 pub use crate::uds::read_memory_by_address::*;
+pub use crate::uds::read_scaling_data_by_identifier::*;
+pub use crate::uds::recording::*;
+pub use crate::uds::request_download::*;
+pub use crate::uds::request_upload::*;
+pub use crate::uds::response_on_event::*;
+pub use crate::uds::routine_control::*;
+pub use crate::uds::security_access::*;
+pub use crate::uds::tester_present::*;
+pub use crate::uds::transfer_data::*;
 pub use crate::uds::uds_definitions::*;
 pub use crate::uds::write_data_by_identifier::*;
-use diagnostic_session_control::DiagnosticSessionControlResponse;
+pub use crate::uds::write_memory_by_address::*;
 #[allow(unused_imports)]
 use log::{debug, error, info, trace, warn};
 use thiserror::Error;
@@ -110,10 +186,27 @@ pub enum UdsResponse {
     EcuReset(DataFormat<EcuResetResponse>),
     ReadDataByIdentifier(DataFormat<ReadDataByIdentifierResponse>),
     ReadMemoryByAddress(DataFormat<ReadMemoryByAddressResponse>),
+    ReadScalingDataByIdentifier(DataFormat<ReadScalingDataByIdentifierResponse>),
     ReadDTCInformation(DataFormat<ReadDTCInformationResponse>),
     ClearDiagnosticInformation,
     WriteDataByIdentifier(DataFormat<WriteDataByIdentifierResponse>),
+    WriteMemoryByAddress(DataFormat<WriteMemoryByAddressResponse>),
+    InputOutputControlByIdentifier(DataFormat<InputOutputControlResponse>),
+    CommunicationControl(DataFormat<CommunicationControlResponse>),
+    ControlDtcSetting(DataFormat<ControlDtcSettingResponse>),
+    ReadDataByPeriodicIdentifier(DataFormat<ReadDataByPeriodicIdentifierResponse>),
+    DynamicallyDefineDataIdentifier(DataFormat<DynamicallyDefineDataIdentifierResponse>),
+    ResponseOnEvent(DataFormat<ResponseOnEventResponse>),
     DiagnosticSessionControl(DataFormat<DiagnosticSessionControlResponse>),
+    TesterPresent,
+    SecurityAccess(DataFormat<SecurityAccessResponse>),
+    RoutineControl(DataFormat<RoutineControlResponse>),
+    RequestDownload(DataFormat<RequestDownloadResponse>),
+    RequestUpload(DataFormat<RequestUploadResponse>),
+    TransferData(DataFormat<TransferDataResponse>),
+    RequestTransferExit(DataFormat<RequestTransferExitResponse>),
+    /// Request was sent with suppress positive response bit and no negative response arrived
+    PositiveResponseSuppressed,
 }
 
 /// If program was able to parse received data, the response struct will be stored in Parsed.
@@ -142,6 +235,18 @@ pub enum UdsError {
         received: u16,
         raw_message: Vec<u8>,
     },
+    #[error("Sent and received routine identifier don't match. Expected: {expected:x}, Received: {received:x}")]
+    RidMismatch {
+        expected: u16,
+        received: u16,
+        raw_message: Vec<u8>,
+    },
+    #[error("Sent and received security access type don't match. Expected: {expected:x}, Received: {received:x}")]
+    SecurityAccessTypeMismatch {
+        expected: u8,
+        received: u8,
+        raw_message: Vec<u8>,
+    },
     #[error("Received message doesn't correspond to expected length. Received message: {raw_message:x?}")]
     InvalidLength { raw_message: Vec<u8> },
     #[error("Negative response code was received: {nrc:?}")]
@@ -162,6 +267,27 @@ pub enum UdsError {
     RequestEmpty,
     #[error("Error from lower layer {error:?}")]
     CommunicationError { error: UdsCommunicationError },
+    #[error("Server did not respond to request {sid:x} within {timeout:?}")]
+    ResponseTimeout { sid: u8, timeout: Duration },
+    #[error("Key sent to unlock security level {level:x} is not valid")]
+    InvalidKey { level: u8 },
+    #[error("Number of attempts to unlock security level {level:x} was exceeded")]
+    ExceededNumberOfAttempts { level: u8 },
+    #[error(
+        "Delay required before next attempt to unlock security level {level:x} has not expired"
+    )]
+    RequiredTimeDelayNotExpired { level: u8 },
+    #[error("Transfer aborted after {transferred} bytes: {reason}")]
+    TransferAborted {
+        transferred: usize,
+        reason: Box<UdsError>,
+    },
+    #[error("Writing of uploaded data failed: {kind}")]
+    SinkError { kind: std::io::ErrorKind },
+    #[error(
+        "Server accepted block {block_sequence_counter:x}, but its response with data was lost"
+    )]
+    BlockLost { block_sequence_counter: u8 },
 }
 
 /// Struct containing rejected sid and nrc for UdsError::Enc type
@@ -184,21 +310,93 @@ impl From<communication::Error> for UdsError {
     }
 }
 
+/// Time limits for the server responses, see ISO 14229-2.
+///
+/// Default values correspond to the default session. After successful
+/// [UdsClient::diagnostic_session_control] the client adopts P2 and P2* reported by the server,
+/// after successful [UdsClient::ecu_reset] it returns to the default P2 and P2*.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ResponseTiming {
+    /// maximum time between the request and the first response
+    pub p2: Duration,
+    /// maximum time between NRC 0x78 RequestCorrectlyReceivedResponsePending and next response
+    pub p2_star: Duration,
+    /// added to both P2 and P2* to cover the transport latency. Timeout is measured until the whole
+    /// response is received, so for long segmented responses the margin needs to be higher.
+    pub network_margin: Duration,
+}
+
+impl Default for ResponseTiming {
+    fn default() -> Self {
+        ResponseTiming {
+            p2: Duration::from_millis(50),
+            p2_star: Duration::from_millis(5000),
+            network_margin: Duration::from_millis(500),
+        }
+    }
+}
+
+/// Handling of the negative responses, which do not finish the request.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// how many times is the request repeated after NRC 0x21 BusyRepeatRequest
+    pub max_busy_retries: u32,
+    /// delay before the request is repeated after NRC 0x21 BusyRepeatRequest
+    pub busy_retry_delay: Duration,
+    /// how many NRC 0x78 RequestCorrectlyReceivedResponsePending are accepted for single request
+    pub max_pending_extensions: u32,
+    /// overall time limit for the request including all repeats and pending responses
+    pub overall_deadline: Option<Duration>,
+    /// how many times is SecurityAccess requestSeed repeated after NRC 0x37
+    /// RequiredTimeDelayNotExpired, 0 disables the repeating
+    pub max_security_delay_retries: u32,
+    /// delay before the seed is requested again after NRC 0x37 RequiredTimeDelayNotExpired
+    pub security_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_busy_retries: 3,
+            busy_retry_delay: Duration::from_millis(50),
+            max_pending_extensions: 100,
+            overall_deadline: None,
+            max_security_delay_retries: 0,
+            security_delay: Duration::from_secs(10),
+        }
+    }
+}
+
 /// Main struct providing all API calls.
+///
+/// Communication is done trough any [UdsTransport], by default [UdsSocket] is used.
+///
+/// Client spawns background task receiving responses, so it has to be created from within tokio
+/// runtime. The task is stopped when the client and all its clones are dropped.
+///
+/// Client is a cheap handle, clones share the connection, timing and retry policy and can be
+/// moved to other tasks. Requests of all clones are serialized - each waits until the previous
+/// one receives its final response. Dropping the future of the service method is safe, late
+/// response to the cancelled request is discarded before the next request is sent.
+#[derive(Clone)]
 pub struct UdsClient {
-    socket: UdsSocket,
+    dispatcher: Arc<Dispatcher>,
+    keep_alive: Arc<Mutex<tester_present::KeepAlive>>,
+    timing: Arc<Mutex<ResponseTiming>>,
+    retry_policy: Arc<Mutex<RetryPolicy>>,
+    /// identifiers of the live [PeriodicDataStream]s
+    periodic_identifiers: Arc<Mutex<HashSet<u8>>>,
 }
 
 impl UdsClient {
-
     pub fn new(
         canifc: &str,
         src: impl Into<Id>,
         dst: impl Into<Id>,
     ) -> Result<UdsClient, UdsError> {
-        Ok(UdsClient {
-            socket: UdsSocket::new(canifc, src, dst)?,
-        })
+        Ok(UdsClient::new_from_transport(UdsSocket::new(
+            canifc, src, dst,
+        )?))
     }
 
     pub fn new_vw(
@@ -206,78 +404,200 @@ impl UdsClient {
         src: impl Into<Id>,
         dst: impl Into<Id>,
     ) -> Result<UdsClient, UdsError> {
-        Ok(UdsClient {
-            socket: UdsSocket::new_vw(canifc, src, dst)?,
-        })
+        Ok(UdsClient::new_from_transport(UdsSocket::new_vw(
+            canifc, src, dst,
+        )?))
     }
 
     pub fn new_from_socket(socket: UdsSocket) -> UdsClient {
-        UdsClient { socket }
+        UdsClient::new_from_transport(socket)
+    }
+
+    /// Create client communicating trough custom transport layer, e.g. DoIP, userspace ISO-TP or mock
+    pub fn new_from_transport(transport: impl UdsTransport + 'static) -> UdsClient {
+        UdsClient {
+            dispatcher: Arc::new(Dispatcher::new(Arc::new(transport))),
+            keep_alive: Arc::new(Mutex::new(tester_present::KeepAlive::default())),
+            timing: Arc::new(Mutex::new(ResponseTiming::default())),
+            retry_policy: Arc::new(Mutex::new(RetryPolicy::default())),
+            periodic_identifiers: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    /// Currently used response timing
+    pub fn response_timing(&self) -> ResponseTiming {
+        *self.timing.lock().unwrap()
+    }
+
+    /// Override response timing. Note that it will be overwritten by next successful
+    /// [UdsClient::diagnostic_session_control].
+    pub fn set_response_timing(&self, timing: ResponseTiming) {
+        *self.timing.lock().unwrap() = timing;
+    }
+
+    /// Currently used retry policy
+    pub fn retry_policy(&self) -> RetryPolicy {
+        *self.retry_policy.lock().unwrap()
+    }
+
+    pub fn set_retry_policy(&self, policy: RetryPolicy) {
+        *self.retry_policy.lock().unwrap() = policy;
+    }
+
+    /// Subscribe to messages received from the server, which do not belong to any pending
+    /// request, e.g. periodic data or events. Only messages received after the call are returned.
+    pub fn unsolicited_responses(&self) -> UnsolicitedResponses {
+        self.dispatcher.subscribe()
     }
 
     async fn send_and_receive(&self, request: &[u8]) -> Result<Vec<u8>, UdsError> {
-        let mut retry_counter = 0;
-        if request.len() == 0 {
+        self.send_and_receive_suppressible(request, false)
+            .await?
+            .ok_or(UdsError::ResponseEmpty)
+    }
+
+    /// With `suppress_positive_response` returns None, if no negative response arrives within P2.
+    /// After NRC 0x78 the server sends final response even for suppressed request.
+    async fn send_and_receive_suppressible(
+        &self,
+        request: &[u8],
+        suppress_positive_response: bool,
+    ) -> Result<Option<Vec<u8>>, UdsError> {
+        if request.is_empty() {
             return Err(UdsError::RequestEmpty);
         }
-        self.socket.send(&request).await?;
-        let mut raw_response = self.socket.receive().await?;
-        // let mut raw_response = match tokio::time::timeout(Duration::from_millis(2500), self.socket.receive()).await {
-        //     Ok(response) => {
-        //         response?
-        //     }
-        //     Err(_) => {
-        //         // Timeout error
-        //         return Err(UdsError::CommunicationError { error: UdsCommunicationError::GeneralError });
-        //     }
-        // };
-
-        while let Err(e) = parse_for_error(&raw_response) {
-            match e {
-                UdsError::NRC { nrc } => {
-                    if nrc.rejected_sid != request[0] {
-                        return Err(UdsError::SidMismatch {
-                            expected: request[0],
-                            received: nrc.rejected_sid,
-                            raw_message: raw_response,
-                        });
+        let _lock = self.dispatcher.lock().await;
+        let timing = self.response_timing();
+        self.dispatcher.drain_cancelled_request(&timing).await;
+        let mut pending = self.dispatcher.register(request)?;
+        let result = self
+            .exchange(&mut pending, request, suppress_positive_response, timing)
+            .await;
+        pending.complete();
+        result
+    }
+
+    /// Sends the request and waits for its final response, handling NRC 0x21 and 0x78
+    async fn exchange(
+        &self,
+        pending: &mut PendingResponse,
+        request: &[u8],
+        suppress_positive_response: bool,
+        timing: ResponseTiming,
+    ) -> Result<Option<Vec<u8>>, UdsError> {
+        let sid = request[0];
+        let policy = self.retry_policy();
+        let p2 = timing.p2 + timing.network_margin;
+        let p2_star = timing.p2_star + timing.network_margin;
+        let deadline = policy
+            .overall_deadline
+            .map(|deadline| (Instant::now() + deadline, deadline));
+        let mut busy_retries = 0;
+        let mut pending_extensions = 0;
+
+        self.dispatcher.send(request).await?;
+        let mut timeout = p2;
+        let mut only_nrc_expected = suppress_positive_response;
+        loop {
+            let raw_response = match wait_for_response(pending, sid, timeout, deadline).await {
+                Ok(raw_response) => raw_response,
+                Err(UdsError::ResponseTimeout { .. }) if only_nrc_expected => {
+                    debug!("No negative response to suppressed request {:#x}", sid);
+                    return Ok(None);
+                }
+                Err(e) => return Err(e),
+            };
+            let nrc = match parse_for_error(&raw_response) {
+                Ok(()) => return Ok(Some(raw_response)),
+                Err(UdsError::NRC { nrc }) => nrc,
+                Err(e) => return Err(e),
+            };
+            if nrc.rejected_sid != sid {
+                return Err(UdsError::SidMismatch {
+                    expected: sid,
+                    received: nrc.rejected_sid,
+                    raw_message: raw_response,
+                });
+            }
+            match nrc.nrc {
+                NegativeResponseCode::BusyRepeatRequest => {
+                    if busy_retries >= policy.max_busy_retries {
+                        warn!("Service failed after {} repeats", busy_retries);
+                        return Err(UdsError::NRC { nrc });
                     }
-                    match nrc.nrc {
-                        NegativeResponseCode::BusyRepeatRequest => {
-                            // Maybe sleep a little?
-                            retry_counter = retry_counter - 1;
-                            if retry_counter == 0 {
-                                warn!("Service failed after multiple repeats");
-                                return Err(UdsError::NRC { nrc });
-                            }
-                            info!("Received NRC BusyRepeatRequest, repeating");
-                            self.socket.send(&request).await?;
-                            raw_response = self.socket.receive().await?;
-                        }
-                        NegativeResponseCode::RequestCorrectlyReceivedResponsePending => {
-                            info!("NRC RequestCorrectlyReceivedResponsePending received, waiting for next response");
-                            match tokio::time::timeout(Duration::from_millis(2500), self.socket.receive()).await {
-                                Ok(delayed_response) => {
-                                    raw_response = delayed_response?;
-                                }
-                                Err(_) => {
-                                    return Err(UdsError::NRC { nrc });
-                                }
-                            }
-                            break;
-                        }
-                        _ => return Err(UdsError::NRC { nrc }),
+                    busy_retries += 1;
+                    info!(
+                        "Received NRC BusyRepeatRequest, repeating ({}/{})",
+                        busy_retries, policy.max_busy_retries
+                    );
+                    tokio::time::sleep(policy.busy_retry_delay).await;
+                    self.dispatcher.send(request).await?;
+                    timeout = p2;
+                }
+                NegativeResponseCode::RequestCorrectlyReceivedResponsePending => {
+                    if pending_extensions >= policy.max_pending_extensions {
+                        warn!(
+                            "Server requested more than {} response pending extensions",
+                            policy.max_pending_extensions
+                        );
+                        return Err(UdsError::NRC { nrc });
                     }
+                    pending_extensions += 1;
+                    only_nrc_expected = false;
+                    info!("NRC RequestCorrectlyReceivedResponsePending received, waiting for next response");
+                    timeout = p2_star;
                 }
-                _ => {
-                    return Err(e);
-                },
+                _ => return Err(UdsError::NRC { nrc }),
             }
         }
-        Ok(raw_response)
     }
 }
 
+/// Waits for the response at most `timeout`, or until the `deadline` if it comes sooner.
+/// Deadline is passed together with its total duration for error reporting.
+async fn wait_for_response(
+    pending: &mut PendingResponse,
+    sid: u8,
+    timeout: Duration,
+    deadline: Option<(Instant, Duration)>,
+) -> Result<Vec<u8>, UdsError> {
+    let mut wait = timeout;
+    let mut reported = timeout;
+    if let Some((deadline, total)) = deadline {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining < wait {
+            wait = remaining;
+            reported = total;
+        }
+    }
+    match tokio::time::timeout(wait, pending.next()).await {
+        Ok(response) => Ok(response?),
+        Err(_) => {
+            warn!("No response to request {:#x} within {:?}", sid, reported);
+            Err(UdsError::ResponseTimeout {
+                sid,
+                timeout: reported,
+            })
+        }
+    }
+}
+
+/// Parse response of the service and unwrap the part selected by `unwrap`, usually the
+/// [DataFormat::Parsed] content. Used by the procedures composed of several services, any other
+/// response is reported as [UdsError::ResponseIncorrect] with the raw response.
+fn expect_parsed<T>(
+    raw_response: &[u8],
+    parse: impl FnOnce(&[u8]) -> EcuResponseResult,
+    unwrap: impl FnOnce(UdsResponse) -> Option<T>,
+) -> Result<T, UdsError> {
+    unwrap(parse(raw_response)?).ok_or_else(|| {
+        error!("Unexpected response: {:x?}", raw_response);
+        UdsError::ResponseIncorrect {
+            raw_message: raw_response.to_vec(),
+        }
+    })
+}
+
 fn parse_for_error(raw_response: &[u8]) -> Result<(), UdsError> {
     let mut response_iter = raw_response.iter();
     let sid = *response_iter.next().ok_or(UdsError::ResponseEmpty)?;
@@ -300,7 +620,20 @@ fn parse_for_error(raw_response: &[u8]) -> Result<(), UdsError> {
 #[cfg(test)]
 mod tests {
     use crate::uds::uds_definitions::NEGATIVE_RESPONSE_SID;
-    use crate::uds::{parse_for_error, UdsError};
+    use crate::uds::{
+        parse_for_error, DataFormat, DataRecord, MockTransport, NegativeResponseCode, NrcData,
+        ReadDataByIdentifierResponse, ResetType, ResponseTiming, RetryPolicy, UdsClient, UdsError,
+        UdsResponse,
+    };
+    use std::time::Duration;
+
+    fn short_timing() -> ResponseTiming {
+        ResponseTiming {
+            p2: Duration::from_millis(20),
+            p2_star: Duration::from_millis(100),
+            network_margin: Duration::ZERO,
+        }
+    }
 
     #[test]
     fn test_parse_for_error_wrong_nrc() {
@@ -312,4 +645,260 @@ mod tests {
         let result = parse_for_error(&raw_response);
         assert_eq!(Err(expected), result);
     }
+
+    #[tokio::test]
+    async fn test_p2_timeout() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x11, 0x01])
+            .respond_after(Duration::from_millis(200), &[0x51, 0x01]);
+        let client = UdsClient::new_from_transport(mock.clone());
+        client.set_response_timing(short_timing());
+
+        let result = client.ecu_reset(ResetType::HardReset).await;
+        assert_eq!(
+            result,
+            Err(UdsError::ResponseTimeout {
+                sid: 0x11,
+                timeout: Duration::from_millis(20)
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_p2_star_timeout_after_response_pending() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x11, 0x01])
+            .respond_pending(0x11)
+            .respond_after(Duration::from_millis(50), &[0x51, 0x01]);
+        mock.expect(&[0x11, 0x01])
+            .respond_pending(0x11)
+            .respond_after(Duration::from_millis(300), &[0x51, 0x01]);
+        let client = UdsClient::new_from_transport(mock.clone());
+        client.set_response_timing(short_timing());
+
+        let result = client.ecu_reset(ResetType::HardReset).await;
+        assert!(result.is_ok());
+        // successful reset restores the default session timing
+        client.set_response_timing(short_timing());
+        let result = client.ecu_reset(ResetType::HardReset).await;
+        assert_eq!(
+            result,
+            Err(UdsError::ResponseTimeout {
+                sid: 0x11,
+                timeout: Duration::from_millis(100)
+            })
+        );
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_multiple_response_pending_followed_by_positive_response() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x14, 0xff, 0xff, 0xff])
+            .respond_pending(0x14)
+            .respond_pending(0x14)
+            .respond_pending(0x14)
+            .respond(&[0x54]);
+        let client = UdsClient::new_from_transport(mock.clone());
+
+        let result = client.clear_diagnostic_information(0xffffff).await;
+        assert_eq!(result, Ok(UdsResponse::ClearDiagnosticInformation));
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_response_pending_followed_by_negative_response() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x14, 0xff, 0xff, 0xff])
+            .respond_pending(0x14)
+            .respond_pending(0x14)
+            .respond_nrc(0x14, 0x22);
+        let client = UdsClient::new_from_transport(mock.clone());
+
+        let result = client.clear_diagnostic_information(0xffffff).await;
+        assert_eq!(
+            result,
+            Err(UdsError::NRC {
+                nrc: NrcData {
+                    rejected_sid: 0x14,
+                    nrc: NegativeResponseCode::ConditionsNotCorrect
+                }
+            })
+        );
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_busy_retries_are_limited() {
+        let mock = MockTransport::new();
+        for _ in 0..3 {
+            mock.expect(&[0x14, 0xff, 0xff, 0xff]).respond_busy(0x14);
+        }
+        let client = UdsClient::new_from_transport(mock.clone());
+        client.set_retry_policy(RetryPolicy {
+            max_busy_retries: 2,
+            busy_retry_delay: Duration::from_millis(1),
+            ..RetryPolicy::default()
+        });
+
+        let result = client.clear_diagnostic_information(0xffffff).await;
+        assert_eq!(
+            result,
+            Err(UdsError::NRC {
+                nrc: NrcData {
+                    rejected_sid: 0x14,
+                    nrc: NegativeResponseCode::BusyRepeatRequest
+                }
+            })
+        );
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_pending_extensions_are_limited() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x14, 0xff, 0xff, 0xff])
+            .respond_pending(0x14)
+            .respond_pending(0x14)
+            .respond(&[0x54]);
+        let client = UdsClient::new_from_transport(mock.clone());
+        client.set_retry_policy(RetryPolicy {
+            max_pending_extensions: 1,
+            ..RetryPolicy::default()
+        });
+
+        let result = client.clear_diagnostic_information(0xffffff).await;
+        assert_eq!(
+            result,
+            Err(UdsError::NRC {
+                nrc: NrcData {
+                    rejected_sid: 0x14,
+                    nrc: NegativeResponseCode::RequestCorrectlyReceivedResponsePending
+                }
+            })
+        );
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_overall_deadline() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x14, 0xff, 0xff, 0xff])
+            .respond_pending(0x14)
+            .respond_after(Duration::from_millis(40), &[0x7f, 0x14, 0x78])
+            .respond_after(Duration::from_millis(40), &[0x7f, 0x14, 0x78])
+            .respond_after(Duration::from_millis(40), &[0x54]);
+        let client = UdsClient::new_from_transport(mock.clone());
+        client.set_response_timing(short_timing());
+        client.set_retry_policy(RetryPolicy {
+            overall_deadline: Some(Duration::from_millis(60)),
+            ..RetryPolicy::default()
+        });
+
+        let result = client.clear_diagnostic_information(0xffffff).await;
+        assert_eq!(
+            result,
+            Err(UdsError::ResponseTimeout {
+                sid: 0x14,
+                timeout: Duration::from_millis(60)
+            })
+        );
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_suppressed_positive_response() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x11, 0x81]);
+        let client = UdsClient::new_from_transport(mock.clone());
+        client.set_response_timing(short_timing());
+
+        let result = client.ecu_reset_suppressed(ResetType::HardReset).await;
+        assert_eq!(result, Ok(UdsResponse::PositiveResponseSuppressed));
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_suppressed_request_reports_nrc() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x11, 0x81]).respond_nrc(0x11, 0x22);
+        let client = UdsClient::new_from_transport(mock.clone());
+        client.set_response_timing(short_timing());
+
+        let result = client.ecu_reset_suppressed(ResetType::HardReset).await;
+        assert_eq!(
+            result,
+            Err(UdsError::NRC {
+                nrc: NrcData {
+                    rejected_sid: 0x11,
+                    nrc: NegativeResponseCode::ConditionsNotCorrect
+                }
+            })
+        );
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_suppressed_request_after_response_pending() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x11, 0x81])
+            .respond_pending(0x11)
+            .respond_after(Duration::from_millis(50), &[0x51, 0x81]);
+        let client = UdsClient::new_from_transport(mock.clone());
+        client.set_response_timing(short_timing());
+
+        let result = client.ecu_reset_suppressed(ResetType::HardReset).await;
+        assert!(matches!(
+            result,
+            Ok(UdsResponse::EcuReset(DataFormat::Parsed(_)))
+        ));
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_requests_of_clones_are_serialized() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x22, 0xf1, 0x90])
+            .respond_after(Duration::from_millis(10), &[0x62, 0xf1, 0x90, 0x41]);
+        mock.expect(&[0x22, 0xf1, 0x8c])
+            .respond_after(Duration::from_millis(10), &[0x62, 0xf1, 0x8c, 0x42]);
+        let client = UdsClient::new_from_transport(mock.clone());
+        let other = client.clone();
+
+        let task = tokio::spawn(async move { other.read_data_by_identifier(&[0xf18c]).await });
+        let result = client.read_data_by_identifier(&[0xf190]).await;
+        assert!(result.is_ok());
+        assert!(task.await.unwrap().is_ok());
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_response_of_cancelled_request_is_not_consumed_by_next_request() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x22, 0xf1, 0x90])
+            .respond_after(Duration::from_millis(50), &[0x62, 0xf1, 0x90, 0x41]);
+        mock.expect(&[0x22, 0xf1, 0x8c])
+            .respond(&[0x62, 0xf1, 0x8c, 0x42]);
+        let client = UdsClient::new_from_transport(mock.clone());
+
+        let cancelled = tokio::time::timeout(
+            Duration::from_millis(10),
+            client.read_data_by_identifier(&[0xf190]),
+        )
+        .await;
+        assert!(cancelled.is_err());
+        let result = client.read_data_by_identifier(&[0xf18c]).await;
+        assert_eq!(
+            result,
+            Ok(UdsResponse::ReadDataByIdentifier(DataFormat::Parsed(
+                ReadDataByIdentifierResponse {
+                    data_records: vec![DataRecord {
+                        data_identifier: 0xf18c,
+                        data: vec![0x42],
+                    }],
+                }
+            )))
+        );
+        mock.assert_done();
+    }
 }
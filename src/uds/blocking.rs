@@ -0,0 +1,982 @@
+//! # Synchronous client
+//!
+//! Available with the `blocking` cargo feature.
+//!
+//! [BlockingUdsClient] wraps [UdsClient] together with its own current-thread tokio runtime and
+//! provides the same service methods with blocking signatures and the same error types. The
+//! runtime is driven by a dedicated thread, so the background tasks of the client (receiving of
+//! responses, automatic TesterPresent) keep running also between the calls.
+//!
+//! Blocking methods must not be called from within asynchronous context - use [UdsClient]
+//! directly there.
+//!
+//! ```rust,no_run
+//! use uds_rs::{BlockingUdsClient, StandardId, UdsError};
+//!
+//! fn main() -> Result<(), UdsError> {
+//!     let c = BlockingUdsClient::new(
+//!         "can0",
+//!         StandardId::new(0x774).expect("Invalid src id"),
+//!         StandardId::new(0x70A).expect("Invalid dst id"),
+//!     )?;
+//!     println!("{:#x?}", c.read_data_by_identifier(&[0xf18a])?);
+//!     Ok(())
+//! }
+//! ```
+use crate::uds::communication::{Id, UdsCommunicationError, UdsSocket, UdsTransport};
+use crate::uds::{
+    CommunicationControlType, CommunicationType, DtcSettingType, DynamicDataField,
+    DynamicallyDefinedDataIdentifier, EcuResponseResult, Event, EventType, InputOutputControlGuard,
+    InputOutputControlParameter, ResetType, ResponseTiming, RetryPolicy, RoutineControlType,
+    ScaledValue, SeedKeyAlgorithm, TransmissionMode, UdsClient, UdsError,
+};
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+use std::future::Future;
+use std::io::Write;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use tokio::io::AsyncWrite;
+use tokio::runtime::{Builder, Handle};
+use tokio::sync::oneshot;
+
+pub struct BlockingUdsClient {
+    /// Option only to be dropped within the runtime context
+    client: Option<UdsClient>,
+    handle: Handle,
+    shutdown: Option<oneshot::Sender<()>>,
+    runtime_thread: Option<JoinHandle<()>>,
+}
+
+impl BlockingUdsClient {
+    pub fn new(
+        canifc: &str,
+        src: impl Into<Id>,
+        dst: impl Into<Id>,
+    ) -> Result<BlockingUdsClient, UdsError> {
+        BlockingUdsClient::build(|| UdsClient::new(canifc, src, dst))
+    }
+
+    pub fn new_vw(
+        canifc: &str,
+        src: impl Into<Id>,
+        dst: impl Into<Id>,
+    ) -> Result<BlockingUdsClient, UdsError> {
+        BlockingUdsClient::build(|| UdsClient::new_vw(canifc, src, dst))
+    }
+
+    pub fn new_from_socket(socket: UdsSocket) -> Result<BlockingUdsClient, UdsError> {
+        BlockingUdsClient::build(|| Ok(UdsClient::new_from_socket(socket)))
+    }
+
+    /// Transport is created outside of the runtime. If it needs the runtime during its creation
+    /// (e.g. [DoipTransport](crate::DoipTransport)), use [BlockingUdsClient::new_with] instead.
+    pub fn new_from_transport(
+        transport: impl UdsTransport + 'static,
+    ) -> Result<BlockingUdsClient, UdsError> {
+        BlockingUdsClient::build(|| Ok(UdsClient::new_from_transport(transport)))
+    }
+
+    /// Create the client from asynchronous code executed on the runtime of the blocking client,
+    /// e.g. to connect the transport first
+    pub fn new_with<F>(create: F) -> Result<BlockingUdsClient, UdsError>
+    where
+        F: Future<Output = Result<UdsClient, UdsError>>,
+    {
+        let mut blocking = BlockingUdsClient::start_runtime()?;
+        blocking.client = Some(blocking.handle.block_on(create)?);
+        Ok(blocking)
+    }
+
+    fn build(
+        create: impl FnOnce() -> Result<UdsClient, UdsError>,
+    ) -> Result<BlockingUdsClient, UdsError> {
+        let mut blocking = BlockingUdsClient::start_runtime()?;
+        let guard = blocking.handle.enter();
+        blocking.client = Some(create()?);
+        drop(guard);
+        Ok(blocking)
+    }
+
+    fn start_runtime() -> Result<BlockingUdsClient, UdsError> {
+        let runtime = Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| {
+                error!("Failed to create tokio runtime: {:?}", e);
+                UdsCommunicationError::StdIOError
+            })?;
+        let handle = runtime.handle().clone();
+        let (shutdown, shutdown_rx) = oneshot::channel::<()>();
+        let runtime_thread = std::thread::Builder::new()
+            .name("uds-rs-blocking".to_string())
+            .spawn(move || {
+                let _ = runtime.block_on(shutdown_rx);
+            })
+            .map_err(|e| {
+                error!("Failed to spawn runtime thread: {:?}", e);
+                UdsCommunicationError::StdIOError
+            })?;
+        Ok(BlockingUdsClient {
+            client: None,
+            handle,
+            shutdown: Some(shutdown),
+            runtime_thread: Some(runtime_thread),
+        })
+    }
+
+    fn client(&self) -> &UdsClient {
+        self.client.as_ref().unwrap()
+    }
+
+    fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.handle.block_on(future)
+    }
+
+    pub fn response_timing(&self) -> ResponseTiming {
+        self.client().response_timing()
+    }
+
+    pub fn set_response_timing(&self, timing: ResponseTiming) {
+        self.client().set_response_timing(timing)
+    }
+
+    pub fn retry_policy(&self) -> RetryPolicy {
+        self.client().retry_policy()
+    }
+
+    pub fn set_retry_policy(&self, policy: RetryPolicy) {
+        self.client().set_retry_policy(policy)
+    }
+
+    pub fn set_tester_present_interval(&self, interval: Option<Duration>) -> Result<(), UdsError> {
+        self.client().set_tester_present_interval(interval)
+    }
+
+    pub fn tester_present_interval(&self) -> Option<Duration> {
+        self.client().tester_present_interval()
+    }
+
+    pub fn clear_diagnostic_information(&self, group_of_dtc: u32) -> EcuResponseResult {
+        self.block_on(self.client().clear_diagnostic_information(group_of_dtc))
+    }
+
+    pub fn diagnostic_session_control(&self, session_id: u8) -> EcuResponseResult {
+        self.block_on(self.client().diagnostic_session_control(session_id))
+    }
+
+    pub fn diagnostic_session_control_suppressed(&self, session_id: u8) -> EcuResponseResult {
+        self.block_on(
+            self.client()
+                .diagnostic_session_control_suppressed(session_id),
+        )
+    }
+
+    pub fn communication_control(
+        &self,
+        control_type: CommunicationControlType,
+        communication_type: CommunicationType,
+        node_identification_number: Option<u16>,
+    ) -> EcuResponseResult {
+        self.block_on(self.client().communication_control(
+            control_type,
+            communication_type,
+            node_identification_number,
+        ))
+    }
+
+    pub fn communication_control_suppressed(
+        &self,
+        control_type: CommunicationControlType,
+        communication_type: CommunicationType,
+        node_identification_number: Option<u16>,
+    ) -> EcuResponseResult {
+        self.block_on(self.client().communication_control_suppressed(
+            control_type,
+            communication_type,
+            node_identification_number,
+        ))
+    }
+
+    pub fn control_dtc_setting(
+        &self,
+        dtc_setting_type: DtcSettingType,
+        dtc_setting_control_option_record: &[u8],
+    ) -> EcuResponseResult {
+        self.block_on(
+            self.client()
+                .control_dtc_setting(dtc_setting_type, dtc_setting_control_option_record),
+        )
+    }
+
+    pub fn control_dtc_setting_suppressed(
+        &self,
+        dtc_setting_type: DtcSettingType,
+        dtc_setting_control_option_record: &[u8],
+    ) -> EcuResponseResult {
+        self.block_on(
+            self.client().control_dtc_setting_suppressed(
+                dtc_setting_type,
+                dtc_setting_control_option_record,
+            ),
+        )
+    }
+
+    pub fn dynamically_define_data_identifier(
+        &self,
+        definition: &DynamicallyDefinedDataIdentifier,
+    ) -> EcuResponseResult {
+        self.block_on(self.client().dynamically_define_data_identifier(definition))
+    }
+
+    pub fn dynamically_define_data_identifier_suppressed(
+        &self,
+        definition: &DynamicallyDefinedDataIdentifier,
+    ) -> EcuResponseResult {
+        self.block_on(
+            self.client()
+                .dynamically_define_data_identifier_suppressed(definition),
+        )
+    }
+
+    pub fn clear_dynamically_defined_data_identifier(
+        &self,
+        data_identifier: Option<u16>,
+    ) -> EcuResponseResult {
+        self.block_on(
+            self.client()
+                .clear_dynamically_defined_data_identifier(data_identifier),
+        )
+    }
+
+    pub fn clear_dynamically_defined_data_identifier_suppressed(
+        &self,
+        data_identifier: Option<u16>,
+    ) -> EcuResponseResult {
+        self.block_on(
+            self.client()
+                .clear_dynamically_defined_data_identifier_suppressed(data_identifier),
+        )
+    }
+
+    pub fn read_dynamically_defined_data_identifier(
+        &self,
+        definition: &DynamicallyDefinedDataIdentifier,
+    ) -> Result<Vec<DynamicDataField>, UdsError> {
+        self.block_on(
+            self.client()
+                .read_dynamically_defined_data_identifier(definition),
+        )
+    }
+
+    pub fn read_scaling_data_by_identifier(&self, data_identifier: u16) -> EcuResponseResult {
+        self.block_on(
+            self.client()
+                .read_scaling_data_by_identifier(data_identifier),
+        )
+    }
+
+    pub fn read_scaled_data_by_identifier(
+        &self,
+        data_identifier: u16,
+    ) -> Result<Vec<ScaledValue>, UdsError> {
+        self.block_on(
+            self.client()
+                .read_scaled_data_by_identifier(data_identifier),
+        )
+    }
+
+    pub fn response_on_event(
+        &self,
+        event_type: EventType,
+        store_event: bool,
+        event_window_time: u8,
+        event_type_record: &[u8],
+        service_to_respond_to_record: &[u8],
+    ) -> EcuResponseResult {
+        self.block_on(self.client().response_on_event(
+            event_type,
+            store_event,
+            event_window_time,
+            event_type_record,
+            service_to_respond_to_record,
+        ))
+    }
+
+    pub fn response_on_event_suppressed(
+        &self,
+        event_type: EventType,
+        store_event: bool,
+        event_window_time: u8,
+        event_type_record: &[u8],
+        service_to_respond_to_record: &[u8],
+    ) -> EcuResponseResult {
+        self.block_on(self.client().response_on_event_suppressed(
+            event_type,
+            store_event,
+            event_window_time,
+            event_type_record,
+            service_to_respond_to_record,
+        ))
+    }
+
+    pub fn setup_response_on_event(
+        &self,
+        event: &Event,
+        store_event: bool,
+        event_window_time: u8,
+        service_to_respond_to_record: &[u8],
+    ) -> EcuResponseResult {
+        self.block_on(self.client().setup_response_on_event(
+            event,
+            store_event,
+            event_window_time,
+            service_to_respond_to_record,
+        ))
+    }
+
+    pub fn start_response_on_event(&self, event_window_time: u8) -> EcuResponseResult {
+        self.block_on(self.client().start_response_on_event(event_window_time))
+    }
+
+    pub fn start_response_on_event_suppressed(&self, event_window_time: u8) -> EcuResponseResult {
+        self.block_on(
+            self.client()
+                .start_response_on_event_suppressed(event_window_time),
+        )
+    }
+
+    pub fn stop_response_on_event(&self, event_window_time: u8) -> EcuResponseResult {
+        self.block_on(self.client().stop_response_on_event(event_window_time))
+    }
+
+    pub fn stop_response_on_event_suppressed(&self, event_window_time: u8) -> EcuResponseResult {
+        self.block_on(
+            self.client()
+                .stop_response_on_event_suppressed(event_window_time),
+        )
+    }
+
+    pub fn clear_response_on_event(&self, event_window_time: u8) -> EcuResponseResult {
+        self.block_on(self.client().clear_response_on_event(event_window_time))
+    }
+
+    pub fn clear_response_on_event_suppressed(&self, event_window_time: u8) -> EcuResponseResult {
+        self.block_on(
+            self.client()
+                .clear_response_on_event_suppressed(event_window_time),
+        )
+    }
+
+    pub fn report_activated_events(&self) -> EcuResponseResult {
+        self.block_on(self.client().report_activated_events())
+    }
+
+    pub fn ecu_reset(&self, reset_type: ResetType) -> EcuResponseResult {
+        self.block_on(self.client().ecu_reset(reset_type))
+    }
+
+    pub fn ecu_reset_suppressed(&self, reset_type: ResetType) -> EcuResponseResult {
+        self.block_on(self.client().ecu_reset_suppressed(reset_type))
+    }
+
+    pub fn read_data_by_identifier(&self, data_identifiers: &[u16]) -> EcuResponseResult {
+        self.block_on(self.client().read_data_by_identifier(data_identifiers))
+    }
+
+    pub fn report_number_of_dtc_by_status_mask(&self, dtc_status_mask: u8) -> EcuResponseResult {
+        self.block_on(
+            self.client()
+                .report_number_of_dtc_by_status_mask(dtc_status_mask),
+        )
+    }
+
+    pub fn report_number_of_dtc_by_status_mask_suppressed(
+        &self,
+        dtc_status_mask: u8,
+    ) -> EcuResponseResult {
+        self.block_on(
+            self.client()
+                .report_number_of_dtc_by_status_mask_suppressed(dtc_status_mask),
+        )
+    }
+
+    pub fn report_dtc_by_status_mask(&self, dtc_status_mask: u8) -> EcuResponseResult {
+        self.block_on(self.client().report_dtc_by_status_mask(dtc_status_mask))
+    }
+
+    pub fn report_dtc_by_status_mask_suppressed(&self, dtc_status_mask: u8) -> EcuResponseResult {
+        self.block_on(
+            self.client()
+                .report_dtc_by_status_mask_suppressed(dtc_status_mask),
+        )
+    }
+
+    pub fn report_dtc_ext_data_record_by_dtc_number(
+        &self,
+        dtc_mask_record: u32,
+        dtc_ext_data_record_number: u8,
+    ) -> EcuResponseResult {
+        self.block_on(
+            self.client().report_dtc_ext_data_record_by_dtc_number(
+                dtc_mask_record,
+                dtc_ext_data_record_number,
+            ),
+        )
+    }
+
+    pub fn report_dtc_ext_data_record_by_dtc_number_suppressed(
+        &self,
+        dtc_mask_record: u32,
+        dtc_ext_data_record_number: u8,
+    ) -> EcuResponseResult {
+        self.block_on(
+            self.client()
+                .report_dtc_ext_data_record_by_dtc_number_suppressed(
+                    dtc_mask_record,
+                    dtc_ext_data_record_number,
+                ),
+        )
+    }
+
+    pub fn report_most_recent_confirmed_dtc(&self) -> EcuResponseResult {
+        self.block_on(self.client().report_most_recent_confirmed_dtc())
+    }
+
+    pub fn report_most_recent_confirmed_dtc_suppressed(&self) -> EcuResponseResult {
+        self.block_on(self.client().report_most_recent_confirmed_dtc_suppressed())
+    }
+
+    pub fn read_data_by_periodic_identifier(
+        &self,
+        transmission_mode: TransmissionMode,
+        periodic_identifiers: &[u8],
+    ) -> EcuResponseResult {
+        self.block_on(
+            self.client()
+                .read_data_by_periodic_identifier(transmission_mode, periodic_identifiers),
+        )
+    }
+
+    pub fn read_memory_by_address(
+        &self,
+        address_and_memory_length_format_identifier: u8,
+        memory_address: &[u8],
+        memory_size: &[u8],
+    ) -> EcuResponseResult {
+        self.block_on(self.client().read_memory_by_address(
+            address_and_memory_length_format_identifier,
+            memory_address,
+            memory_size,
+        ))
+    }
+
+    pub fn read_memory_by_address_simplified(
+        &self,
+        memory_address: u64,
+        memory_size: u64,
+        memory_address_len: Option<u8>,
+        memory_size_len: Option<u8>,
+    ) -> EcuResponseResult {
+        self.block_on(self.client().read_memory_by_address_simplified(
+            memory_address,
+            memory_size,
+            memory_address_len,
+            memory_size_len,
+        ))
+    }
+
+    pub fn write_memory_by_address(
+        &self,
+        address_and_memory_length_format_identifier: u8,
+        memory_address: &[u8],
+        memory_size: &[u8],
+        data_record: &[u8],
+    ) -> EcuResponseResult {
+        self.block_on(self.client().write_memory_by_address(
+            address_and_memory_length_format_identifier,
+            memory_address,
+            memory_size,
+            data_record,
+        ))
+    }
+
+    pub fn write_memory_by_address_simplified(
+        &self,
+        memory_address: u64,
+        data_record: &[u8],
+        memory_address_len: Option<u8>,
+        memory_size_len: Option<u8>,
+    ) -> EcuResponseResult {
+        self.block_on(self.client().write_memory_by_address_simplified(
+            memory_address,
+            data_record,
+            memory_address_len,
+            memory_size_len,
+        ))
+    }
+
+    pub fn write_memory_by_address_chunked(
+        &self,
+        memory_address: u64,
+        data: &[u8],
+        chunk_size: usize,
+    ) -> Result<(), UdsError> {
+        self.block_on(self.client().write_memory_by_address_chunked(
+            memory_address,
+            data,
+            chunk_size,
+        ))
+    }
+
+    pub fn request_download(
+        &self,
+        data_format_identifier: u8,
+        address_and_length_format_identifier: u8,
+        memory_address: &[u8],
+        memory_size: &[u8],
+    ) -> EcuResponseResult {
+        self.block_on(self.client().request_download(
+            data_format_identifier,
+            address_and_length_format_identifier,
+            memory_address,
+            memory_size,
+        ))
+    }
+
+    pub fn request_download_simplified(
+        &self,
+        data_format_identifier: u8,
+        memory_address: u64,
+        memory_size: u64,
+        memory_address_len: Option<u8>,
+        memory_size_len: Option<u8>,
+    ) -> EcuResponseResult {
+        self.block_on(self.client().request_download_simplified(
+            data_format_identifier,
+            memory_address,
+            memory_size,
+            memory_address_len,
+            memory_size_len,
+        ))
+    }
+
+    pub fn download(
+        &self,
+        memory_address: u64,
+        data: &[u8],
+        progress: impl FnMut(usize, usize),
+    ) -> Result<(), UdsError> {
+        self.block_on(self.client().download(memory_address, data, progress))
+    }
+
+    pub fn download_with_data_format(
+        &self,
+        data_format_identifier: u8,
+        memory_address: u64,
+        data: &[u8],
+        progress: impl FnMut(usize, usize),
+    ) -> Result<(), UdsError> {
+        self.block_on(self.client().download_with_data_format(
+            data_format_identifier,
+            memory_address,
+            data,
+            progress,
+        ))
+    }
+
+    pub fn request_upload(
+        &self,
+        data_format_identifier: u8,
+        address_and_length_format_identifier: u8,
+        memory_address: &[u8],
+        memory_size: &[u8],
+    ) -> EcuResponseResult {
+        self.block_on(self.client().request_upload(
+            data_format_identifier,
+            address_and_length_format_identifier,
+            memory_address,
+            memory_size,
+        ))
+    }
+
+    pub fn request_upload_simplified(
+        &self,
+        data_format_identifier: u8,
+        memory_address: u64,
+        memory_size: u64,
+        memory_address_len: Option<u8>,
+        memory_size_len: Option<u8>,
+    ) -> EcuResponseResult {
+        self.block_on(self.client().request_upload_simplified(
+            data_format_identifier,
+            memory_address,
+            memory_size,
+            memory_address_len,
+            memory_size_len,
+        ))
+    }
+
+    pub fn upload(&self, memory_address: u64, length: usize) -> Result<Vec<u8>, UdsError> {
+        self.block_on(self.client().upload(memory_address, length))
+    }
+
+    /// Blocking counterpart of [UdsClient::upload_into], the blocks are written to `sink` on the
+    /// calling thread as they arrive
+    pub fn upload_into(
+        &self,
+        memory_address: u64,
+        length: usize,
+        sink: &mut impl Write,
+    ) -> Result<(), UdsError> {
+        self.block_on(
+            self.client()
+                .upload_into(memory_address, length, &mut BlockingSink(sink)),
+        )
+    }
+
+    pub fn transfer_data(
+        &self,
+        block_sequence_counter: u8,
+        transfer_request_parameter_record: &[u8],
+    ) -> EcuResponseResult {
+        self.block_on(
+            self.client()
+                .transfer_data(block_sequence_counter, transfer_request_parameter_record),
+        )
+    }
+
+    pub fn request_transfer_exit(
+        &self,
+        transfer_request_parameter_record: &[u8],
+    ) -> EcuResponseResult {
+        self.block_on(
+            self.client()
+                .request_transfer_exit(transfer_request_parameter_record),
+        )
+    }
+
+    pub fn input_output_control_by_identifier(
+        &self,
+        data_identifier: u16,
+        control_parameter: InputOutputControlParameter,
+        control_state: &[u8],
+        control_enable_mask: &[u8],
+    ) -> EcuResponseResult {
+        self.block_on(self.client().input_output_control_by_identifier(
+            data_identifier,
+            control_parameter,
+            control_state,
+            control_enable_mask,
+        ))
+    }
+
+    /// Blocking counterpart of [UdsClient::take_input_output_control]. The guard borrows the
+    /// client, so the control is always returned before the runtime of the client shuts down.
+    pub fn take_input_output_control(
+        &self,
+        data_identifier: u16,
+        control_parameter: InputOutputControlParameter,
+        control_state: &[u8],
+        control_enable_mask: &[u8],
+    ) -> Result<BlockingInputOutputControlGuard<'_>, UdsError> {
+        let guard = self.block_on(self.client().take_input_output_control(
+            data_identifier,
+            control_parameter,
+            control_state,
+            control_enable_mask,
+        ))?;
+        Ok(BlockingInputOutputControlGuard {
+            client: self,
+            guard: Some(guard),
+        })
+    }
+
+    pub fn routine_control(
+        &self,
+        routine_control_type: RoutineControlType,
+        routine_identifier: u16,
+        routine_control_option_record: &[u8],
+    ) -> EcuResponseResult {
+        self.block_on(self.client().routine_control(
+            routine_control_type,
+            routine_identifier,
+            routine_control_option_record,
+        ))
+    }
+
+    pub fn routine_control_suppressed(
+        &self,
+        routine_control_type: RoutineControlType,
+        routine_identifier: u16,
+        routine_control_option_record: &[u8],
+    ) -> EcuResponseResult {
+        self.block_on(self.client().routine_control_suppressed(
+            routine_control_type,
+            routine_identifier,
+            routine_control_option_record,
+        ))
+    }
+
+    pub fn start_routine(
+        &self,
+        routine_identifier: u16,
+        routine_control_option_record: &[u8],
+    ) -> EcuResponseResult {
+        self.block_on(
+            self.client()
+                .start_routine(routine_identifier, routine_control_option_record),
+        )
+    }
+
+    pub fn start_routine_suppressed(
+        &self,
+        routine_identifier: u16,
+        routine_control_option_record: &[u8],
+    ) -> EcuResponseResult {
+        self.block_on(
+            self.client()
+                .start_routine_suppressed(routine_identifier, routine_control_option_record),
+        )
+    }
+
+    pub fn stop_routine(
+        &self,
+        routine_identifier: u16,
+        routine_control_option_record: &[u8],
+    ) -> EcuResponseResult {
+        self.block_on(
+            self.client()
+                .stop_routine(routine_identifier, routine_control_option_record),
+        )
+    }
+
+    pub fn stop_routine_suppressed(
+        &self,
+        routine_identifier: u16,
+        routine_control_option_record: &[u8],
+    ) -> EcuResponseResult {
+        self.block_on(
+            self.client()
+                .stop_routine_suppressed(routine_identifier, routine_control_option_record),
+        )
+    }
+
+    pub fn request_routine_results(&self, routine_identifier: u16) -> EcuResponseResult {
+        self.block_on(self.client().request_routine_results(routine_identifier))
+    }
+
+    pub fn security_access_request_seed(
+        &self,
+        level: u8,
+        security_access_data_record: &[u8],
+    ) -> EcuResponseResult {
+        self.block_on(
+            self.client()
+                .security_access_request_seed(level, security_access_data_record),
+        )
+    }
+
+    pub fn security_access_send_key(&self, level: u8, key: &[u8]) -> EcuResponseResult {
+        self.block_on(self.client().security_access_send_key(level, key))
+    }
+
+    pub fn unlock(&self, level: u8, algorithm: &impl SeedKeyAlgorithm) -> Result<(), UdsError> {
+        self.block_on(self.client().unlock(level, algorithm))
+    }
+
+    pub fn tester_present(&self) -> EcuResponseResult {
+        self.block_on(self.client().tester_present())
+    }
+
+    pub fn tester_present_suppressed(&self) -> EcuResponseResult {
+        self.block_on(self.client().tester_present_suppressed())
+    }
+
+    pub fn write_data_by_identifier(
+        &self,
+        data_identifier: u16,
+        data_record: &[u8],
+    ) -> EcuResponseResult {
+        self.block_on(
+            self.client()
+                .write_data_by_identifier(data_identifier, data_record),
+        )
+    }
+}
+
+/// Returned by [BlockingUdsClient::take_input_output_control]. Control of the identifier is
+/// returned to the ECU by [BlockingInputOutputControlGuard::return_control], or when the guard is
+/// dropped, in which case the drop blocks until the server responds.
+#[must_use = "control is returned to the ECU when the guard is dropped"]
+pub struct BlockingInputOutputControlGuard<'a> {
+    client: &'a BlockingUdsClient,
+    /// None once the control was returned
+    guard: Option<InputOutputControlGuard>,
+}
+
+impl BlockingInputOutputControlGuard<'_> {
+    pub fn data_identifier(&self) -> u16 {
+        self.guard.as_ref().unwrap().data_identifier()
+    }
+
+    /// Blocking counterpart of [InputOutputControlGuard::return_control]
+    pub fn return_control(mut self) -> EcuResponseResult {
+        let guard = self.guard.take().unwrap();
+        self.client.block_on(guard.return_control())
+    }
+}
+
+impl Drop for BlockingInputOutputControlGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(guard) = self.guard.take() {
+            let data_identifier = guard.data_identifier();
+            if let Err(e) = self.client.block_on(guard.return_control()) {
+                error!(
+                    "Returning control of {:#x} to ECU failed: {:?}",
+                    data_identifier, e
+                );
+            }
+        }
+    }
+}
+
+/// Adapts [Write] to [AsyncWrite]. Futures are polled by [Handle::block_on] on the calling
+/// thread, so blocking writes do not stall the runtime thread.
+struct BlockingSink<'a, W: Write>(&'a mut W);
+
+impl<W: Write> AsyncWrite for BlockingSink<'_, W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Poll::Ready(self.get_mut().0.write(buf))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(self.get_mut().0.flush())
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        self.poll_flush(cx)
+    }
+}
+
+impl Drop for BlockingUdsClient {
+    fn drop(&mut self) {
+        // background tasks of the client are aborted, which needs the runtime context
+        let guard = self.handle.enter();
+        self.client.take();
+        drop(guard);
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+        if let Some(runtime_thread) = self.runtime_thread.take() {
+            let _ = runtime_thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uds::{DataFormat, MockTransport, UdsResponse};
+
+    #[test]
+    fn test_blocking_request() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x22, 0xf1, 0x90])
+            .respond(&[0x62, 0xf1, 0x90, 0x41]);
+        mock.expect(&[0x11, 0x01]).respond(&[0x7f, 0x11, 0x22]);
+        let client = BlockingUdsClient::new_from_transport(mock.clone()).unwrap();
+
+        assert!(matches!(
+            client.read_data_by_identifier(&[0xf190]),
+            Ok(UdsResponse::ReadDataByIdentifier(DataFormat::Parsed(_)))
+        ));
+        assert!(matches!(
+            client.ecu_reset(ResetType::HardReset),
+            Err(UdsError::NRC { .. })
+        ));
+        mock.assert_done();
+    }
+
+    #[test]
+    fn test_keep_alive_runs_between_calls() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x10, 0x03])
+            .respond(&[0x50, 0x03, 0x00, 0x32, 0x01, 0xf4]);
+        mock.expect(&[0x3e, 0x80]);
+        let client = BlockingUdsClient::new_from_transport(mock.clone()).unwrap();
+        client
+            .set_tester_present_interval(Some(Duration::from_millis(100)))
+            .unwrap();
+
+        client.diagnostic_session_control(0x03).unwrap();
+        std::thread::sleep(Duration::from_millis(150));
+        drop(client);
+        mock.assert_done();
+    }
+
+    #[test]
+    fn test_upload_into_writer() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x35, 0x00, 0x11, 0x10, 0x03])
+            .respond(&[0x75, 0x10, 0x04]);
+        mock.expect(&[0x36, 0x01]).respond(&[0x76, 0x01, 0, 1]);
+        mock.expect(&[0x36, 0x02]).respond(&[0x76, 0x02, 2]);
+        mock.expect(&[0x37]).respond(&[0x77]);
+        let client = BlockingUdsClient::new_from_transport(mock.clone()).unwrap();
+
+        let mut sink = std::io::Cursor::new(vec![]);
+        client.upload_into(0x10, 3, &mut sink).unwrap();
+        assert_eq!(sink.into_inner(), vec![0, 1, 2]);
+        mock.assert_done();
+    }
+
+    #[test]
+    fn test_input_output_control_returned() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x2f, 0xf1, 0x01, 0x03, 0x01])
+            .respond(&[0x6f, 0xf1, 0x01, 0x03, 0x01]);
+        mock.expect(&[0x2f, 0xf1, 0x01, 0x00])
+            .respond(&[0x6f, 0xf1, 0x01, 0x00, 0x00]);
+        mock.expect(&[0x2f, 0xf1, 0x02, 0x02])
+            .respond(&[0x6f, 0xf1, 0x02, 0x02, 0x05]);
+        mock.expect(&[0x2f, 0xf1, 0x02, 0x00])
+            .respond(&[0x6f, 0xf1, 0x02, 0x00, 0x05]);
+        let client = BlockingUdsClient::new_from_transport(mock.clone()).unwrap();
+
+        let guard = client
+            .take_input_output_control(
+                0xf101,
+                InputOutputControlParameter::ShortTermAdjustment,
+                &[0x01],
+                &[],
+            )
+            .unwrap();
+        assert_eq!(guard.data_identifier(), 0xf101);
+        assert!(matches!(
+            guard.return_control(),
+            Ok(UdsResponse::InputOutputControlByIdentifier(
+                DataFormat::Parsed(_)
+            ))
+        ));
+
+        // dropped guard returns the control before the client is dropped
+        let guard = client
+            .take_input_output_control(
+                0xf102,
+                InputOutputControlParameter::FreezeCurrentState,
+                &[],
+                &[],
+            )
+            .unwrap();
+        drop(guard);
+        mock.assert_done();
+        drop(client);
+    }
+}
@@ -4,8 +4,14 @@
 //!
 //! [UdsClient::clear_diagnostic_information]
 //!
+//! and following methods for FunctionalUdsClient:
+//!
+//! [FunctionalUdsClient::clear_diagnostic_information]
+//!
 use crate::uds::uds_definitions::SEND_RECEIVE_SID_OFFSET;
-use crate::uds::{EcuResponseResult, UdsClient, UdsError, UdsResponse};
+use crate::uds::{
+    EcuResponseResult, FunctionalResponses, FunctionalUdsClient, UdsClient, UdsError, UdsResponse,
+};
 use log::error;
 
 const CLEAR_DIAGNOSTIC_INFORMATION_SID: u8 = 0x14;
@@ -14,8 +20,18 @@ impl UdsClient {
     pub async fn clear_diagnostic_information(&self, group_of_dtc: u32) -> EcuResponseResult {
         let request = compose_clear_diagnostic_information_request(group_of_dtc);
         let raw_response = self.send_and_receive(&request).await?;
-        let response = parse_clear_diagnostic_information_response(&raw_response);
-        response
+        parse_clear_diagnostic_information_response(&raw_response)
+    }
+}
+
+impl FunctionalUdsClient {
+    pub async fn clear_diagnostic_information(
+        &self,
+        group_of_dtc: u32,
+    ) -> Result<FunctionalResponses, UdsError> {
+        let request = compose_clear_diagnostic_information_request(group_of_dtc);
+        self.send_and_collect(&request, parse_clear_diagnostic_information_response)
+            .await
     }
 }
 
@@ -28,7 +44,9 @@ fn compose_clear_diagnostic_information_request(group_of_dtc: u32) -> Vec<u8> {
     ]
 }
 
-fn parse_clear_diagnostic_information_response(raw_response: &[u8]) -> EcuResponseResult {
+pub(super) fn parse_clear_diagnostic_information_response(
+    raw_response: &[u8],
+) -> EcuResponseResult {
     let mut response_iter = raw_response.iter();
     let sid = *response_iter.next().ok_or(UdsError::ResponseEmpty)?;
     if sid != CLEAR_DIAGNOSTIC_INFORMATION_SID + SEND_RECEIVE_SID_OFFSET {
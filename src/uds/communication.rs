@@ -4,9 +4,16 @@
 //! Currently built using tokio_socketcan_isotp library, the process should be similar for
 //! different network protocols and even runtimes, but it is currently tested only on tokio_socketcan_isotp and you knowledge may vary.
 //!
-//! To provide your own backend communication just rewrite the read, write and socket creation process to use your own API, and you should be good to go.
+//! When the kernel ISO-TP module is not available, [UdsSocket] can use userspace implementation
+//! over raw CAN socket instead, see [UdsSocket::new_userspace].
+//!
+//! To provide your own backend communication implement the [UdsTransport] trait for your type and
+//! pass it to [UdsClient::new_from_transport](crate::UdsClient::new_from_transport). [UdsSocket]
+//! is the default implementation of the trait.
 //!
 
+use crate::uds::isotp::UserspaceIsoTp;
+use futures::future::BoxFuture;
 use std::time::Duration;
 pub use tokio_socketcan_isotp::{
     Error, ExtendedId, FlowControlOptions, Id, IsoTpBehaviour, IsoTpOptions, LinkLayerOptions,
@@ -14,7 +21,7 @@ pub use tokio_socketcan_isotp::{
 };
 
 #[allow(dead_code)]
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum UdsCommunicationError {
     FailedToFindCanDevice,
     SocketCanIOError,
@@ -22,6 +29,24 @@ pub enum UdsCommunicationError {
     GeneralError,
     NotImplementedError,
     SocketCreationError,
+    /// Lower layer did not respond in time
+    Timeout,
+    /// Connection to the server was closed
+    ConnectionClosed,
+    /// Received frame does not follow the transport protocol
+    InvalidFrame,
+    /// DoIP entity refused routing activation
+    RoutingActivationDenied {
+        response_code: u8,
+    },
+    /// DoIP entity refused diagnostic message
+    DiagnosticMessageNack {
+        nack_code: u8,
+    },
+    /// Receiver of segmented message reported buffer overflow
+    BufferOverflow,
+    /// Transport configuration is not valid
+    InvalidArgument,
 }
 
 impl From<Error> for UdsCommunicationError {
@@ -39,19 +64,40 @@ impl From<std::io::Error> for UdsCommunicationError {
     }
 }
 
+/// Transport layer used by [UdsClient](crate::UdsClient) to exchange whole UDS PDUs with the server.
+///
+/// Segmentation, flow control and addressing are responsibility of the implementor -
+/// [UdsTransport::send] takes complete request and [UdsTransport::receive] returns complete
+/// response, both starting with SID byte.
+///
+/// Methods return boxed futures, so the trait can be used as a trait object.
+pub trait UdsTransport: Send + Sync {
+    /// Send single UDS PDU to the server
+    fn send<'a>(&'a self, payload: &'a [u8]) -> BoxFuture<'a, Result<(), UdsCommunicationError>>;
+    /// Wait for single UDS PDU from the server
+    fn receive(&self) -> BoxFuture<'_, Result<Vec<u8>, UdsCommunicationError>>;
+}
+
+/// ISO-TP implementation used by [UdsSocket]
+enum IsoTpBackend {
+    Kernel(Box<tokio_socketcan_isotp::IsoTpSocket>),
+    Userspace(UserspaceIsoTp),
+}
+
 pub struct UdsSocket {
-    isotp_socket: tokio_socketcan_isotp::IsoTpSocket,
+    isotp_socket: IsoTpBackend,
 }
 
 impl UdsSocket {
-
     pub fn new(
         ifname: &str,
         src: impl Into<Id>,
         dst: impl Into<Id>,
     ) -> Result<UdsSocket, UdsCommunicationError> {
         Ok(UdsSocket {
-            isotp_socket: tokio_socketcan_isotp::IsoTpSocket::open(ifname, src, dst)?,
+            isotp_socket: IsoTpBackend::Kernel(Box::new(tokio_socketcan_isotp::IsoTpSocket::open(
+                ifname, src, dst,
+            )?)),
         })
     }
 
@@ -77,8 +123,8 @@ impl UdsSocket {
             }
             Err(_) => println!("Cannot set options!"),
         }
-        let uds_socket = UdsSocket::new_with_opts(&ifname, src, dst, options, None, None)?;
-        return Ok(uds_socket);
+        let uds_socket = UdsSocket::new_with_opts(ifname, src, dst, options, None, None)?;
+        Ok(uds_socket)
     }
 
     pub fn new_with_opts(
@@ -90,21 +136,47 @@ impl UdsSocket {
         link_layer_options: Option<LinkLayerOptions>,
     ) -> Result<UdsSocket, UdsCommunicationError> {
         Ok(UdsSocket {
-            isotp_socket: tokio_socketcan_isotp::IsoTpSocket::open_with_opts(
-                ifname,
-                src,
-                dst,
-                isotp_options,
-                rx_flow_control_options,
-                link_layer_options,
-            )?,
+            isotp_socket: IsoTpBackend::Kernel(Box::new(
+                tokio_socketcan_isotp::IsoTpSocket::open_with_opts(
+                    ifname,
+                    src,
+                    dst,
+                    isotp_options,
+                    rx_flow_control_options,
+                    link_layer_options,
+                )?,
+            )),
         })
     }
 
+    /// Use userspace ISO-TP implementation instead of the kernel module,
+    /// see also [UdsSocket::new_userspace]
+    pub fn new_from_userspace_isotp(isotp: UserspaceIsoTp) -> UdsSocket {
+        UdsSocket {
+            isotp_socket: IsoTpBackend::Userspace(isotp),
+        }
+    }
+
     pub async fn send(&self, payload: &[u8]) -> Result<(), UdsCommunicationError> {
-        Ok(self.isotp_socket.write_packet(payload)?.await?)
+        match &self.isotp_socket {
+            IsoTpBackend::Kernel(socket) => Ok(socket.write_packet(payload)?.await?),
+            IsoTpBackend::Userspace(isotp) => isotp.send(payload).await,
+        }
     }
     pub async fn receive(&self) -> Result<Vec<u8>, UdsCommunicationError> {
-        Ok(self.isotp_socket.read_packet()?.await?)
+        match &self.isotp_socket {
+            IsoTpBackend::Kernel(socket) => Ok(socket.read_packet()?.await?),
+            IsoTpBackend::Userspace(isotp) => isotp.receive().await,
+        }
+    }
+}
+
+impl UdsTransport for UdsSocket {
+    fn send<'a>(&'a self, payload: &'a [u8]) -> BoxFuture<'a, Result<(), UdsCommunicationError>> {
+        Box::pin(UdsSocket::send(self, payload))
+    }
+
+    fn receive(&self) -> BoxFuture<'_, Result<Vec<u8>, UdsCommunicationError>> {
+        Box::pin(UdsSocket::receive(self))
     }
 }
@@ -0,0 +1,345 @@
+//! # Implementation of CommunicationControl 0x28 service
+//!
+//! This module provides following methods for UdsClient:
+//!
+//! [UdsClient::communication_control]
+//! [UdsClient::communication_control_suppressed]
+//!
+//! and following methods for FunctionalUdsClient:
+//!
+//! [FunctionalUdsClient::communication_control]
+//! [FunctionalUdsClient::communication_control_suppressed]
+//!
+//! Usually sent functionally with suppressed positive response before programming, together with
+//! [UdsClient::control_dtc_setting], and restored by [CommunicationControlType::EnableRxAndTx]
+//! afterwards.
+//!
+use super::*;
+use crate::uds::uds_definitions::{
+    compose_sub_function, parse_sub_function, SEND_RECEIVE_SID_OFFSET,
+};
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+
+const COMMUNICATION_CONTROL_SID: u8 = 0x28;
+
+#[derive(IntoPrimitive, TryFromPrimitive, Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum CommunicationControlType {
+    EnableRxAndTx = 0,
+    EnableRxAndDisableTx = 1,
+    DisableRxAndEnableTx = 2,
+    DisableRxAndTx = 3,
+    /// Requires node identification number
+    EnableRxAndDisableTxWithEnhancedAddressInformation = 4,
+    /// Requires node identification number
+    EnableRxAndTxWithEnhancedAddressInformation = 5,
+}
+
+impl CommunicationControlType {
+    fn requires_node_identification_number(self) -> bool {
+        matches!(
+            self,
+            CommunicationControlType::EnableRxAndDisableTxWithEnhancedAddressInformation
+                | CommunicationControlType::EnableRxAndTxWithEnhancedAddressInformation
+        )
+    }
+}
+
+/// Low nibble of the communicationType
+#[derive(IntoPrimitive, TryFromPrimitive, Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum CommunicationMessages {
+    Normal = 1,
+    NetworkManagement = 2,
+    NormalAndNetworkManagement = 3,
+}
+
+/// communicationType parameter - which messages on which subnet are controlled
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CommunicationType {
+    pub messages: CommunicationMessages,
+    /// 0x0 all subnets, 0x1-0xE specific subnet, 0xF subnet on which the request is received
+    pub subnet_number: u8,
+}
+
+impl CommunicationType {
+    pub const SUBNET_ALL: u8 = 0x0;
+    pub const SUBNET_RECEIVING: u8 = 0xF;
+
+    /// Control the messages on all subnets
+    pub fn new(messages: CommunicationMessages) -> CommunicationType {
+        CommunicationType {
+            messages,
+            subnet_number: CommunicationType::SUBNET_ALL,
+        }
+    }
+
+    pub fn with_subnet(mut self, subnet_number: u8) -> CommunicationType {
+        self.subnet_number = subnet_number;
+        self
+    }
+}
+
+impl From<CommunicationType> for u8 {
+    fn from(communication_type: CommunicationType) -> u8 {
+        (communication_type.subnet_number << 4) | u8::from(communication_type.messages)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct CommunicationControlResponse {
+    pub control_type: CommunicationControlType,
+}
+
+impl UdsClient {
+    /// `node_identification_number` has to be provided for control types with enhanced address
+    /// information and only for them
+    pub async fn communication_control(
+        &self,
+        control_type: CommunicationControlType,
+        communication_type: CommunicationType,
+        node_identification_number: Option<u16>,
+    ) -> EcuResponseResult {
+        self.communication_control_suppressible(
+            control_type,
+            communication_type,
+            node_identification_number,
+            false,
+        )
+        .await
+    }
+
+    /// Same as [UdsClient::communication_control] with suppressed positive response. Returns
+    /// [UdsResponse::PositiveResponseSuppressed] unless the server responds with NRC.
+    pub async fn communication_control_suppressed(
+        &self,
+        control_type: CommunicationControlType,
+        communication_type: CommunicationType,
+        node_identification_number: Option<u16>,
+    ) -> EcuResponseResult {
+        self.communication_control_suppressible(
+            control_type,
+            communication_type,
+            node_identification_number,
+            true,
+        )
+        .await
+    }
+
+    async fn communication_control_suppressible(
+        &self,
+        control_type: CommunicationControlType,
+        communication_type: CommunicationType,
+        node_identification_number: Option<u16>,
+        suppress_positive_response: bool,
+    ) -> EcuResponseResult {
+        let request = compose_communication_control_request(
+            control_type,
+            communication_type,
+            node_identification_number,
+            suppress_positive_response,
+        )?;
+        let Some(raw_response) = self
+            .send_and_receive_suppressible(&request, suppress_positive_response)
+            .await?
+        else {
+            return Ok(UdsResponse::PositiveResponseSuppressed);
+        };
+        parse_communication_control_response(&raw_response)
+    }
+}
+
+impl FunctionalUdsClient {
+    pub async fn communication_control(
+        &self,
+        control_type: CommunicationControlType,
+        communication_type: CommunicationType,
+        node_identification_number: Option<u16>,
+    ) -> Result<FunctionalResponses, UdsError> {
+        self.communication_control_suppressible(
+            control_type,
+            communication_type,
+            node_identification_number,
+            false,
+        )
+        .await
+    }
+
+    /// Same as [FunctionalUdsClient::communication_control] with suppressed positive response,
+    /// only the ECUs responding with NRC are in the result
+    pub async fn communication_control_suppressed(
+        &self,
+        control_type: CommunicationControlType,
+        communication_type: CommunicationType,
+        node_identification_number: Option<u16>,
+    ) -> Result<FunctionalResponses, UdsError> {
+        self.communication_control_suppressible(
+            control_type,
+            communication_type,
+            node_identification_number,
+            true,
+        )
+        .await
+    }
+
+    async fn communication_control_suppressible(
+        &self,
+        control_type: CommunicationControlType,
+        communication_type: CommunicationType,
+        node_identification_number: Option<u16>,
+        suppress_positive_response: bool,
+    ) -> Result<FunctionalResponses, UdsError> {
+        let request = compose_communication_control_request(
+            control_type,
+            communication_type,
+            node_identification_number,
+            suppress_positive_response,
+        )?;
+        self.send_and_collect(&request, parse_communication_control_response)
+            .await
+    }
+}
+
+fn compose_communication_control_request(
+    control_type: CommunicationControlType,
+    communication_type: CommunicationType,
+    node_identification_number: Option<u16>,
+    suppress_positive_response: bool,
+) -> Result<Vec<u8>, UdsError> {
+    if communication_type.subnet_number > 0xF
+        || control_type.requires_node_identification_number()
+            != node_identification_number.is_some()
+    {
+        error!(
+            "Invalid communication control {:?} of {:?} with node {:?}",
+            control_type, communication_type, node_identification_number
+        );
+        return Err(UdsError::InvalidArgument);
+    }
+    let mut request = vec![
+        COMMUNICATION_CONTROL_SID,
+        compose_sub_function(control_type.into(), suppress_positive_response),
+        communication_type.into(),
+    ];
+    if let Some(node_identification_number) = node_identification_number {
+        request.extend_from_slice(&node_identification_number.to_be_bytes());
+    }
+    Ok(request)
+}
+
+pub(super) fn parse_communication_control_response(raw_response: &[u8]) -> EcuResponseResult {
+    let mut response_iter = raw_response.iter();
+    let sid = *response_iter.next().ok_or(UdsError::ResponseEmpty)?;
+    if sid != COMMUNICATION_CONTROL_SID + SEND_RECEIVE_SID_OFFSET {
+        return Err(UdsError::SidMismatch {
+            expected: COMMUNICATION_CONTROL_SID + SEND_RECEIVE_SID_OFFSET,
+            received: sid,
+            raw_message: raw_response.to_vec(),
+        });
+    }
+    let control_type_byte = *response_iter.next().ok_or(UdsError::InvalidLength {
+        raw_message: raw_response.to_vec(),
+    })?;
+    let control_type =
+        CommunicationControlType::try_from_primitive(parse_sub_function(control_type_byte))
+            .map_err(|_| UdsError::ResponseIncorrect {
+                raw_message: raw_response.to_vec(),
+            })?;
+    Ok(UdsResponse::CommunicationControl(DataFormat::Parsed(
+        CommunicationControlResponse { control_type },
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uds::functional::tests::MockBus;
+
+    #[test]
+    fn test_compose_request() {
+        assert_eq!(
+            compose_communication_control_request(
+                CommunicationControlType::DisableRxAndTx,
+                CommunicationType::new(CommunicationMessages::NormalAndNetworkManagement),
+                None,
+                true
+            ),
+            Ok(vec![0x28, 0x83, 0x03])
+        );
+        assert_eq!(
+            compose_communication_control_request(
+                CommunicationControlType::EnableRxAndDisableTxWithEnhancedAddressInformation,
+                CommunicationType::new(CommunicationMessages::Normal).with_subnet(0x2),
+                Some(0x000a),
+                false
+            ),
+            Ok(vec![0x28, 0x04, 0x21, 0x00, 0x0a])
+        );
+    }
+
+    #[test]
+    fn test_node_identification_number_required() {
+        assert_eq!(
+            compose_communication_control_request(
+                CommunicationControlType::EnableRxAndTxWithEnhancedAddressInformation,
+                CommunicationType::new(CommunicationMessages::Normal),
+                None,
+                false
+            ),
+            Err(UdsError::InvalidArgument)
+        );
+        assert_eq!(
+            compose_communication_control_request(
+                CommunicationControlType::EnableRxAndTx,
+                CommunicationType::new(CommunicationMessages::Normal),
+                Some(0x000a),
+                false
+            ),
+            Err(UdsError::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn test_parse_response() {
+        assert_eq!(
+            parse_communication_control_response(&[0x68, 0x03]),
+            Ok(UdsResponse::CommunicationControl(DataFormat::Parsed(
+                CommunicationControlResponse {
+                    control_type: CommunicationControlType::DisableRxAndTx
+                }
+            )))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_functional_with_suppressed_positive_response() {
+        let ecu_1 = MockTransport::new();
+        let ecu_2 = MockTransport::new();
+        ecu_1.expect(&[0x28, 0x83, 0x01]);
+        ecu_2
+            .expect(&[0x28, 0x83, 0x01])
+            .respond_after(Duration::from_millis(10), &[0x7f, 0x28, 0x22]);
+        let bus = MockBus(vec![(0x7e8, ecu_1), (0x7e9, ecu_2)]);
+        let client = bus.client();
+
+        let result = client
+            .communication_control_suppressed(
+                CommunicationControlType::DisableRxAndTx,
+                CommunicationType::new(CommunicationMessages::Normal),
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(
+            result[&0x7e9],
+            Err(UdsError::NRC {
+                nrc: NrcData {
+                    rejected_sid: 0x28,
+                    nrc: NegativeResponseCode::ConditionsNotCorrect
+                }
+            })
+        );
+        bus.assert_done();
+    }
+}
@@ -0,0 +1,202 @@
+//! # Implementation of ControlDTCSetting 0x85 service
+//!
+//! This module provides following methods for UdsClient:
+//!
+//! [UdsClient::control_dtc_setting]
+//! [UdsClient::control_dtc_setting_suppressed]
+//!
+//! and following methods for FunctionalUdsClient:
+//!
+//! [FunctionalUdsClient::control_dtc_setting]
+//! [FunctionalUdsClient::control_dtc_setting_suppressed]
+//!
+use super::*;
+use crate::uds::uds_definitions::{
+    compose_sub_function, parse_sub_function, SEND_RECEIVE_SID_OFFSET,
+};
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+
+const CONTROL_DTC_SETTING_SID: u8 = 0x85;
+
+#[derive(IntoPrimitive, TryFromPrimitive, Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum DtcSettingType {
+    On = 1,
+    Off = 2,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ControlDtcSettingResponse {
+    pub dtc_setting_type: DtcSettingType,
+}
+
+impl UdsClient {
+    /// `dtc_setting_control_option_record` is usually empty, or groupOfDTC (0xFFFFFF for all
+    /// groups) when supported by the server
+    pub async fn control_dtc_setting(
+        &self,
+        dtc_setting_type: DtcSettingType,
+        dtc_setting_control_option_record: &[u8],
+    ) -> EcuResponseResult {
+        self.control_dtc_setting_suppressible(
+            dtc_setting_type,
+            dtc_setting_control_option_record,
+            false,
+        )
+        .await
+    }
+
+    /// Same as [UdsClient::control_dtc_setting] with suppressed positive response. Returns
+    /// [UdsResponse::PositiveResponseSuppressed] unless the server responds with NRC.
+    pub async fn control_dtc_setting_suppressed(
+        &self,
+        dtc_setting_type: DtcSettingType,
+        dtc_setting_control_option_record: &[u8],
+    ) -> EcuResponseResult {
+        self.control_dtc_setting_suppressible(
+            dtc_setting_type,
+            dtc_setting_control_option_record,
+            true,
+        )
+        .await
+    }
+
+    async fn control_dtc_setting_suppressible(
+        &self,
+        dtc_setting_type: DtcSettingType,
+        dtc_setting_control_option_record: &[u8],
+        suppress_positive_response: bool,
+    ) -> EcuResponseResult {
+        let request = compose_control_dtc_setting_request(
+            dtc_setting_type,
+            dtc_setting_control_option_record,
+            suppress_positive_response,
+        );
+        let Some(raw_response) = self
+            .send_and_receive_suppressible(&request, suppress_positive_response)
+            .await?
+        else {
+            return Ok(UdsResponse::PositiveResponseSuppressed);
+        };
+        parse_control_dtc_setting_response(&raw_response)
+    }
+}
+
+impl FunctionalUdsClient {
+    pub async fn control_dtc_setting(
+        &self,
+        dtc_setting_type: DtcSettingType,
+        dtc_setting_control_option_record: &[u8],
+    ) -> Result<FunctionalResponses, UdsError> {
+        self.control_dtc_setting_suppressible(
+            dtc_setting_type,
+            dtc_setting_control_option_record,
+            false,
+        )
+        .await
+    }
+
+    /// Same as [FunctionalUdsClient::control_dtc_setting] with suppressed positive response, only
+    /// the ECUs responding with NRC are in the result
+    pub async fn control_dtc_setting_suppressed(
+        &self,
+        dtc_setting_type: DtcSettingType,
+        dtc_setting_control_option_record: &[u8],
+    ) -> Result<FunctionalResponses, UdsError> {
+        self.control_dtc_setting_suppressible(
+            dtc_setting_type,
+            dtc_setting_control_option_record,
+            true,
+        )
+        .await
+    }
+
+    async fn control_dtc_setting_suppressible(
+        &self,
+        dtc_setting_type: DtcSettingType,
+        dtc_setting_control_option_record: &[u8],
+        suppress_positive_response: bool,
+    ) -> Result<FunctionalResponses, UdsError> {
+        let request = compose_control_dtc_setting_request(
+            dtc_setting_type,
+            dtc_setting_control_option_record,
+            suppress_positive_response,
+        );
+        self.send_and_collect(&request, parse_control_dtc_setting_response)
+            .await
+    }
+}
+
+fn compose_control_dtc_setting_request(
+    dtc_setting_type: DtcSettingType,
+    dtc_setting_control_option_record: &[u8],
+    suppress_positive_response: bool,
+) -> Vec<u8> {
+    let mut request = vec![
+        CONTROL_DTC_SETTING_SID,
+        compose_sub_function(dtc_setting_type.into(), suppress_positive_response),
+    ];
+    request.extend_from_slice(dtc_setting_control_option_record);
+    request
+}
+
+pub(super) fn parse_control_dtc_setting_response(raw_response: &[u8]) -> EcuResponseResult {
+    let mut response_iter = raw_response.iter();
+    let sid = *response_iter.next().ok_or(UdsError::ResponseEmpty)?;
+    if sid != CONTROL_DTC_SETTING_SID + SEND_RECEIVE_SID_OFFSET {
+        return Err(UdsError::SidMismatch {
+            expected: CONTROL_DTC_SETTING_SID + SEND_RECEIVE_SID_OFFSET,
+            received: sid,
+            raw_message: raw_response.to_vec(),
+        });
+    }
+    let dtc_setting_type_byte = *response_iter.next().ok_or(UdsError::InvalidLength {
+        raw_message: raw_response.to_vec(),
+    })?;
+    let dtc_setting_type = DtcSettingType::try_from_primitive(parse_sub_function(
+        dtc_setting_type_byte,
+    ))
+    .map_err(|_| UdsError::ResponseIncorrect {
+        raw_message: raw_response.to_vec(),
+    })?;
+    Ok(UdsResponse::ControlDtcSetting(DataFormat::Parsed(
+        ControlDtcSettingResponse { dtc_setting_type },
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compose_request() {
+        assert_eq!(
+            compose_control_dtc_setting_request(DtcSettingType::Off, &[], true),
+            vec![0x85, 0x82]
+        );
+        assert_eq!(
+            compose_control_dtc_setting_request(DtcSettingType::On, &[0xff, 0xff, 0xff], false),
+            vec![0x85, 0x01, 0xff, 0xff, 0xff]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_suppressed_positive_response() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x85, 0x82]);
+        let client = UdsClient::new_from_transport(mock.clone());
+        client.set_response_timing(ResponseTiming {
+            p2: Duration::from_millis(20),
+            p2_star: Duration::from_millis(100),
+            network_margin: Duration::ZERO,
+        });
+
+        assert_eq!(
+            client
+                .control_dtc_setting_suppressed(DtcSettingType::Off, &[])
+                .await,
+            Ok(UdsResponse::PositiveResponseSuppressed)
+        );
+        mock.assert_done();
+    }
+}
@@ -3,39 +3,142 @@
 //! This module provides following methods for UdsClient:
 //!
 //! [UdsClient::diagnostic_session_control]
+//! [UdsClient::diagnostic_session_control_suppressed]
 //!
-use crate::uds::uds_definitions::SEND_RECEIVE_SID_OFFSET;
-use crate::uds::{EcuResponseResult, UdsClient, UdsError, UdsResponse};
-use log::error;
+//! and following methods for FunctionalUdsClient:
+//!
+//! [FunctionalUdsClient::diagnostic_session_control]
+//! [FunctionalUdsClient::diagnostic_session_control_suppressed]
+//!
+//! Timing parameters received in positive response are adopted by the client, see
+//! [UdsClient::response_timing]. Entering non-default session starts automatic TesterPresent,
+//! see [UdsClient::set_tester_present_interval].
+//!
+use crate::uds::uds_definitions::{
+    compose_sub_function, parse_sub_function, SEND_RECEIVE_SID_OFFSET,
+};
+use crate::uds::{
+    EcuResponseResult, FunctionalResponses, FunctionalUdsClient, UdsClient, UdsError, UdsResponse,
+};
+use log::{error, info};
+use std::time::Duration;
 
 use super::DataFormat;
 
 const DIAGNOSTIC_SESSION_CONTROL_SID: u8 = 0x10;
+const DEFAULT_SESSION: u8 = 0x01;
 
 #[derive(Debug, PartialEq)]
 pub struct DiagnosticSessionControlResponse {
-    session: u8,
-    p2: u16,
-    p2_star: u16,
+    pub session: u8,
+    /// P2Server_max in 1 ms resolution
+    pub p2: u16,
+    /// P2*Server_max in 10 ms resolution
+    pub p2_star: u16,
+}
+
+impl DiagnosticSessionControlResponse {
+    pub fn p2_duration(&self) -> Duration {
+        Duration::from_millis(self.p2 as u64)
+    }
+
+    pub fn p2_star_duration(&self) -> Duration {
+        Duration::from_millis(self.p2_star as u64 * 10)
+    }
 }
 
 impl UdsClient {
+    /// Switch to the provided session. On positive response the client starts to use P2 and P2*
+    /// reported by the server.
     pub async fn diagnostic_session_control(&self, session_id: u8) -> EcuResponseResult {
-        let request = compose_diagnostic_session_control_request(session_id);
-        let raw_response = self.send_and_receive(&request).await?;
-        let response = parse_diagnostic_session_control_response(&raw_response);
-        response
+        self.diagnostic_session_control_suppressible(session_id, false)
+            .await
+    }
+
+    /// Same as [UdsClient::diagnostic_session_control] with suppressed positive response. The
+    /// timing of the new session is not known, so the current one is kept.
+    pub async fn diagnostic_session_control_suppressed(&self, session_id: u8) -> EcuResponseResult {
+        self.diagnostic_session_control_suppressible(session_id, true)
+            .await
+    }
+
+    async fn diagnostic_session_control_suppressible(
+        &self,
+        session_id: u8,
+        suppress_positive_response: bool,
+    ) -> EcuResponseResult {
+        let request =
+            compose_diagnostic_session_control_request(session_id, suppress_positive_response);
+        let response = match self
+            .send_and_receive_suppressible(&request, suppress_positive_response)
+            .await?
+        {
+            Some(raw_response) => parse_diagnostic_session_control_response(&raw_response)?,
+            None => UdsResponse::PositiveResponseSuppressed,
+        };
+        if let UdsResponse::DiagnosticSessionControl(DataFormat::Parsed(session)) = &response {
+            let mut timing = self.response_timing();
+            timing.p2 = session.p2_duration();
+            timing.p2_star = session.p2_star_duration();
+            info!(
+                "Session {:#x} entered, adopting timing {:?}",
+                session_id, timing
+            );
+            self.set_response_timing(timing);
+        }
+        if session_id == DEFAULT_SESSION {
+            self.stop_keep_alive();
+        } else {
+            self.start_keep_alive();
+        }
+        Ok(response)
+    }
+}
+
+impl FunctionalUdsClient {
+    /// Switch all ECUs to the provided session. Timing reported by the ECUs is not adopted,
+    /// as it may differ between them.
+    pub async fn diagnostic_session_control(
+        &self,
+        session_id: u8,
+    ) -> Result<FunctionalResponses, UdsError> {
+        self.diagnostic_session_control_suppressible(session_id, false)
+            .await
+    }
+
+    /// Same as [FunctionalUdsClient::diagnostic_session_control] with suppressed positive
+    /// response, only the ECUs responding with NRC are in the result
+    pub async fn diagnostic_session_control_suppressed(
+        &self,
+        session_id: u8,
+    ) -> Result<FunctionalResponses, UdsError> {
+        self.diagnostic_session_control_suppressible(session_id, true)
+            .await
+    }
+
+    async fn diagnostic_session_control_suppressible(
+        &self,
+        session_id: u8,
+        suppress_positive_response: bool,
+    ) -> Result<FunctionalResponses, UdsError> {
+        let request =
+            compose_diagnostic_session_control_request(session_id, suppress_positive_response);
+        self.send_and_collect(&request, parse_diagnostic_session_control_response)
+            .await
     }
 }
 
-fn compose_diagnostic_session_control_request(session_id: u8) -> Vec<u8> {
+fn compose_diagnostic_session_control_request(
+    session_id: u8,
+    suppress_positive_response: bool,
+) -> Vec<u8> {
     vec![
         DIAGNOSTIC_SESSION_CONTROL_SID,
-        session_id,
+        compose_sub_function(session_id, suppress_positive_response),
     ]
 }
 
-fn parse_diagnostic_session_control_response(raw_response: &[u8]) -> EcuResponseResult {
+pub(super) fn parse_diagnostic_session_control_response(raw_response: &[u8]) -> EcuResponseResult {
     let mut response_iter = raw_response.iter();
     let sid = *response_iter.next().ok_or(UdsError::ResponseEmpty)?;
     if sid != DIAGNOSTIC_SESSION_CONTROL_SID + SEND_RECEIVE_SID_OFFSET {
@@ -46,9 +149,9 @@ fn parse_diagnostic_session_control_response(raw_response: &[u8]) -> EcuResponse
             raw_message: raw_response.to_vec(),
         });
     }
-    let session = *response_iter.next().ok_or(UdsError::InvalidLength {
+    let session = parse_sub_function(*response_iter.next().ok_or(UdsError::InvalidLength {
         raw_message: raw_response.to_vec(),
-    })?;
+    })?);
     let p2_hi = *response_iter.next().ok_or(UdsError::InvalidLength {
         raw_message: raw_response.to_vec(),
     })?;
@@ -64,11 +167,13 @@ fn parse_diagnostic_session_control_response(raw_response: &[u8]) -> EcuResponse
     let p2 = ((p2_hi as u16) << 8) + p2_lo as u16;
     let p2_star = ((p2s_hi as u16) << 8) + p2s_lo as u16;
 
-    let result = UdsResponse::DiagnosticSessionControl(DataFormat::Parsed(DiagnosticSessionControlResponse {
-        session,
-        p2,
-        p2_star,
-    }));
+    let result = UdsResponse::DiagnosticSessionControl(DataFormat::Parsed(
+        DiagnosticSessionControlResponse {
+            session,
+            p2,
+            p2_star,
+        },
+    ));
     Ok(result)
 }
 
@@ -103,4 +208,40 @@ fn parse_ecu_reset_response(raw_response: &[u8]) -> EcuResponseResult {
     }));
     Ok(response)
 }
-*/
\ No newline at end of file
+*/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uds::MockTransport;
+
+    #[test]
+    fn test_parse_response() {
+        let raw_response = vec![0x50, 0x03, 0x00, 0x32, 0x01, 0xf4];
+        let expected = UdsResponse::DiagnosticSessionControl(DataFormat::Parsed(
+            DiagnosticSessionControlResponse {
+                session: 0x03,
+                p2: 50,
+                p2_star: 500,
+            },
+        ));
+        assert_eq!(
+            parse_diagnostic_session_control_response(&raw_response),
+            Ok(expected)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_session_timing_is_adopted() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x10, 0x03])
+            .respond(&[0x50, 0x03, 0x00, 0x19, 0x00, 0xc8]);
+        let client = UdsClient::new_from_transport(mock.clone());
+
+        client.diagnostic_session_control(0x03).await.unwrap();
+        let timing = client.response_timing();
+        assert_eq!(timing.p2, Duration::from_millis(25));
+        assert_eq!(timing.p2_star, Duration::from_millis(2000));
+        mock.assert_done();
+    }
+}
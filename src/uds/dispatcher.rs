@@ -0,0 +1,722 @@
+//! # Background receive task
+//!
+//! [Dispatcher] decouples writes to the transport from reads. Requests are sent directly by the
+//! calling task, while single background task owns [UdsTransport::receive] and routes every
+//! received message:
+//!
+//! - negative response is routed to the request with the rejected SID
+//! - positive response is routed to the request with SID = response SID - 0x40, while events of
+//!   the same service are registered by [Dispatcher::register_event], only if it echoes the
+//!   identifier of the request (e.g. data identifier of ReadDataByIdentifier)
+//! - periodic data (0x6A with periodic identifier) and responses nobody is waiting for (e.g.
+//!   events triggered by ResponseOnEvent) are published as unsolicited responses
+//!
+//! Before sending the request, caller registers itself by [Dispatcher::register] so no response
+//! can be missed. The registration is removed when returned [PendingResponse] is dropped.
+//!
+//! Server processes single request at a time, so requests are serialized by [Dispatcher::lock].
+//! When the caller is cancelled while waiting for the response, the registration is kept as
+//! orphaned and the next request first waits for its response by
+//! [Dispatcher::drain_cancelled_request], so the late response can not be mistaken for the
+//! response to the next request.
+use crate::uds::communication::{UdsCommunicationError, UdsTransport};
+use crate::uds::uds_definitions::{
+    parse_sub_function, NegativeResponseCode, ServiceIdentifier, NEGATIVE_RESPONSE_SID,
+    SEND_RECEIVE_SID_OFFSET,
+};
+use crate::uds::ResponseTiming;
+use futures::stream::{BoxStream, Stream, StreamExt};
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+use num_enum::TryFromPrimitive;
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc, MutexGuard};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+/// Number of unsolicited responses buffered for each subscriber before the oldest are dropped
+const UNSOLICITED_CAPACITY: usize = 256;
+/// Receive errors in a row after which the transport is considered broken
+pub(super) const MAX_CONSECUTIVE_RECEIVE_ERRORS: u32 = 10;
+/// Delay after failed receive, multiplied by the number of errors in a row
+pub(super) const RECEIVE_ERROR_BACKOFF: Duration = Duration::from_millis(10);
+
+const PERIODIC_DATA_RESPONSE_SID: u8 =
+    ServiceIdentifier::ReadDataByPeriodicIdentifier as u8 + SEND_RECEIVE_SID_OFFSET;
+
+type ResponseSender = mpsc::UnboundedSender<Result<Vec<u8>, UdsCommunicationError>>;
+type ResponseReceiver = mpsc::UnboundedReceiver<Result<Vec<u8>, UdsCommunicationError>>;
+
+struct DispatcherState {
+    /// requests waiting for response, indexed by request SID
+    pending: HashMap<u8, Registration>,
+    /// serviceToRespondToRecord of events registered by [Dispatcher::register_event]
+    events: HashMap<u64, Vec<u8>>,
+    next_event_id: u64,
+    /// receive task ended, no more responses will be delivered
+    closed: bool,
+    /// last time a request was sent or finished
+    last_activity: Instant,
+    /// request cancelled before its final response arrived, its registration is still pending
+    orphan: Option<OrphanedRequest>,
+}
+
+struct Registration {
+    tx: ResponseSender,
+    /// identifier echoed by the positive response, see [echoed_identifier]
+    identifier: Vec<u8>,
+}
+
+struct OrphanedRequest {
+    sid: u8,
+    tx: ResponseSender,
+    rx: ResponseReceiver,
+    cancelled_at: Instant,
+    /// NRC 0x78 was received, so the response is expected within P2*
+    response_pending: bool,
+}
+
+impl Default for DispatcherState {
+    fn default() -> Self {
+        DispatcherState {
+            pending: HashMap::new(),
+            events: HashMap::new(),
+            next_event_id: 0,
+            closed: false,
+            last_activity: Instant::now(),
+            orphan: None,
+        }
+    }
+}
+
+pub(crate) struct Dispatcher {
+    transport: Arc<dyn UdsTransport>,
+    state: Arc<Mutex<DispatcherState>>,
+    unsolicited_tx: broadcast::Sender<(Instant, Vec<u8>)>,
+    receive_task: JoinHandle<()>,
+    /// fair mutex, so the waiting requests are sent in the order of their arrival
+    request_lock: tokio::sync::Mutex<()>,
+}
+
+/// Registration of a request waiting for response, see [Dispatcher::register]
+pub(crate) struct PendingResponse {
+    state: Arc<Mutex<DispatcherState>>,
+    sid: u8,
+    tx: ResponseSender,
+    rx: ResponseReceiver,
+    completed: bool,
+    response_pending: bool,
+}
+
+/// Registration of an event, see [Dispatcher::register_event]. The event is unregistered when
+/// dropped.
+pub(crate) struct EventRegistration {
+    state: Arc<Mutex<DispatcherState>>,
+    id: u64,
+}
+
+/// Stream of messages received from the server, which do not belong to any pending request.
+///
+/// Returned by [UdsClient::unsolicited_responses](crate::UdsClient::unsolicited_responses).
+/// Each item is raw message starting with the SID. When the subscriber is not consuming fast
+/// enough, the oldest messages are dropped.
+pub struct UnsolicitedResponses {
+    inner: BoxStream<'static, Vec<u8>>,
+}
+
+impl Dispatcher {
+    /// Spawns the receive task, has to be called from within tokio runtime
+    pub(crate) fn new(transport: Arc<dyn UdsTransport>) -> Dispatcher {
+        let state = Arc::new(Mutex::new(DispatcherState::default()));
+        let (unsolicited_tx, _) = broadcast::channel(UNSOLICITED_CAPACITY);
+        let receive_task = tokio::spawn(receive_loop(
+            transport.clone(),
+            state.clone(),
+            unsolicited_tx.clone(),
+        ));
+        Dispatcher {
+            transport,
+            state,
+            unsolicited_tx,
+            receive_task,
+            request_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Exclusive access to the server for the whole request, including repeats and pending
+    /// responses
+    pub(crate) async fn lock(&self) -> MutexGuard<'_, ()> {
+        self.request_lock.lock().await
+    }
+
+    /// If the previous request was cancelled, wait until its final response arrives or until it
+    /// times out. Has to be called with the lock held, before the next request is registered.
+    pub(crate) async fn drain_cancelled_request(&self, timing: &ResponseTiming) {
+        let Some(orphan) = self.state.lock().unwrap().orphan.take() else {
+            return;
+        };
+        // puts the orphan back, if the drain itself is cancelled
+        let mut drain = OrphanDrain {
+            state: &self.state,
+            orphan: Some(orphan),
+        };
+        let orphan = drain.orphan.as_mut().unwrap();
+        loop {
+            let timeout = if orphan.response_pending {
+                timing.p2_star
+            } else {
+                timing.p2
+            };
+            let deadline = orphan.cancelled_at + timeout + timing.network_margin;
+            match tokio::time::timeout_at(deadline, orphan.rx.recv()).await {
+                Ok(Some(Ok(response))) if is_response_pending(&response, orphan.sid) => {
+                    orphan.response_pending = true;
+                    orphan.cancelled_at = Instant::now();
+                }
+                Ok(Some(Ok(response))) => {
+                    debug!("Discarding response {:x?} of cancelled request", response);
+                    break;
+                }
+                Ok(Some(Err(e))) => {
+                    debug!("Discarding error {:?} of cancelled request", e);
+                    break;
+                }
+                Ok(None) => break,
+                Err(_) => {
+                    debug!(
+                        "Cancelled request {:#x} did not receive response",
+                        orphan.sid
+                    );
+                    break;
+                }
+            }
+        }
+        let orphan = drain.orphan.take().unwrap();
+        let mut state = self.state.lock().unwrap();
+        remove_registration(&mut state, orphan.sid, &orphan.tx);
+        state.last_activity = Instant::now();
+    }
+
+    /// Register for responses to the request. Has to be called before the request is sent.
+    pub(crate) fn register(
+        &self,
+        request: &[u8],
+    ) -> Result<PendingResponse, UdsCommunicationError> {
+        let sid = request[0];
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return Err(UdsCommunicationError::ConnectionClosed);
+        }
+        let (tx, rx) = mpsc::unbounded_channel();
+        let registration = Registration {
+            tx: tx.clone(),
+            identifier: echoed_identifier(sid, request),
+        };
+        if state.pending.insert(sid, registration).is_some() {
+            warn!(
+                "Request with SID {:#x} is already waiting for response, replacing it",
+                sid
+            );
+        }
+        Ok(PendingResponse {
+            state: self.state.clone(),
+            sid,
+            tx,
+            rx,
+            completed: false,
+            response_pending: false,
+        })
+    }
+
+    /// Register event, which the server reports by response to `service_to_respond_to_record`.
+    /// While the event is registered, response to the pending request of the same service is
+    /// told apart from the event by the echoed identifier, the event is published as unsolicited
+    /// response.
+    pub(crate) fn register_event(&self, service_to_respond_to_record: &[u8]) -> EventRegistration {
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_event_id;
+        state.next_event_id += 1;
+        state
+            .events
+            .insert(id, service_to_respond_to_record.to_vec());
+        EventRegistration {
+            state: self.state.clone(),
+            id,
+        }
+    }
+
+    pub(crate) async fn send(&self, request: &[u8]) -> Result<(), UdsCommunicationError> {
+        let result = self.transport.send(request).await;
+        self.state.lock().unwrap().last_activity = Instant::now();
+        result
+    }
+
+    /// Last time the communication with the server happened. Returns current time if any request
+    /// is waiting for response.
+    pub(crate) fn last_activity(&self) -> Instant {
+        let state = self.state.lock().unwrap();
+        let waiting = state.pending.values().any(|pending| match &state.orphan {
+            Some(orphan) => !pending.tx.same_channel(&orphan.tx),
+            None => true,
+        });
+        if waiting {
+            Instant::now()
+        } else {
+            state.last_activity
+        }
+    }
+
+    pub(crate) fn subscribe(&self) -> UnsolicitedResponses {
+        UnsolicitedResponses {
+            inner: self
+                .subscribe_timestamped()
+                .map(|(_, response)| response)
+                .boxed(),
+        }
+    }
+
+    /// Unsolicited responses together with the time of their reception
+    pub(crate) fn subscribe_timestamped(&self) -> BoxStream<'static, (Instant, Vec<u8>)> {
+        let rx = self.unsolicited_tx.subscribe();
+        let inner = futures::stream::unfold(rx, |mut rx| async move {
+            loop {
+                match rx.recv().await {
+                    Ok(response) => return Some((response, rx)),
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!(
+                            "Unsolicited responses subscriber lagged, {} dropped",
+                            skipped
+                        );
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        });
+        inner.boxed()
+    }
+}
+
+impl Drop for Dispatcher {
+    fn drop(&mut self) {
+        self.receive_task.abort();
+    }
+}
+
+impl PendingResponse {
+    /// Wait for next response routed to this request
+    pub(crate) async fn next(&mut self) -> Result<Vec<u8>, UdsCommunicationError> {
+        let response = self
+            .rx
+            .recv()
+            .await
+            .unwrap_or(Err(UdsCommunicationError::ConnectionClosed));
+        if let Ok(response) = &response {
+            self.response_pending |= is_response_pending(response, self.sid);
+        }
+        response
+    }
+
+    /// Mark the request as finished, no more responses are expected. Registration dropped
+    /// without completion is considered cancelled.
+    pub(crate) fn complete(&mut self) {
+        self.completed = true;
+    }
+}
+
+impl Drop for PendingResponse {
+    fn drop(&mut self) {
+        let mut state = self.state.lock().unwrap();
+        if !matches!(state.pending.get(&self.sid), Some(pending) if pending.tx.same_channel(&self.tx))
+        {
+            return;
+        }
+        state.last_activity = Instant::now();
+        if self.completed {
+            state.pending.remove(&self.sid);
+            return;
+        }
+        debug!(
+            "Request {:#x} cancelled while waiting for response",
+            self.sid
+        );
+        let (_, closed_rx) = mpsc::unbounded_channel();
+        let orphan = OrphanedRequest {
+            sid: self.sid,
+            tx: self.tx.clone(),
+            rx: std::mem::replace(&mut self.rx, closed_rx),
+            cancelled_at: Instant::now(),
+            response_pending: self.response_pending,
+        };
+        if let Some(previous) = state.orphan.replace(orphan) {
+            remove_registration(&mut state, previous.sid, &previous.tx);
+        }
+    }
+}
+
+/// Orphan being drained by [Dispatcher::drain_cancelled_request]
+struct OrphanDrain<'a> {
+    state: &'a Mutex<DispatcherState>,
+    /// None once the drain finished
+    orphan: Option<OrphanedRequest>,
+}
+
+impl Drop for OrphanDrain<'_> {
+    fn drop(&mut self) {
+        let Some(orphan) = self.orphan.take() else {
+            return;
+        };
+        let mut state = self.state.lock().unwrap();
+        debug!(
+            "Draining of cancelled request {:#x} cancelled, next request drains it again",
+            orphan.sid
+        );
+        if let Some(previous) = state.orphan.replace(orphan) {
+            remove_registration(&mut state, previous.sid, &previous.tx);
+        }
+    }
+}
+
+impl Drop for EventRegistration {
+    fn drop(&mut self) {
+        self.state.lock().unwrap().events.remove(&self.id);
+    }
+}
+
+fn remove_registration(state: &mut DispatcherState, sid: u8, tx: &ResponseSender) {
+    if matches!(state.pending.get(&sid), Some(pending) if pending.tx.same_channel(tx)) {
+        state.pending.remove(&sid);
+    }
+}
+
+fn is_response_pending(response: &[u8], sid: u8) -> bool {
+    response
+        == [
+            NEGATIVE_RESPONSE_SID,
+            sid,
+            NegativeResponseCode::RequestCorrectlyReceivedResponsePending as u8,
+        ]
+}
+
+impl Stream for UnsolicitedResponses {
+    type Item = Vec<u8>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Vec<u8>>> {
+        self.inner.as_mut().poll_next(cx)
+    }
+}
+
+/// Returns SID of the request the response belongs to, None if the response is unsolicited by
+/// its nature
+fn request_sid(response: &[u8]) -> Option<u8> {
+    match response {
+        [NEGATIVE_RESPONSE_SID, rejected_sid, ..] => Some(*rejected_sid),
+        [PERIODIC_DATA_RESPONSE_SID, _, ..] => None,
+        [sid, ..] if *sid >= SEND_RECEIVE_SID_OFFSET => Some(sid - SEND_RECEIVE_SID_OFFSET),
+        _ => None,
+    }
+}
+
+/// Bytes following the SID, which the positive response echoes from the request and by which it
+/// identifies the request, e.g. data identifier of ReadDataByIdentifier. They are at the same
+/// position in the request and in the response, so `message` may be either of them.
+/// suppressPosRspMsgIndicationBit of the sub-function is ignored. Empty for services without
+/// such identifier.
+pub(super) fn echoed_identifier(service: u8, message: &[u8]) -> Vec<u8> {
+    let (len, has_sub_function) = match ServiceIdentifier::try_from_primitive(service) {
+        Ok(ServiceIdentifier::ReadDtcInformation) => (1, true),
+        Ok(
+            ServiceIdentifier::ReadDataByIdentifier
+            | ServiceIdentifier::ReadScalingDataByIdentifier
+            | ServiceIdentifier::WriteDataByIdentifier
+            | ServiceIdentifier::InputOutputControlByIdentifier,
+        ) => (2, false),
+        Ok(ServiceIdentifier::RoutineControl) => (3, true),
+        _ => (0, false),
+    };
+    let mut identifier: Vec<u8> = message.iter().skip(1).take(len).copied().collect();
+    if has_sub_function {
+        if let Some(sub_function) = identifier.first_mut() {
+            *sub_function = parse_sub_function(*sub_function);
+        }
+    }
+    identifier
+}
+
+/// Positive response goes to the pending request of the same service only if it echoes its
+/// identifier, when an event of the service is registered
+fn belongs_to_request(
+    state: &DispatcherState,
+    sid: u8,
+    pending: &Registration,
+    response: &[u8],
+) -> bool {
+    if response.first() == Some(&NEGATIVE_RESPONSE_SID) {
+        return true;
+    }
+    let event_registered = state
+        .events
+        .values()
+        .any(|record| record.first() == Some(&sid));
+    !event_registered || echoed_identifier(sid, response) == pending.identifier
+}
+
+async fn receive_loop(
+    transport: Arc<dyn UdsTransport>,
+    state: Arc<Mutex<DispatcherState>>,
+    unsolicited_tx: broadcast::Sender<(Instant, Vec<u8>)>,
+) {
+    let mut consecutive_errors = 0;
+    loop {
+        let e = match transport.receive().await {
+            Ok(response) => {
+                consecutive_errors = 0;
+                dispatch(response, &state, &unsolicited_tx);
+                continue;
+            }
+            Err(e) => e,
+        };
+        // error is most likely caused by corrupted response to the pending request
+        warn!("Receiving failed with error: {:?}", e);
+        for pending in state.lock().unwrap().pending.values() {
+            let _ = pending.tx.send(Err(e.clone()));
+        }
+        if is_persistent_error(&e) {
+            error!("Transport failed with {:?}, stopping receive task", e);
+            break;
+        }
+        consecutive_errors += 1;
+        if consecutive_errors >= MAX_CONSECUTIVE_RECEIVE_ERRORS {
+            error!(
+                "Receiving failed {} times in a row, stopping receive task",
+                consecutive_errors
+            );
+            break;
+        }
+        tokio::time::sleep(RECEIVE_ERROR_BACKOFF * consecutive_errors).await;
+    }
+    let mut state = state.lock().unwrap();
+    state.closed = true;
+    // dropping the senders wakes up all waiting requests
+    state.pending.clear();
+}
+
+/// Errors after which the transport can not receive anything anymore
+pub(super) fn is_persistent_error(error: &UdsCommunicationError) -> bool {
+    matches!(
+        error,
+        UdsCommunicationError::ConnectionClosed
+            | UdsCommunicationError::NotImplementedError
+            | UdsCommunicationError::FailedToFindCanDevice
+            | UdsCommunicationError::SocketCreationError
+    )
+}
+
+fn dispatch(
+    response: Vec<u8>,
+    state: &Mutex<DispatcherState>,
+    unsolicited_tx: &broadcast::Sender<(Instant, Vec<u8>)>,
+) {
+    if let Some(sid) = request_sid(&response) {
+        let state = state.lock().unwrap();
+        if let Some(pending) = state.pending.get(&sid) {
+            if belongs_to_request(&state, sid, pending, &response) {
+                trace!("Routing response {:x?} to request {:#x}", response, sid);
+                let _ = pending.tx.send(Ok(response));
+                return;
+            }
+        }
+    }
+    debug!("Received unsolicited response {:x?}", response);
+    if unsolicited_tx.send((Instant::now(), response)).is_err() {
+        trace!("No subscriber for unsolicited responses");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uds::mock_transport::MockTransport;
+    use futures::StreamExt;
+
+    #[test]
+    fn test_request_sid() {
+        assert_eq!(request_sid(&[0x62, 0xf1, 0x90]), Some(0x22));
+        assert_eq!(request_sid(&[0x7f, 0x22, 0x78]), Some(0x22));
+        assert_eq!(request_sid(&[0x6a]), Some(0x2a));
+        assert_eq!(request_sid(&[0x6a, 0x01, 0xaa]), None);
+        assert_eq!(request_sid(&[0x10]), None);
+        assert_eq!(request_sid(&[]), None);
+    }
+
+    #[tokio::test]
+    async fn test_response_routed_to_pending_request() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x22, 0xf1, 0x90])
+            .respond(&[0x6a, 0x01, 0xaa])
+            .respond(&[0x62, 0xf1, 0x90, 0x41]);
+        let dispatcher = Dispatcher::new(Arc::new(mock.clone()));
+        let mut unsolicited = dispatcher.subscribe();
+
+        let mut pending = dispatcher.register(&[0x22, 0xf1, 0x90]).unwrap();
+        dispatcher.send(&[0x22, 0xf1, 0x90]).await.unwrap();
+        assert_eq!(pending.next().await, Ok(vec![0x62, 0xf1, 0x90, 0x41]));
+        assert_eq!(unsolicited.next().await, Some(vec![0x6a, 0x01, 0xaa]));
+        mock.assert_done();
+    }
+
+    #[test]
+    fn test_echoed_identifier() {
+        assert_eq!(
+            echoed_identifier(0x22, &[0x22, 0xf1, 0x90, 0xf1, 0x8c]),
+            vec![0xf1, 0x90]
+        );
+        assert_eq!(
+            echoed_identifier(0x22, &[0x62, 0xf1, 0x90, 0x41]),
+            vec![0xf1, 0x90]
+        );
+        assert_eq!(
+            echoed_identifier(0x31, &[0x31, 0x81, 0xff, 0x00]),
+            vec![0x01, 0xff, 0x00]
+        );
+        assert_eq!(echoed_identifier(0x23, &[0x23, 0x12, 0x20, 0x48]), vec![]);
+    }
+
+    #[tokio::test]
+    async fn test_event_not_routed_to_pending_request() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x22, 0xf1, 0x8c])
+            .respond(&[0x62, 0xf1, 0x90, 0x41])
+            .respond(&[0x62, 0xf1, 0x8c, 0x01]);
+        let dispatcher = Dispatcher::new(Arc::new(mock.clone()));
+        let mut unsolicited = dispatcher.subscribe();
+        let event = dispatcher.register_event(&[0x22, 0xf1, 0x90]);
+
+        let mut pending = dispatcher.register(&[0x22, 0xf1, 0x8c]).unwrap();
+        dispatcher.send(&[0x22, 0xf1, 0x8c]).await.unwrap();
+        assert_eq!(pending.next().await, Ok(vec![0x62, 0xf1, 0x8c, 0x01]));
+        assert_eq!(unsolicited.next().await, Some(vec![0x62, 0xf1, 0x90, 0x41]));
+        drop(event);
+        assert!(dispatcher.state.lock().unwrap().events.is_empty());
+        mock.assert_done();
+    }
+
+    /// Wait until the receive task stops
+    async fn wait_for_closed(dispatcher: &Dispatcher) {
+        while !dispatcher.state.lock().unwrap().closed {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_receive_task_stops_on_persistent_error() {
+        let mock = MockTransport::new();
+        let dispatcher = Dispatcher::new(Arc::new(mock.clone()));
+        let mut pending = dispatcher.register(&[0x22, 0xf1, 0x90]).unwrap();
+
+        mock.push_error(UdsCommunicationError::NotImplementedError);
+        assert_eq!(
+            pending.next().await,
+            Err(UdsCommunicationError::NotImplementedError)
+        );
+        tokio::time::timeout(Duration::from_millis(100), wait_for_closed(&dispatcher))
+            .await
+            .expect("receive task did not stop");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_receive_task_stops_after_repeated_errors() {
+        let mock = MockTransport::new();
+        let dispatcher = Dispatcher::new(Arc::new(mock.clone()));
+        let mut unsolicited = dispatcher.subscribe();
+
+        // successful receive resets the error counter
+        for _ in 0..MAX_CONSECUTIVE_RECEIVE_ERRORS - 1 {
+            mock.push_error(UdsCommunicationError::InvalidFrame);
+        }
+        mock.push(&[0x6a, 0x01, 0xaa]);
+        assert_eq!(unsolicited.next().await, Some(vec![0x6a, 0x01, 0xaa]));
+        assert!(!dispatcher.state.lock().unwrap().closed);
+
+        let start = Instant::now();
+        for _ in 0..MAX_CONSECUTIVE_RECEIVE_ERRORS {
+            mock.push_error(UdsCommunicationError::InvalidFrame);
+        }
+        wait_for_closed(&dispatcher).await;
+        // errors are received with increasing delay instead of busy loop
+        let backoff_steps: u32 = (1..MAX_CONSECUTIVE_RECEIVE_ERRORS).sum();
+        assert!(start.elapsed() >= RECEIVE_ERROR_BACKOFF * backoff_steps);
+    }
+
+    #[tokio::test]
+    async fn test_response_after_finished_request_is_unsolicited() {
+        let mock = MockTransport::new();
+        let dispatcher = Dispatcher::new(Arc::new(mock.clone()));
+        let mut unsolicited = dispatcher.subscribe();
+
+        let mut pending = dispatcher.register(&[0x19, 0x02, 0xff]).unwrap();
+        pending.complete();
+        drop(pending);
+        mock.push(&[0x59, 0x01]);
+        assert_eq!(unsolicited.next().await, Some(vec![0x59, 0x01]));
+        assert!(dispatcher.state.lock().unwrap().pending.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_response_of_cancelled_request_is_drained() {
+        let mock = MockTransport::new();
+        let dispatcher = Dispatcher::new(Arc::new(mock.clone()));
+        let mut unsolicited = dispatcher.subscribe();
+
+        let pending = dispatcher.register(&[0x22, 0xf1, 0x90]).unwrap();
+        drop(pending);
+        mock.push(&[0x7f, 0x22, 0x78]);
+        mock.push(&[0x62, 0xf1, 0x90, 0x41]);
+        mock.push(&[0x6a, 0x01, 0xaa]);
+        dispatcher
+            .drain_cancelled_request(&ResponseTiming::default())
+            .await;
+        assert_eq!(unsolicited.next().await, Some(vec![0x6a, 0x01, 0xaa]));
+        let state = dispatcher.state.lock().unwrap();
+        assert!(state.pending.is_empty());
+        assert!(state.orphan.is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cancelled_drain_keeps_orphan() {
+        let mock = MockTransport::new();
+        let dispatcher = Dispatcher::new(Arc::new(mock.clone()));
+        let mut unsolicited = dispatcher.subscribe();
+        let timing = ResponseTiming::default();
+
+        let pending = dispatcher.register(&[0x22, 0xf1, 0x90]).unwrap();
+        drop(pending);
+        mock.push(&[0x7f, 0x22, 0x78]);
+        let drained = tokio::time::timeout(
+            Duration::from_millis(10),
+            dispatcher.drain_cancelled_request(&timing),
+        )
+        .await;
+        assert!(drained.is_err());
+        {
+            let state = dispatcher.state.lock().unwrap();
+            assert!(state.orphan.as_ref().unwrap().response_pending);
+            assert!(state.pending.contains_key(&0x22));
+        }
+        // orphaned request is not considered to be waiting for response
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(dispatcher.last_activity() < Instant::now());
+
+        // late response is still drained by the next request
+        mock.push(&[0x62, 0xf1, 0x90, 0x41]);
+        mock.push(&[0x6a, 0x01, 0xaa]);
+        dispatcher.drain_cancelled_request(&timing).await;
+        assert_eq!(unsolicited.next().await, Some(vec![0x6a, 0x01, 0xaa]));
+        let state = dispatcher.state.lock().unwrap();
+        assert!(state.pending.is_empty());
+        assert!(state.orphan.is_none());
+    }
+}
@@ -0,0 +1,696 @@
+//! # DoIP (ISO 13400-2) transport
+//!
+//! Provides [DoipTransport], implementation of [UdsTransport] communicating with ECU trough
+//! Ethernet DoIP gateway, and UDP vehicle discovery functions [vehicle_identification] and
+//! [listen_for_vehicle_announcements].
+//!
+//! After TCP connection is established, routing activation is performed for the tester logical
+//! address. Background task then reads all incoming DoIP messages - diagnostic messages are
+//! passed to [UdsTransport::receive], diagnostic message acknowledgements are awaited by
+//! [UdsTransport::send] and alive check requests from the gateway are answered directly.
+//!
+//! ```rust,no_run
+//! use uds_rs::{DoipConfig, DoipTransport, UdsClient, UdsError};
+//!
+//! #[tokio::main(flavor = "current_thread")]
+//! async fn main() -> Result<(), UdsError> {
+//!     let transport =
+//!         DoipTransport::connect("192.168.0.10:13400", DoipConfig::new(0x0e00, 0x1001)).await?;
+//!     let c = UdsClient::new_from_transport(transport);
+//!     let _vin = c.read_data_by_identifier(&[0xf190]).await;
+//!     Ok(())
+//! }
+//! ```
+use crate::uds::communication::{UdsCommunicationError, UdsTransport};
+use futures::future::BoxFuture;
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpStream, ToSocketAddrs, UdpSocket};
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::JoinHandle;
+
+/// UDP and TCP port used for DoIP communication
+pub const DOIP_PORT: u16 = 13400;
+
+const DOIP_PROTOCOL_VERSION: u8 = 0x02;
+/// Protocol version, which may be used only in vehicle identification request
+const DOIP_DEFAULT_PROTOCOL_VERSION: u8 = 0xFF;
+const DOIP_HEADER_LEN: usize = 8;
+/// Upper bound for accepted payload, protects from allocating garbage lengths
+const DOIP_MAX_PAYLOAD_LEN: u32 = 0x0100_0000;
+
+const GENERIC_NACK: u16 = 0x0000;
+const VEHICLE_IDENTIFICATION_REQUEST: u16 = 0x0001;
+const VEHICLE_ANNOUNCEMENT: u16 = 0x0004;
+const ROUTING_ACTIVATION_REQUEST: u16 = 0x0005;
+const ROUTING_ACTIVATION_RESPONSE: u16 = 0x0006;
+const ALIVE_CHECK_REQUEST: u16 = 0x0007;
+const ALIVE_CHECK_RESPONSE: u16 = 0x0008;
+const DIAGNOSTIC_MESSAGE: u16 = 0x8001;
+const DIAGNOSTIC_MESSAGE_POSITIVE_ACK: u16 = 0x8002;
+const DIAGNOSTIC_MESSAGE_NEGATIVE_ACK: u16 = 0x8003;
+
+const ROUTING_ACTIVATION_SUCCESSFUL: u8 = 0x10;
+
+/// Addressing and timing used by [DoipTransport]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DoipConfig {
+    /// Logical address of the tester (source address of requests)
+    pub tester_address: u16,
+    /// Logical address of the ECU (target address of requests)
+    pub ecu_address: u16,
+    /// 0x00 default, 0x01 WWH-OBD, 0xE0 central security
+    pub activation_type: u8,
+    /// A_DoIP_Ctrl - time to wait for routing activation response and vehicle announcements
+    pub control_timeout: Duration,
+    /// A_DoIP_Diagnostic_Message - time to wait for diagnostic message acknowledgement
+    pub ack_timeout: Duration,
+}
+
+impl DoipConfig {
+    pub fn new(tester_address: u16, ecu_address: u16) -> DoipConfig {
+        DoipConfig {
+            tester_address,
+            ecu_address,
+            activation_type: 0x00,
+            control_timeout: Duration::from_secs(2),
+            ack_timeout: Duration::from_secs(2),
+        }
+    }
+}
+
+/// Vehicle identification response / vehicle announcement message payload
+#[derive(Debug, Clone, PartialEq)]
+pub struct VehicleAnnouncement {
+    pub vin: [u8; 17],
+    pub logical_address: u16,
+    pub eid: [u8; 6],
+    pub gid: [u8; 6],
+    pub further_action_required: u8,
+    pub vin_gid_sync_status: Option<u8>,
+}
+
+/// Send vehicle identification request to `target` (unicast or broadcast address) and collect
+/// all responses received within `timeout`.
+pub async fn vehicle_identification(
+    target: SocketAddr,
+    timeout: Duration,
+) -> Result<Vec<(SocketAddr, VehicleAnnouncement)>, UdsCommunicationError> {
+    let bind_address: SocketAddr = if target.is_ipv4() {
+        "0.0.0.0:0".parse().unwrap()
+    } else {
+        "[::]:0".parse().unwrap()
+    };
+    let socket = UdpSocket::bind(bind_address).await?;
+    socket.set_broadcast(true)?;
+    let request = compose_doip_message_with_version(
+        DOIP_DEFAULT_PROTOCOL_VERSION,
+        VEHICLE_IDENTIFICATION_REQUEST,
+        &[],
+    );
+    socket.send_to(&request, target).await?;
+    collect_vehicle_announcements(&socket, timeout).await
+}
+
+/// Listen on `bind_address` (usually 0.0.0.0:13400) for vehicle announcements sent by DoIP
+/// entities after power up, for the duration of `timeout`.
+pub async fn listen_for_vehicle_announcements(
+    bind_address: SocketAddr,
+    timeout: Duration,
+) -> Result<Vec<(SocketAddr, VehicleAnnouncement)>, UdsCommunicationError> {
+    let socket = UdpSocket::bind(bind_address).await?;
+    collect_vehicle_announcements(&socket, timeout).await
+}
+
+async fn collect_vehicle_announcements(
+    socket: &UdpSocket,
+    timeout: Duration,
+) -> Result<Vec<(SocketAddr, VehicleAnnouncement)>, UdsCommunicationError> {
+    let mut announcements = vec![];
+    let deadline = tokio::time::Instant::now() + timeout;
+    let mut buffer = [0u8; 512];
+    loop {
+        let (len, source) =
+            match tokio::time::timeout_at(deadline, socket.recv_from(&mut buffer)).await {
+                Ok(received) => received?,
+                Err(_) => break,
+            };
+        let datagram = &buffer[..len];
+        if datagram.len() < DOIP_HEADER_LEN {
+            warn!("Received too short DoIP datagram from {}", source);
+            continue;
+        }
+        let (payload_type, payload_len) = match parse_doip_header(&datagram[..DOIP_HEADER_LEN]) {
+            Ok(header) => header,
+            Err(_) => {
+                warn!("Received invalid DoIP header from {}", source);
+                continue;
+            }
+        };
+        let payload = &datagram[DOIP_HEADER_LEN..];
+        if payload_type != VEHICLE_ANNOUNCEMENT || payload.len() != payload_len as usize {
+            debug!(
+                "Ignoring DoIP datagram of type {:#06x} from {}",
+                payload_type, source
+            );
+            continue;
+        }
+        match parse_vehicle_announcement(payload) {
+            Ok(announcement) => announcements.push((source, announcement)),
+            Err(_) => warn!("Received malformed vehicle announcement from {}", source),
+        }
+    }
+    Ok(announcements)
+}
+
+/// DoIP implementation of [UdsTransport]
+pub struct DoipTransport {
+    config: DoipConfig,
+    writer: Arc<Mutex<OwnedWriteHalf>>,
+    diagnostic_rx: Mutex<mpsc::UnboundedReceiver<Vec<u8>>>,
+    ack_rx: Mutex<mpsc::UnboundedReceiver<Result<(), UdsCommunicationError>>>,
+    reader_task: JoinHandle<()>,
+}
+
+impl DoipTransport {
+    /// Open TCP connection to the DoIP entity and perform routing activation
+    pub async fn connect(
+        address: impl ToSocketAddrs,
+        config: DoipConfig,
+    ) -> Result<DoipTransport, UdsCommunicationError> {
+        let stream = TcpStream::connect(address).await?;
+        stream.set_nodelay(true)?;
+        let (reader, writer) = stream.into_split();
+        let writer = Arc::new(Mutex::new(writer));
+        let (diagnostic_tx, diagnostic_rx) = mpsc::unbounded_channel();
+        let (ack_tx, ack_rx) = mpsc::unbounded_channel();
+        let (routing_tx, mut routing_rx) = mpsc::unbounded_channel();
+        let reader_task = tokio::spawn(doip_reader_task(
+            reader,
+            writer.clone(),
+            config.clone(),
+            diagnostic_tx,
+            ack_tx,
+            routing_tx,
+        ));
+        let transport = DoipTransport {
+            config,
+            writer,
+            diagnostic_rx: Mutex::new(diagnostic_rx),
+            ack_rx: Mutex::new(ack_rx),
+            reader_task,
+        };
+
+        let request = compose_routing_activation_request(
+            transport.config.tester_address,
+            transport.config.activation_type,
+        );
+        transport
+            .write_message(ROUTING_ACTIVATION_REQUEST, &request)
+            .await?;
+        let response =
+            match tokio::time::timeout(transport.config.control_timeout, routing_rx.recv()).await {
+                Ok(Some(response)) => response,
+                Ok(None) => return Err(UdsCommunicationError::ConnectionClosed),
+                Err(_) => return Err(UdsCommunicationError::Timeout),
+            };
+        let response_code = parse_routing_activation_response(&response)?;
+        if response_code != ROUTING_ACTIVATION_SUCCESSFUL {
+            error!(
+                "Routing activation denied with response code {:#04x}",
+                response_code
+            );
+            return Err(UdsCommunicationError::RoutingActivationDenied { response_code });
+        }
+        info!(
+            "DoIP routing activated for tester address {:#06x}",
+            transport.config.tester_address
+        );
+        Ok(transport)
+    }
+
+    async fn write_message(
+        &self,
+        payload_type: u16,
+        payload: &[u8],
+    ) -> Result<(), UdsCommunicationError> {
+        let message = compose_doip_message(payload_type, payload);
+        self.writer.lock().await.write_all(&message).await?;
+        Ok(())
+    }
+}
+
+impl Drop for DoipTransport {
+    fn drop(&mut self) {
+        self.reader_task.abort();
+    }
+}
+
+impl UdsTransport for DoipTransport {
+    fn send<'a>(&'a self, payload: &'a [u8]) -> BoxFuture<'a, Result<(), UdsCommunicationError>> {
+        Box::pin(async move {
+            let mut ack_rx = self.ack_rx.lock().await;
+            // drop acknowledgements, which arrived after previous send timed out
+            while ack_rx.try_recv().is_ok() {}
+            let message = compose_diagnostic_message(
+                self.config.tester_address,
+                self.config.ecu_address,
+                payload,
+            );
+            self.write_message(DIAGNOSTIC_MESSAGE, &message).await?;
+            match tokio::time::timeout(self.config.ack_timeout, ack_rx.recv()).await {
+                Ok(Some(ack)) => ack,
+                Ok(None) => Err(UdsCommunicationError::ConnectionClosed),
+                Err(_) => Err(UdsCommunicationError::Timeout),
+            }
+        })
+    }
+
+    fn receive(&self) -> BoxFuture<'_, Result<Vec<u8>, UdsCommunicationError>> {
+        Box::pin(async move {
+            self.diagnostic_rx
+                .lock()
+                .await
+                .recv()
+                .await
+                .ok_or(UdsCommunicationError::ConnectionClosed)
+        })
+    }
+}
+
+async fn doip_reader_task(
+    mut reader: OwnedReadHalf,
+    writer: Arc<Mutex<OwnedWriteHalf>>,
+    config: DoipConfig,
+    diagnostic_tx: mpsc::UnboundedSender<Vec<u8>>,
+    ack_tx: mpsc::UnboundedSender<Result<(), UdsCommunicationError>>,
+    routing_tx: mpsc::UnboundedSender<Vec<u8>>,
+) {
+    loop {
+        let (payload_type, payload) = match read_doip_message(&mut reader).await {
+            Ok(message) => message,
+            Err(e) => {
+                info!("DoIP connection closed: {:?}", e);
+                break;
+            }
+        };
+        match payload_type {
+            DIAGNOSTIC_MESSAGE => match parse_diagnostic_message(&payload) {
+                Ok((source, target, user_data))
+                    if source == config.ecu_address && target == config.tester_address =>
+                {
+                    let _ = diagnostic_tx.send(user_data.to_vec());
+                }
+                Ok((source, target, _)) => warn!(
+                    "Ignoring diagnostic message from {:#06x} to {:#06x}",
+                    source, target
+                ),
+                Err(_) => warn!("Received malformed diagnostic message: {:x?}", payload),
+            },
+            DIAGNOSTIC_MESSAGE_POSITIVE_ACK | DIAGNOSTIC_MESSAGE_NEGATIVE_ACK => {
+                match parse_diagnostic_message(&payload) {
+                    Ok((source, target, _))
+                        if source != config.ecu_address || target != config.tester_address =>
+                    {
+                        warn!(
+                            "Ignoring diagnostic message acknowledgement from {:#06x} to {:#06x}",
+                            source, target
+                        );
+                    }
+                    Ok(_) if payload_type == DIAGNOSTIC_MESSAGE_POSITIVE_ACK => {
+                        let _ = ack_tx.send(Ok(()));
+                    }
+                    Ok((_, _, ack)) => {
+                        let nack_code = ack[0];
+                        warn!(
+                            "Diagnostic message NACK received with code {:#04x}",
+                            nack_code
+                        );
+                        let _ = ack_tx.send(Err(UdsCommunicationError::DiagnosticMessageNack {
+                            nack_code,
+                        }));
+                    }
+                    Err(_) => warn!(
+                        "Received malformed diagnostic message acknowledgement: {:x?}",
+                        payload
+                    ),
+                }
+            }
+            GENERIC_NACK => {
+                let nack_code = payload.first().copied().unwrap_or(0xff);
+                error!(
+                    "DoIP generic header NACK received with code {:#04x}",
+                    nack_code
+                );
+                let _ = ack_tx.send(Err(UdsCommunicationError::GeneralError));
+            }
+            ROUTING_ACTIVATION_RESPONSE => {
+                let _ = routing_tx.send(payload);
+            }
+            ALIVE_CHECK_REQUEST => {
+                debug!("Answering DoIP alive check");
+                let response = compose_doip_message(
+                    ALIVE_CHECK_RESPONSE,
+                    &config.tester_address.to_be_bytes(),
+                );
+                if let Err(e) = writer.lock().await.write_all(&response).await {
+                    error!("Failed to answer alive check: {:?}", e);
+                    break;
+                }
+            }
+            _ => debug!("Ignoring DoIP message of type {:#06x}", payload_type),
+        }
+    }
+}
+
+async fn read_doip_message(
+    reader: &mut (impl AsyncRead + Unpin),
+) -> Result<(u16, Vec<u8>), UdsCommunicationError> {
+    let mut header = [0u8; DOIP_HEADER_LEN];
+    reader.read_exact(&mut header).await?;
+    let (payload_type, payload_len) = parse_doip_header(&header)?;
+    let mut payload = vec![0u8; payload_len as usize];
+    reader.read_exact(&mut payload).await?;
+    Ok((payload_type, payload))
+}
+
+fn compose_doip_message(payload_type: u16, payload: &[u8]) -> Vec<u8> {
+    compose_doip_message_with_version(DOIP_PROTOCOL_VERSION, payload_type, payload)
+}
+
+fn compose_doip_message_with_version(version: u8, payload_type: u16, payload: &[u8]) -> Vec<u8> {
+    let mut message = vec![version, !version];
+    message.extend_from_slice(&payload_type.to_be_bytes());
+    message.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    message.extend_from_slice(payload);
+    message
+}
+
+/// Returns payload type and payload length
+fn parse_doip_header(header: &[u8]) -> Result<(u16, u32), UdsCommunicationError> {
+    if header.len() < DOIP_HEADER_LEN || header[0] != !header[1] {
+        return Err(UdsCommunicationError::InvalidFrame);
+    }
+    let payload_type = u16::from_be_bytes([header[2], header[3]]);
+    let payload_len = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+    if payload_len > DOIP_MAX_PAYLOAD_LEN {
+        return Err(UdsCommunicationError::InvalidFrame);
+    }
+    Ok((payload_type, payload_len))
+}
+
+fn compose_routing_activation_request(tester_address: u16, activation_type: u8) -> Vec<u8> {
+    let mut request = tester_address.to_be_bytes().to_vec();
+    request.push(activation_type);
+    // reserved by ISO
+    request.extend_from_slice(&[0x00; 4]);
+    request
+}
+
+/// Returns routing activation response code
+fn parse_routing_activation_response(payload: &[u8]) -> Result<u8, UdsCommunicationError> {
+    payload
+        .get(4)
+        .copied()
+        .ok_or(UdsCommunicationError::InvalidFrame)
+}
+
+fn compose_diagnostic_message(source: u16, target: u16, user_data: &[u8]) -> Vec<u8> {
+    let mut message = source.to_be_bytes().to_vec();
+    message.extend_from_slice(&target.to_be_bytes());
+    message.extend_from_slice(user_data);
+    message
+}
+
+/// Returns source address, target address and user data
+fn parse_diagnostic_message(payload: &[u8]) -> Result<(u16, u16, &[u8]), UdsCommunicationError> {
+    if payload.len() < 5 {
+        return Err(UdsCommunicationError::InvalidFrame);
+    }
+    let source = u16::from_be_bytes([payload[0], payload[1]]);
+    let target = u16::from_be_bytes([payload[2], payload[3]]);
+    Ok((source, target, &payload[4..]))
+}
+
+fn parse_vehicle_announcement(
+    payload: &[u8],
+) -> Result<VehicleAnnouncement, UdsCommunicationError> {
+    if payload.len() != 32 && payload.len() != 33 {
+        return Err(UdsCommunicationError::InvalidFrame);
+    }
+    let mut vin = [0u8; 17];
+    vin.copy_from_slice(&payload[0..17]);
+    let logical_address = u16::from_be_bytes([payload[17], payload[18]]);
+    let mut eid = [0u8; 6];
+    eid.copy_from_slice(&payload[19..25]);
+    let mut gid = [0u8; 6];
+    gid.copy_from_slice(&payload[25..31]);
+    Ok(VehicleAnnouncement {
+        vin,
+        logical_address,
+        eid,
+        gid,
+        further_action_required: payload[31],
+        vin_gid_sync_status: payload.get(32).copied(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uds::{DataFormat, DataRecord, ReadDataByIdentifierResponse, UdsClient};
+    use crate::uds::{UdsError, UdsResponse};
+    use tokio::net::{TcpListener, TcpStream};
+
+    const TESTER: u16 = 0x0e00;
+    const ECU: u16 = 0x1001;
+
+    fn test_announcement() -> Vec<u8> {
+        let mut payload = b"WVWZZZ1JZXW000001".to_vec();
+        payload.extend_from_slice(&ECU.to_be_bytes());
+        payload.extend_from_slice(&[1, 2, 3, 4, 5, 6]);
+        payload.extend_from_slice(&[6, 5, 4, 3, 2, 1]);
+        payload.push(0x00);
+        payload
+    }
+
+    /// Minimal DoIP entity - activates routing, performs alive check, acknowledges diagnostic
+    /// messages and answers them with canned response
+    async fn run_stand_in_server(listener: TcpListener, routing_code: u8, response: Vec<u8>) {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let (payload_type, payload) = read_doip_message(&mut stream).await.unwrap();
+        assert_eq!(payload_type, ROUTING_ACTIVATION_REQUEST);
+        assert_eq!(payload, vec![0x0e, 0x00, 0x00, 0, 0, 0, 0]);
+        let mut routing_response = TESTER.to_be_bytes().to_vec();
+        routing_response.extend_from_slice(&ECU.to_be_bytes());
+        routing_response.extend_from_slice(&[routing_code, 0, 0, 0, 0]);
+        stream
+            .write_all(&compose_doip_message(
+                ROUTING_ACTIVATION_RESPONSE,
+                &routing_response,
+            ))
+            .await
+            .unwrap();
+
+        stream
+            .write_all(&compose_doip_message(ALIVE_CHECK_REQUEST, &[]))
+            .await
+            .unwrap();
+        let (payload_type, payload) = read_doip_message(&mut stream).await.unwrap();
+        assert_eq!(payload_type, ALIVE_CHECK_RESPONSE);
+        assert_eq!(payload, TESTER.to_be_bytes().to_vec());
+
+        while let Ok((payload_type, payload)) = read_doip_message(&mut stream).await {
+            assert_eq!(payload_type, DIAGNOSTIC_MESSAGE);
+            let (source, target, user_data) = parse_diagnostic_message(&payload).unwrap();
+            assert_eq!((source, target), (TESTER, ECU));
+            let mut ack = compose_diagnostic_message(ECU, TESTER, &[0x00]);
+            ack.extend_from_slice(user_data);
+            stream
+                .write_all(&compose_doip_message(DIAGNOSTIC_MESSAGE_POSITIVE_ACK, &ack))
+                .await
+                .unwrap();
+            let message = compose_diagnostic_message(ECU, TESTER, &response);
+            stream
+                .write_all(&compose_doip_message(DIAGNOSTIC_MESSAGE, &message))
+                .await
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_compose_and_parse_header() {
+        let message = compose_doip_message(DIAGNOSTIC_MESSAGE, &[1, 2, 3]);
+        assert_eq!(message, vec![0x02, 0xfd, 0x80, 0x01, 0, 0, 0, 3, 1, 2, 3]);
+        assert_eq!(parse_doip_header(&message), Ok((DIAGNOSTIC_MESSAGE, 3)));
+    }
+
+    #[test]
+    fn test_parse_header_wrong_inverse_version() {
+        let header = [0x02, 0xfe, 0x80, 0x01, 0, 0, 0, 3];
+        assert_eq!(
+            parse_doip_header(&header),
+            Err(UdsCommunicationError::InvalidFrame)
+        );
+    }
+
+    #[test]
+    fn test_parse_vehicle_announcement() {
+        let result = parse_vehicle_announcement(&test_announcement()).unwrap();
+        assert_eq!(&result.vin, b"WVWZZZ1JZXW000001");
+        assert_eq!(result.logical_address, ECU);
+        assert_eq!(result.eid, [1, 2, 3, 4, 5, 6]);
+        assert_eq!(result.gid, [6, 5, 4, 3, 2, 1]);
+        assert_eq!(result.vin_gid_sync_status, None);
+    }
+
+    #[tokio::test]
+    async fn test_vehicle_identification_on_loopback() {
+        let entity = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let entity_address = entity.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buffer = [0u8; 64];
+            let (len, source) = entity.recv_from(&mut buffer).await.unwrap();
+            assert_eq!(
+                parse_doip_header(&buffer[..len]),
+                Ok((VEHICLE_IDENTIFICATION_REQUEST, 0))
+            );
+            let response = compose_doip_message(VEHICLE_ANNOUNCEMENT, &test_announcement());
+            entity.send_to(&response, source).await.unwrap();
+        });
+
+        let result = vehicle_identification(entity_address, Duration::from_millis(200))
+            .await
+            .unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].0, entity_address);
+        assert_eq!(result[0].1.logical_address, ECU);
+    }
+
+    #[tokio::test]
+    async fn test_client_over_doip_on_loopback() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = tokio::spawn(run_stand_in_server(
+            listener,
+            ROUTING_ACTIVATION_SUCCESSFUL,
+            vec![0x62, 0xf1, 0x90, 0x41, 0x42],
+        ));
+
+        let transport = DoipTransport::connect(address, DoipConfig::new(TESTER, ECU))
+            .await
+            .unwrap();
+        let client = UdsClient::new_from_transport(transport);
+        let result = client.read_data_by_identifier(&[0xf190]).await;
+        let expected =
+            UdsResponse::ReadDataByIdentifier(DataFormat::Parsed(ReadDataByIdentifierResponse {
+                data_records: vec![DataRecord {
+                    data_identifier: 0xf190,
+                    data: vec![0x41, 0x42],
+                }],
+            }));
+        assert_eq!(result, Ok(expected));
+        drop(client);
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_routing_activation_denied() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(run_stand_in_server(listener, 0x00, vec![]));
+
+        let result = DoipTransport::connect(address, DoipConfig::new(TESTER, ECU)).await;
+        assert!(matches!(
+            result,
+            Err(UdsCommunicationError::RoutingActivationDenied {
+                response_code: 0x00
+            })
+        ));
+    }
+
+    /// Accept the connection and activate routing
+    async fn accept_with_routing(listener: TcpListener) -> TcpStream {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        read_doip_message(&mut stream).await.unwrap();
+        let mut routing_response = TESTER.to_be_bytes().to_vec();
+        routing_response.extend_from_slice(&ECU.to_be_bytes());
+        routing_response.extend_from_slice(&[ROUTING_ACTIVATION_SUCCESSFUL, 0, 0, 0, 0]);
+        stream
+            .write_all(&compose_doip_message(
+                ROUTING_ACTIVATION_RESPONSE,
+                &routing_response,
+            ))
+            .await
+            .unwrap();
+        stream
+    }
+
+    #[tokio::test]
+    async fn test_diagnostic_message_nack() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut stream = accept_with_routing(listener).await;
+            read_doip_message(&mut stream).await.unwrap();
+            let nack = compose_diagnostic_message(ECU, TESTER, &[0x03]);
+            stream
+                .write_all(&compose_doip_message(
+                    DIAGNOSTIC_MESSAGE_NEGATIVE_ACK,
+                    &nack,
+                ))
+                .await
+                .unwrap();
+        });
+
+        let transport = DoipTransport::connect(address, DoipConfig::new(TESTER, ECU))
+            .await
+            .unwrap();
+        let client = UdsClient::new_from_transport(transport);
+        let result = client.ecu_reset(crate::uds::ResetType::HardReset).await;
+        assert_eq!(
+            result,
+            Err(UdsError::CommunicationError {
+                error: UdsCommunicationError::DiagnosticMessageNack { nack_code: 0x03 }
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ack_for_other_address_is_ignored() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut stream = accept_with_routing(listener).await;
+            read_doip_message(&mut stream).await.unwrap();
+            let stray_ack = compose_diagnostic_message(0x1002, TESTER, &[0x00]);
+            let nack = compose_diagnostic_message(ECU, TESTER, &[0x03]);
+            for (payload_type, payload) in [
+                (DIAGNOSTIC_MESSAGE_POSITIVE_ACK, stray_ack),
+                (DIAGNOSTIC_MESSAGE_NEGATIVE_ACK, nack),
+            ] {
+                stream
+                    .write_all(&compose_doip_message(payload_type, &payload))
+                    .await
+                    .unwrap();
+            }
+        });
+
+        let transport = DoipTransport::connect(address, DoipConfig::new(TESTER, ECU))
+            .await
+            .unwrap();
+        let client = UdsClient::new_from_transport(transport);
+        let result = client.ecu_reset(crate::uds::ResetType::HardReset).await;
+        assert_eq!(
+            result,
+            Err(UdsError::CommunicationError {
+                error: UdsCommunicationError::DiagnosticMessageNack { nack_code: 0x03 }
+            })
+        );
+    }
+}
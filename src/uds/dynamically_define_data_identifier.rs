@@ -0,0 +1,562 @@
+//! # Implementation of DynamicallyDefineDataIdentifier 0x2C service
+//!
+//! This module provides following methods for UdsClient:
+//!
+//! [UdsClient::dynamically_define_data_identifier]
+//! [UdsClient::dynamically_define_data_identifier_suppressed]
+//! [UdsClient::clear_dynamically_defined_data_identifier]
+//! [UdsClient::clear_dynamically_defined_data_identifier_suppressed]
+//! [UdsClient::read_dynamically_defined_data_identifier]
+//!
+//! Definition is built by [DynamicallyDefinedDataIdentifier] from parts of other data identifiers
+//! and from memory areas. Data of the defined identifier are concatenation of all parts in the
+//! order of definition, so they can be read by [UdsClient::read_data_by_identifier] and split back
+//! into the parts by [DynamicallyDefinedDataIdentifier::decode].
+//!
+//! Parts defined by identifier and by memory address can not be combined in single request, the
+//! definition is sent in as many requests as needed, each extending the identifier.
+//!
+use super::*;
+use crate::uds::read_memory_by_address::convert_from_simple_to_normal;
+use crate::uds::uds_definitions::{
+    compose_sub_function, parse_sub_function, SEND_RECEIVE_SID_OFFSET,
+};
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+
+const DYNAMICALLY_DEFINE_DATA_IDENTIFIER_SID: u8 = 0x2C;
+
+#[derive(IntoPrimitive, TryFromPrimitive, Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum DefinitionType {
+    DefineByIdentifier = 1,
+    DefineByMemoryAddress = 2,
+    ClearDynamicallyDefinedDataIdentifier = 3,
+}
+
+/// Single part of dynamically defined data identifier
+#[derive(Debug, Clone, PartialEq)]
+pub enum DynamicDataSource {
+    Identifier {
+        source_data_identifier: u16,
+        /// position of the first byte in the source data record, starting at 1
+        position_in_source_data_record: u8,
+        memory_size: u8,
+    },
+    MemoryAddress {
+        memory_address: u64,
+        memory_size: u64,
+    },
+}
+
+impl DynamicDataSource {
+    /// None when the size does not fit into usize
+    fn len(&self) -> Option<usize> {
+        match self {
+            DynamicDataSource::Identifier { memory_size, .. } => Some(*memory_size as usize),
+            DynamicDataSource::MemoryAddress { memory_size, .. } => {
+                usize::try_from(*memory_size).ok()
+            }
+        }
+    }
+
+    fn definition_type(&self) -> DefinitionType {
+        match self {
+            DynamicDataSource::Identifier { .. } => DefinitionType::DefineByIdentifier,
+            DynamicDataSource::MemoryAddress { .. } => DefinitionType::DefineByMemoryAddress,
+        }
+    }
+}
+
+/// Data of single part, returned by [DynamicallyDefinedDataIdentifier::decode]
+#[derive(Debug, PartialEq)]
+pub struct DynamicDataField {
+    pub source: DynamicDataSource,
+    pub data: Vec<u8>,
+}
+
+/// Builder of the definition.
+///
+/// ```
+/// use uds_rs::DynamicallyDefinedDataIdentifier;
+///
+/// let definition = DynamicallyDefinedDataIdentifier::new(0xf301)
+///     .by_identifier(0x010a, 1, 2)
+///     .by_identifier(0x0b01, 3, 1)
+///     .by_memory_address(0x2000_4000, 4);
+/// assert_eq!(definition.sources().len(), 3);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct DynamicallyDefinedDataIdentifier {
+    data_identifier: u16,
+    sources: Vec<DynamicDataSource>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct DynamicallyDefineDataIdentifierResponse {
+    pub definition_type: DefinitionType,
+    /// None when all dynamically defined identifiers were cleared
+    pub dynamically_defined_data_identifier: Option<u16>,
+}
+
+impl DynamicallyDefinedDataIdentifier {
+    /// Identifiers 0xF200 - 0xF3FF are reserved for dynamically defined identifiers
+    pub fn new(data_identifier: u16) -> DynamicallyDefinedDataIdentifier {
+        DynamicallyDefinedDataIdentifier {
+            data_identifier,
+            sources: vec![],
+        }
+    }
+
+    /// Append `memory_size` bytes of `source_data_identifier` starting at
+    /// `position_in_source_data_record` (first byte is 1)
+    pub fn by_identifier(
+        mut self,
+        source_data_identifier: u16,
+        position_in_source_data_record: u8,
+        memory_size: u8,
+    ) -> DynamicallyDefinedDataIdentifier {
+        self.sources.push(DynamicDataSource::Identifier {
+            source_data_identifier,
+            position_in_source_data_record,
+            memory_size,
+        });
+        self
+    }
+
+    /// Append `memory_size` bytes of memory starting at `memory_address`
+    pub fn by_memory_address(
+        mut self,
+        memory_address: u64,
+        memory_size: u64,
+    ) -> DynamicallyDefinedDataIdentifier {
+        self.sources.push(DynamicDataSource::MemoryAddress {
+            memory_address,
+            memory_size,
+        });
+        self
+    }
+
+    pub fn data_identifier(&self) -> u16 {
+        self.data_identifier
+    }
+
+    pub fn sources(&self) -> &[DynamicDataSource] {
+        &self.sources
+    }
+
+    /// Requests defining the identifier - consecutive parts of the same type are defined by single
+    /// request
+    fn compose_requests(&self, suppress_positive_response: bool) -> Result<Vec<Vec<u8>>, UdsError> {
+        if self.sources.is_empty() {
+            error!(
+                "Dynamically defined identifier {:#x} has no source",
+                self.data_identifier
+            );
+            return Err(UdsError::InvalidArgument);
+        }
+        self.sources
+            .chunk_by(|a, b| a.definition_type() == b.definition_type())
+            .map(|sources| {
+                let definition_type = sources[0].definition_type();
+                let mut request = vec![
+                    DYNAMICALLY_DEFINE_DATA_IDENTIFIER_SID,
+                    compose_sub_function(definition_type.into(), suppress_positive_response),
+                ];
+                request.extend_from_slice(&self.data_identifier.to_be_bytes());
+                match definition_type {
+                    DefinitionType::DefineByMemoryAddress => {
+                        compose_memory_sources(&mut request, sources)?
+                    }
+                    _ => compose_identifier_sources(&mut request, sources),
+                }
+                Ok(request)
+            })
+            .collect()
+    }
+
+    /// Split data read from the defined identifier into its parts
+    pub fn decode(&self, data: &[u8]) -> Result<Vec<DynamicDataField>, UdsError> {
+        let Some(expected_len) = self
+            .sources
+            .iter()
+            .try_fold(0usize, |total, source| total.checked_add(source.len()?))
+        else {
+            error!(
+                "Size of identifier {:#x} exceeds the address space",
+                self.data_identifier
+            );
+            return Err(UdsError::InvalidArgument);
+        };
+        if data.len() != expected_len {
+            error!(
+                "Identifier {:#x} has {} bytes, definition expects {}",
+                self.data_identifier,
+                data.len(),
+                expected_len
+            );
+            return Err(UdsError::InvalidLength {
+                raw_message: data.to_vec(),
+            });
+        }
+        // sizes were checked above, so none of them overflows
+        let mut remaining = data;
+        let fields = self
+            .sources
+            .iter()
+            .map(|source| {
+                let (field_data, rest) = remaining.split_at(source.len().unwrap_or_default());
+                remaining = rest;
+                DynamicDataField {
+                    source: source.clone(),
+                    data: field_data.to_vec(),
+                }
+            })
+            .collect();
+        Ok(fields)
+    }
+}
+
+impl UdsClient {
+    /// Send all requests of the definition, returns response to the last one
+    pub async fn dynamically_define_data_identifier(
+        &self,
+        definition: &DynamicallyDefinedDataIdentifier,
+    ) -> EcuResponseResult {
+        self.dynamically_define_data_identifier_suppressible(definition, false)
+            .await
+    }
+
+    /// Same as [UdsClient::dynamically_define_data_identifier] with suppressed positive
+    /// responses. Returns [UdsResponse::PositiveResponseSuppressed] unless the server responds
+    /// with NRC.
+    pub async fn dynamically_define_data_identifier_suppressed(
+        &self,
+        definition: &DynamicallyDefinedDataIdentifier,
+    ) -> EcuResponseResult {
+        self.dynamically_define_data_identifier_suppressible(definition, true)
+            .await
+    }
+
+    /// Clear the identifier, or all dynamically defined identifiers when None
+    pub async fn clear_dynamically_defined_data_identifier(
+        &self,
+        data_identifier: Option<u16>,
+    ) -> EcuResponseResult {
+        self.clear_dynamically_defined_data_identifier_suppressible(data_identifier, false)
+            .await
+    }
+
+    /// Same as [UdsClient::clear_dynamically_defined_data_identifier] with suppressed positive
+    /// response
+    pub async fn clear_dynamically_defined_data_identifier_suppressed(
+        &self,
+        data_identifier: Option<u16>,
+    ) -> EcuResponseResult {
+        self.clear_dynamically_defined_data_identifier_suppressible(data_identifier, true)
+            .await
+    }
+
+    async fn dynamically_define_data_identifier_suppressible(
+        &self,
+        definition: &DynamicallyDefinedDataIdentifier,
+        suppress_positive_response: bool,
+    ) -> EcuResponseResult {
+        let mut response = Ok(UdsResponse::PositiveResponseSuppressed);
+        for request in definition.compose_requests(suppress_positive_response)? {
+            response = self
+                .send_dynamically_define_data_identifier(
+                    &request,
+                    Some(definition.data_identifier),
+                    suppress_positive_response,
+                )
+                .await;
+            if response.is_err() {
+                break;
+            }
+        }
+        response
+    }
+
+    async fn clear_dynamically_defined_data_identifier_suppressible(
+        &self,
+        data_identifier: Option<u16>,
+        suppress_positive_response: bool,
+    ) -> EcuResponseResult {
+        let mut request = vec![
+            DYNAMICALLY_DEFINE_DATA_IDENTIFIER_SID,
+            compose_sub_function(
+                DefinitionType::ClearDynamicallyDefinedDataIdentifier.into(),
+                suppress_positive_response,
+            ),
+        ];
+        if let Some(data_identifier) = data_identifier {
+            request.extend_from_slice(&data_identifier.to_be_bytes());
+        }
+        self.send_dynamically_define_data_identifier(
+            &request,
+            data_identifier,
+            suppress_positive_response,
+        )
+        .await
+    }
+
+    /// Read the defined identifier by [UdsClient::read_data_by_identifier] and split its data
+    /// into the parts
+    pub async fn read_dynamically_defined_data_identifier(
+        &self,
+        definition: &DynamicallyDefinedDataIdentifier,
+    ) -> Result<Vec<DynamicDataField>, UdsError> {
+        let record = self.read_data_record(definition.data_identifier).await?;
+        definition.decode(&record.data)
+    }
+
+    /// Send single request and check that the response echoes `data_identifier`
+    async fn send_dynamically_define_data_identifier(
+        &self,
+        request: &[u8],
+        data_identifier: Option<u16>,
+        suppress_positive_response: bool,
+    ) -> EcuResponseResult {
+        let Some(raw_response) = self
+            .send_and_receive_suppressible(request, suppress_positive_response)
+            .await?
+        else {
+            return Ok(UdsResponse::PositiveResponseSuppressed);
+        };
+        let response = parse_dynamically_define_data_identifier_response(&raw_response)?;
+        if let (
+            Some(expected),
+            UdsResponse::DynamicallyDefineDataIdentifier(DataFormat::Parsed(defined)),
+        ) = (data_identifier, &response)
+        {
+            match defined.dynamically_defined_data_identifier {
+                Some(received) if received != expected => {
+                    return Err(UdsError::DidMismatch {
+                        expected,
+                        received,
+                        raw_message: raw_response,
+                    });
+                }
+                None => {
+                    error!("Response does not echo identifier {:#x}", expected);
+                    return Err(UdsError::ResponseIncorrect {
+                        raw_message: raw_response,
+                    });
+                }
+                _ => {}
+            }
+        }
+        Ok(response)
+    }
+}
+
+fn compose_identifier_sources(request: &mut Vec<u8>, sources: &[DynamicDataSource]) {
+    for source in sources {
+        if let DynamicDataSource::Identifier {
+            source_data_identifier,
+            position_in_source_data_record,
+            memory_size,
+        } = source
+        {
+            request.extend_from_slice(&source_data_identifier.to_be_bytes());
+            request.push(*position_in_source_data_record);
+            request.push(*memory_size);
+        }
+    }
+}
+
+/// All memory areas in single request share addressAndLengthFormatIdentifier, so it is derived
+/// from the largest address and size
+fn compose_memory_sources(
+    request: &mut Vec<u8>,
+    sources: &[DynamicDataSource],
+) -> Result<(), UdsError> {
+    let memory_areas: Vec<(u64, u64)> = sources
+        .iter()
+        .filter_map(|source| match source {
+            DynamicDataSource::MemoryAddress {
+                memory_address,
+                memory_size,
+            } => Some((*memory_address, *memory_size)),
+            _ => None,
+        })
+        .collect();
+    let max_address = memory_areas.iter().map(|(a, _)| *a).max().unwrap_or(0);
+    let max_size = memory_areas.iter().map(|(_, s)| *s).max().unwrap_or(0);
+    let (address_and_length_format_identifier, _, _) =
+        convert_from_simple_to_normal(max_address, max_size, None, None)?;
+    request.push(address_and_length_format_identifier);
+    for (memory_address, memory_size) in memory_areas {
+        let (_, memory_address, memory_size) = convert_from_simple_to_normal(
+            memory_address,
+            memory_size,
+            Some(address_and_length_format_identifier & 0x0f),
+            Some(address_and_length_format_identifier >> 4),
+        )?;
+        request.extend_from_slice(&memory_address);
+        request.extend_from_slice(&memory_size);
+    }
+    Ok(())
+}
+
+pub(super) fn parse_dynamically_define_data_identifier_response(
+    raw_response: &[u8],
+) -> EcuResponseResult {
+    let mut response_iter = raw_response.iter();
+    let sid = *response_iter.next().ok_or(UdsError::ResponseEmpty)?;
+    if sid != DYNAMICALLY_DEFINE_DATA_IDENTIFIER_SID + SEND_RECEIVE_SID_OFFSET {
+        return Err(UdsError::SidMismatch {
+            expected: DYNAMICALLY_DEFINE_DATA_IDENTIFIER_SID + SEND_RECEIVE_SID_OFFSET,
+            received: sid,
+            raw_message: raw_response.to_vec(),
+        });
+    }
+    let definition_type_byte = *response_iter.next().ok_or(UdsError::InvalidLength {
+        raw_message: raw_response.to_vec(),
+    })?;
+    let definition_type = DefinitionType::try_from_primitive(parse_sub_function(
+        definition_type_byte,
+    ))
+    .map_err(|_| UdsError::ResponseIncorrect {
+        raw_message: raw_response.to_vec(),
+    })?;
+    let dynamically_defined_data_identifier = match response_iter.as_slice() {
+        [] if definition_type == DefinitionType::ClearDynamicallyDefinedDataIdentifier => None,
+        [did_hi, did_lo] => Some(u16::from_be_bytes([*did_hi, *did_lo])),
+        _ => {
+            return Err(UdsError::InvalidLength {
+                raw_message: raw_response.to_vec(),
+            })
+        }
+    };
+    Ok(UdsResponse::DynamicallyDefineDataIdentifier(
+        DataFormat::Parsed(DynamicallyDefineDataIdentifierResponse {
+            definition_type,
+            dynamically_defined_data_identifier,
+        }),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compose_requests() {
+        let definition = DynamicallyDefinedDataIdentifier::new(0xf301)
+            .by_identifier(0x010a, 1, 2)
+            .by_identifier(0x0b01, 3, 1)
+            .by_memory_address(0x2000_4000, 4)
+            .by_memory_address(0x10, 0x102);
+        assert_eq!(
+            definition.compose_requests(false),
+            Ok(vec![
+                vec![0x2c, 0x01, 0xf3, 0x01, 0x01, 0x0a, 0x01, 0x02, 0x0b, 0x01, 0x03, 0x01],
+                vec![
+                    0x2c, 0x02, 0xf3, 0x01, 0x24, 0x20, 0x00, 0x40, 0x00, 0x00, 0x04, 0x00, 0x00,
+                    0x00, 0x10, 0x01, 0x02
+                ]
+            ])
+        );
+        assert_eq!(
+            DynamicallyDefinedDataIdentifier::new(0xf301).compose_requests(false),
+            Err(UdsError::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn test_decode() {
+        let definition = DynamicallyDefinedDataIdentifier::new(0xf301)
+            .by_identifier(0x010a, 1, 2)
+            .by_memory_address(0x2000, 1);
+        assert_eq!(
+            definition.decode(&[0x11, 0x22, 0x33]),
+            Ok(vec![
+                DynamicDataField {
+                    source: definition.sources()[0].clone(),
+                    data: vec![0x11, 0x22],
+                },
+                DynamicDataField {
+                    source: definition.sources()[1].clone(),
+                    data: vec![0x33],
+                }
+            ])
+        );
+        assert_eq!(
+            definition.decode(&[0x11, 0x22]),
+            Err(UdsError::InvalidLength {
+                raw_message: vec![0x11, 0x22]
+            })
+        );
+    }
+
+    #[test]
+    fn test_decode_size_overflow() {
+        let definition = DynamicallyDefinedDataIdentifier::new(0xf301)
+            .by_identifier(0x010a, 1, 2)
+            .by_memory_address(0x2000, u64::MAX);
+        assert_eq!(
+            definition.decode(&[0x11, 0x22]),
+            Err(UdsError::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn test_parse_response() {
+        assert_eq!(
+            parse_dynamically_define_data_identifier_response(&[0x6c, 0x03]),
+            Ok(UdsResponse::DynamicallyDefineDataIdentifier(
+                DataFormat::Parsed(DynamicallyDefineDataIdentifierResponse {
+                    definition_type: DefinitionType::ClearDynamicallyDefinedDataIdentifier,
+                    dynamically_defined_data_identifier: None,
+                })
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_define_and_read() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x2c, 0x01, 0xf3, 0x01, 0x01, 0x0a, 0x02, 0x01])
+            .respond(&[0x6c, 0x01, 0xf3, 0x01]);
+        mock.expect(&[0x2c, 0x02, 0xf3, 0x01, 0x12, 0x20, 0x00, 0x02])
+            .respond(&[0x6c, 0x02, 0xf3, 0x01]);
+        mock.expect(&[0x22, 0xf3, 0x01])
+            .respond(&[0x62, 0xf3, 0x01, 0xaa, 0xbb, 0xcc]);
+        let client = UdsClient::new_from_transport(mock.clone());
+        let definition = DynamicallyDefinedDataIdentifier::new(0xf301)
+            .by_identifier(0x010a, 2, 1)
+            .by_memory_address(0x2000, 2);
+
+        assert!(client
+            .dynamically_define_data_identifier(&definition)
+            .await
+            .is_ok());
+        let fields = client
+            .read_dynamically_defined_data_identifier(&definition)
+            .await
+            .unwrap();
+        assert_eq!(fields[0].data, vec![0xaa]);
+        assert_eq!(fields[1].data, vec![0xbb, 0xcc]);
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_echoed_identifier_mismatch() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x2c, 0x01, 0xf3, 0x01, 0x01, 0x0a, 0x02, 0x01])
+            .respond(&[0x6c, 0x01, 0xf3, 0x02]);
+        let client = UdsClient::new_from_transport(mock.clone());
+        let definition = DynamicallyDefinedDataIdentifier::new(0xf301).by_identifier(0x010a, 2, 1);
+
+        assert_eq!(
+            client.dynamically_define_data_identifier(&definition).await,
+            Err(UdsError::DidMismatch {
+                expected: 0xf301,
+                received: 0xf302,
+                raw_message: vec![0x6c, 0x01, 0xf3, 0x02]
+            })
+        );
+        mock.assert_done();
+    }
+}
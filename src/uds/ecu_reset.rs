@@ -3,9 +3,17 @@
 //! This module provides following methods for UdsClient:
 //!
 //! [UdsClient::ecu_reset]
+//! [UdsClient::ecu_reset_suppressed]
+//!
+//! and following methods for FunctionalUdsClient:
+//!
+//! [FunctionalUdsClient::ecu_reset]
+//! [FunctionalUdsClient::ecu_reset_suppressed]
 //!
 use super::*;
-use crate::uds::uds_definitions::SEND_RECEIVE_SID_OFFSET;
+use crate::uds::uds_definitions::{
+    compose_sub_function, parse_sub_function, SEND_RECEIVE_SID_OFFSET,
+};
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 
 const ECU_RESET_SID: u8 = 0x11;
@@ -20,6 +28,16 @@ pub enum ResetType {
     DisableRapidPowerShutDown = 5,
 }
 
+impl ResetType {
+    /// Rapid power shutdown sub-functions only configure the server, they do not reset it
+    fn resets_server(&self) -> bool {
+        matches!(
+            self,
+            ResetType::HardReset | ResetType::KeyOffOnReset | ResetType::SoftReset
+        )
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub struct EcuResetResponse {
     pub reset_type: ResetType,
@@ -28,18 +46,75 @@ pub struct EcuResetResponse {
 
 impl UdsClient {
     pub async fn ecu_reset(&self, reset_type: ResetType) -> EcuResponseResult {
-        let request = compose_ecu_reset_request(reset_type);
-        let raw_response = self.send_and_receive(&request).await?;
-        let response = parse_ecu_reset_response(&raw_response);
-        response
+        self.ecu_reset_suppressible(reset_type, false).await
+    }
+
+    /// Same as [UdsClient::ecu_reset] with suppressed positive response. Returns
+    /// [UdsResponse::PositiveResponseSuppressed] unless the server responds with NRC.
+    pub async fn ecu_reset_suppressed(&self, reset_type: ResetType) -> EcuResponseResult {
+        self.ecu_reset_suppressible(reset_type, true).await
+    }
+
+    async fn ecu_reset_suppressible(
+        &self,
+        reset_type: ResetType,
+        suppress_positive_response: bool,
+    ) -> EcuResponseResult {
+        let resets_server = reset_type.resets_server();
+        let request = compose_ecu_reset_request(reset_type, suppress_positive_response);
+        let response = match self
+            .send_and_receive_suppressible(&request, suppress_positive_response)
+            .await?
+        {
+            Some(raw_response) => parse_ecu_reset_response(&raw_response)?,
+            None => UdsResponse::PositiveResponseSuppressed,
+        };
+        // server starts in the default session after the reset
+        if resets_server {
+            self.stop_keep_alive();
+            let default_timing = ResponseTiming::default();
+            let mut timing = self.response_timing();
+            timing.p2 = default_timing.p2;
+            timing.p2_star = default_timing.p2_star;
+            self.set_response_timing(timing);
+        }
+        Ok(response)
     }
 }
 
-fn compose_ecu_reset_request(reset_type: ResetType) -> Vec<u8> {
-    vec![ECU_RESET_SID, reset_type as u8]
+impl FunctionalUdsClient {
+    pub async fn ecu_reset(&self, reset_type: ResetType) -> Result<FunctionalResponses, UdsError> {
+        self.ecu_reset_suppressible(reset_type, false).await
+    }
+
+    /// Same as [FunctionalUdsClient::ecu_reset] with suppressed positive response, only the ECUs
+    /// responding with NRC are in the result
+    pub async fn ecu_reset_suppressed(
+        &self,
+        reset_type: ResetType,
+    ) -> Result<FunctionalResponses, UdsError> {
+        self.ecu_reset_suppressible(reset_type, true).await
+    }
+
+    async fn ecu_reset_suppressible(
+        &self,
+        reset_type: ResetType,
+        suppress_positive_response: bool,
+    ) -> Result<FunctionalResponses, UdsError> {
+        let request = compose_ecu_reset_request(reset_type, suppress_positive_response);
+        self.send_and_collect(&request, parse_ecu_reset_response)
+            .await
+    }
+}
+
+fn compose_ecu_reset_request(reset_type: ResetType, suppress_positive_response: bool) -> Vec<u8> {
+    vec![
+        ECU_RESET_SID,
+        compose_sub_function(reset_type as u8, suppress_positive_response),
+    ]
 }
 
-fn parse_ecu_reset_response(raw_response: &[u8]) -> EcuResponseResult {
+pub(super) fn parse_ecu_reset_response(raw_response: &[u8]) -> EcuResponseResult {
     let mut response_iter = raw_response.iter();
     let sid = *response_iter.next().ok_or(UdsError::ResponseEmpty)?;
     if sid != ECU_RESET_SID + SEND_RECEIVE_SID_OFFSET {
@@ -52,11 +127,10 @@ fn parse_ecu_reset_response(raw_response: &[u8]) -> EcuResponseResult {
     let reset_type_byte = *response_iter.next().ok_or(UdsError::InvalidLength {
         raw_message: raw_response.to_vec(),
     })?;
-    let reset_type: ResetType = ResetType::try_from_primitive(reset_type_byte).map_err(|_| {
-        UdsError::ResponseIncorrect {
+    let reset_type: ResetType = ResetType::try_from_primitive(parse_sub_function(reset_type_byte))
+        .map_err(|_| UdsError::ResponseIncorrect {
             raw_message: raw_response.to_vec(),
-        }
-    })?;
+        })?;
     let mut power_down_time = None;
     if reset_type == ResetType::EnableRapidPowerShutDown {
         power_down_time = Some(*response_iter.next().ok_or(UdsError::InvalidLength {
@@ -69,3 +143,29 @@ fn parse_ecu_reset_response(raw_response: &[u8]) -> EcuResponseResult {
     }));
     Ok(response)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compose_request_with_suppress_bit() {
+        assert_eq!(
+            compose_ecu_reset_request(ResetType::SoftReset, true),
+            vec![0x11, 0x83]
+        );
+        assert_eq!(
+            compose_ecu_reset_request(ResetType::SoftReset, false),
+            vec![0x11, 0x03]
+        );
+    }
+
+    #[test]
+    fn test_parse_response_with_suppress_bit_echoed() {
+        let expected = UdsResponse::EcuReset(DataFormat::Parsed(EcuResetResponse {
+            reset_type: ResetType::SoftReset,
+            power_down_time: None,
+        }));
+        assert_eq!(parse_ecu_reset_response(&[0x51, 0x83]), Ok(expected));
+    }
+}
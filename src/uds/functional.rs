@@ -0,0 +1,357 @@
+//! # Functional addressing
+//!
+//! [FunctionalUdsClient] broadcasts request on the functional ID (e.g. 0x7DF or 0x18DB33F1) and
+//! collects responses of all ECUs answering within P2. Each ECU is configured by its physical
+//! request ID and response ID. Responses are received by [UdsSocket] opened for each ECU, so long
+//! responses are flow controlled towards the physical request ID of the answering ECU. The
+//! functional request itself is sent as raw CAN single frame, as ISO 15765-2 does not allow
+//! segmented functional requests.
+//!
+//! Result is map from the ECU address (raw response ID for CAN) to the [EcuResponseResult].
+//! ECUs which did not respond, or responded with NRC 0x11, 0x12 or 0x31, which must not be sent
+//! to functional requests, are not part of the map.
+//!
+//! Services available for functional addressing are implemented in service modules next to their
+//! [UdsClient](crate::UdsClient) counterparts.
+//!
+use crate::uds::communication::{Id, UdsCommunicationError, UdsSocket, UdsTransport};
+use crate::uds::dispatcher::{Dispatcher, PendingResponse};
+use crate::uds::isotp::{id_to_raw, segment, CanFrame, CanFrameIo, RawCanSocket};
+use crate::uds::{
+    parse_for_error, EcuResponseResult, NegativeResponseCode, ResponseTiming, UdsError,
+};
+use futures::future::BoxFuture;
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Responses of all ECUs to single functional request, indexed by the ECU address
+pub type FunctionalResponses = HashMap<u32, EcuResponseResult>;
+
+/// Classic CAN frame length
+const CAN_FRAME_LEN: usize = 8;
+
+/// Send-only transport of functional requests used by [FunctionalUdsClient]. Responses are
+/// received from the ECUs on their physical IDs by separate [UdsTransport] for each ECU.
+pub trait FunctionalTransport: Send + Sync {
+    /// Broadcast single UDS PDU to all ECUs
+    fn send<'a>(&'a self, payload: &'a [u8]) -> BoxFuture<'a, Result<(), UdsCommunicationError>>;
+}
+
+/// Transport sending whole request as single ISO-TP frame on the functional ID.
+pub struct FunctionalSender {
+    io: Box<dyn CanFrameIo>,
+    id: Id,
+    frame_len: usize,
+    padding: Option<u8>,
+}
+
+impl FunctionalSender {
+    pub fn new(io: impl CanFrameIo + 'static, id: impl Into<Id>) -> FunctionalSender {
+        FunctionalSender {
+            io: Box::new(io),
+            id: id.into(),
+            frame_len: CAN_FRAME_LEN,
+            padding: None,
+        }
+    }
+
+    /// Fill unused bytes of the frame with provided value
+    pub fn with_padding(mut self, padding: u8) -> FunctionalSender {
+        self.padding = Some(padding);
+        self
+    }
+
+    pub async fn send(&self, payload: &[u8]) -> Result<(), UdsCommunicationError> {
+        let frames = segment(payload, self.frame_len);
+        if payload.is_empty() || frames.len() != 1 {
+            error!(
+                "Functional request {:x?} does not fit into single frame",
+                payload
+            );
+            return Err(UdsCommunicationError::InvalidFrame);
+        }
+        let mut data = frames[0].to_bytes();
+        if let Some(padding) = self.padding {
+            data.resize(self.frame_len, padding);
+        }
+        self.io.send_frame(&CanFrame { id: self.id, data }).await
+    }
+}
+
+impl FunctionalTransport for FunctionalSender {
+    fn send<'a>(&'a self, payload: &'a [u8]) -> BoxFuture<'a, Result<(), UdsCommunicationError>> {
+        Box::pin(FunctionalSender::send(self, payload))
+    }
+}
+
+/// Client sending functionally addressed requests, see module documentation.
+///
+/// Spawns background receive task for each ECU, so it has to be created from within tokio
+/// runtime.
+pub struct FunctionalUdsClient {
+    sender: Box<dyn FunctionalTransport>,
+    responders: Vec<(u32, Dispatcher)>,
+    timing: Mutex<ResponseTiming>,
+}
+
+impl FunctionalUdsClient {
+    /// `ecus` contains pairs of physical request ID and response ID of each ECU
+    pub fn new(
+        canifc: &str,
+        functional_id: impl Into<Id>,
+        ecus: &[(Id, Id)],
+    ) -> Result<FunctionalUdsClient, UdsError> {
+        let sender = FunctionalSender::new(RawCanSocket::open(canifc)?, functional_id);
+        let mut responders: Vec<(u32, Box<dyn UdsTransport>)> = vec![];
+        for (request_id, response_id) in ecus {
+            let socket = UdsSocket::new(canifc, *response_id, *request_id)?;
+            responders.push((id_to_raw(*response_id), Box::new(socket)));
+        }
+        Ok(FunctionalUdsClient::new_from_transports(sender, responders))
+    }
+
+    /// Create client from custom transports. `sender` is used only for sending the functional
+    /// requests, `responders` only for receiving responses of the ECU with given address.
+    pub fn new_from_transports(
+        sender: impl FunctionalTransport + 'static,
+        responders: Vec<(u32, Box<dyn UdsTransport>)>,
+    ) -> FunctionalUdsClient {
+        let responders = responders
+            .into_iter()
+            .map(|(address, transport)| (address, Dispatcher::new(Arc::from(transport))))
+            .collect();
+        FunctionalUdsClient {
+            sender: Box::new(sender),
+            responders,
+            timing: Mutex::new(ResponseTiming::default()),
+        }
+    }
+
+    /// Currently used response timing, applies to each ECU separately
+    pub fn response_timing(&self) -> ResponseTiming {
+        *self.timing.lock().unwrap()
+    }
+
+    pub fn set_response_timing(&self, timing: ResponseTiming) {
+        *self.timing.lock().unwrap() = timing;
+    }
+
+    /// Send request and parse response of each ECU by provided parse function
+    pub(crate) async fn send_and_collect(
+        &self,
+        request: &[u8],
+        parse: impl Fn(&[u8]) -> EcuResponseResult,
+    ) -> Result<FunctionalResponses, UdsError> {
+        if request.is_empty() {
+            return Err(UdsError::RequestEmpty);
+        }
+        let timing = self.response_timing();
+        let p2 = timing.p2 + timing.network_margin;
+        let p2_star = timing.p2_star + timing.network_margin;
+        let mut locks = vec![];
+        let mut pending = vec![];
+        for (address, dispatcher) in &self.responders {
+            locks.push(dispatcher.lock().await);
+            dispatcher.drain_cancelled_request(&timing).await;
+            pending.push((*address, dispatcher.register(request)?));
+        }
+        self.sender.send(request).await?;
+
+        let responses = futures::future::join_all(pending.into_iter().map(
+            |(address, mut pending)| async move {
+                let response = collect_response(&mut pending, request[0], p2, p2_star).await;
+                pending.complete();
+                (address, response)
+            },
+        ))
+        .await;
+        let mut result = FunctionalResponses::new();
+        for (address, response) in responses {
+            match response {
+                Some(Ok(raw_response)) => {
+                    result.insert(address, parse(&raw_response));
+                }
+                Some(Err(e)) => {
+                    result.insert(address, Err(e));
+                }
+                None => trace!("ECU {:#x} did not respond", address),
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// Waits for the final response of single ECU. Returns None if the ECU did not respond or the
+/// response should be ignored.
+async fn collect_response(
+    pending: &mut PendingResponse,
+    sid: u8,
+    p2: Duration,
+    p2_star: Duration,
+) -> Option<Result<Vec<u8>, UdsError>> {
+    let mut timeout = p2;
+    let mut response_pending = false;
+    loop {
+        let raw_response = match tokio::time::timeout(timeout, pending.next()).await {
+            Ok(Ok(raw_response)) => raw_response,
+            Ok(Err(e)) => return Some(Err(e.into())),
+            Err(_) if response_pending => {
+                return Some(Err(UdsError::ResponseTimeout { sid, timeout }));
+            }
+            Err(_) => return None,
+        };
+        match parse_for_error(&raw_response) {
+            Ok(()) => return Some(Ok(raw_response)),
+            Err(UdsError::NRC { nrc }) => match nrc.nrc {
+                NegativeResponseCode::RequestCorrectlyReceivedResponsePending => {
+                    response_pending = true;
+                    timeout = p2_star;
+                }
+                NegativeResponseCode::ServiceNotSupported
+                | NegativeResponseCode::SubFunctionNotSupported
+                | NegativeResponseCode::RequestOutOfRange => {
+                    debug!("Ignoring NRC {:?} to functional request", nrc.nrc);
+                    return None;
+                }
+                _ => return Some(Err(UdsError::NRC { nrc })),
+            },
+            Err(e) => return Some(Err(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::uds::isotp::tests::channel_can_pair;
+    use crate::uds::{MockTransport, NrcData, StandardId, UdsResponse};
+
+    /// Functional request is received by all ECUs on the bus, each ECU mock responds to it by
+    /// its own expectations
+    pub(crate) struct MockBus(pub(crate) Vec<(u32, MockTransport)>);
+
+    impl FunctionalTransport for MockBus {
+        fn send<'a>(
+            &'a self,
+            payload: &'a [u8],
+        ) -> BoxFuture<'a, Result<(), UdsCommunicationError>> {
+            Box::pin(async move {
+                for (_, ecu) in &self.0 {
+                    UdsTransport::send(ecu, payload).await?;
+                }
+                Ok(())
+            })
+        }
+    }
+
+    impl MockBus {
+        pub(crate) fn client(&self) -> FunctionalUdsClient {
+            let responders = self
+                .0
+                .iter()
+                .map(|(address, ecu)| (*address, Box::new(ecu.clone()) as Box<dyn UdsTransport>))
+                .collect();
+            let bus = MockBus(self.0.clone());
+            let client = FunctionalUdsClient::new_from_transports(bus, responders);
+            client.set_response_timing(ResponseTiming {
+                p2: Duration::from_millis(50),
+                p2_star: Duration::from_millis(200),
+                network_margin: Duration::ZERO,
+            });
+            client
+        }
+
+        pub(crate) fn assert_done(&self) {
+            for (_, ecu) in &self.0 {
+                ecu.assert_done();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_collect_responses() {
+        let ecu_1 = MockTransport::new();
+        let ecu_2 = MockTransport::new();
+        let ecu_3 = MockTransport::new();
+        let ecu_4 = MockTransport::new();
+        ecu_1.expect(&[0x11, 0x01]).respond(&[0x51, 0x01]);
+        ecu_2
+            .expect(&[0x11, 0x01])
+            .respond_pending(0x11)
+            .respond_after(Duration::from_millis(100), &[0x7f, 0x11, 0x22]);
+        ecu_3.expect(&[0x11, 0x01]).respond_nrc(0x11, 0x12);
+        ecu_4.expect(&[0x11, 0x01]);
+        let bus = MockBus(vec![
+            (0x7e8, ecu_1),
+            (0x7e9, ecu_2),
+            (0x7ea, ecu_3),
+            (0x7eb, ecu_4),
+        ]);
+        let client = bus.client();
+
+        let result = client
+            .send_and_collect(&[0x11, 0x01], |raw| {
+                Ok(UdsResponse::ReadDataByIdentifier(
+                    crate::uds::DataFormat::Raw(raw.to_vec()),
+                ))
+            })
+            .await
+            .unwrap();
+        assert_eq!(result.len(), 2);
+        assert!(result[&0x7e8].is_ok());
+        assert_eq!(
+            result[&0x7e9],
+            Err(UdsError::NRC {
+                nrc: NrcData {
+                    rejected_sid: 0x11,
+                    nrc: NegativeResponseCode::ConditionsNotCorrect
+                }
+            })
+        );
+        bus.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_timeout_after_response_pending() {
+        let ecu = MockTransport::new();
+        ecu.expect(&[0x11, 0x01]).respond_pending(0x11);
+        let bus = MockBus(vec![(0x7e8, ecu)]);
+        let client = bus.client();
+
+        let result = client
+            .send_and_collect(&[0x11, 0x01], |_| Ok(UdsResponse::TesterPresent))
+            .await
+            .unwrap();
+        assert_eq!(
+            result[&0x7e8],
+            Err(UdsError::ResponseTimeout {
+                sid: 0x11,
+                timeout: Duration::from_millis(200)
+            })
+        );
+        bus.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_functional_sender() {
+        let (tester, ecu) = channel_can_pair();
+        let sender =
+            FunctionalSender::new(tester, StandardId::new(0x7df).unwrap()).with_padding(0xcc);
+
+        sender.send(&[0x3e, 0x00]).await.unwrap();
+        let frame = ecu.receive_frame().await.unwrap();
+        assert_eq!(frame.id, Id::Standard(StandardId::new(0x7df).unwrap()));
+        assert_eq!(
+            frame.data,
+            vec![0x02, 0x3e, 0x00, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc]
+        );
+
+        assert_eq!(
+            sender.send(&[0x22, 1, 2, 3, 4, 5, 6, 7]).await,
+            Err(UdsCommunicationError::InvalidFrame)
+        );
+    }
+}
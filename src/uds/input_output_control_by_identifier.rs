@@ -0,0 +1,285 @@
+//! # Implementation of InputOutputControlByIdentifier 0x2F service
+//!
+//! This module provides following methods for UdsClient:
+//!
+//! [UdsClient::input_output_control_by_identifier]
+//! [UdsClient::take_input_output_control]
+//!
+//! [UdsClient::take_input_output_control] returns [InputOutputControlGuard], which returns the
+//! control to the ECU when dropped, so the actuator is not left in the adjusted state when the test
+//! fails or is cancelled.
+//!
+use super::*;
+use crate::uds::stop_on_drop::{StopOnDrop, StopRequest};
+use crate::uds::uds_definitions::SEND_RECEIVE_SID_OFFSET;
+use futures::future::{BoxFuture, FutureExt};
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+
+const INPUT_OUTPUT_CONTROL_BY_IDENTIFIER_SID: u8 = 0x2F;
+
+#[derive(IntoPrimitive, TryFromPrimitive, Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum InputOutputControlParameter {
+    ReturnControlToEcu = 0,
+    ResetToDefault = 1,
+    FreezeCurrentState = 2,
+    /// The only parameter sending controlState
+    ShortTermAdjustment = 3,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct InputOutputControlResponse {
+    pub data_identifier: u16,
+    pub control_parameter: InputOutputControlParameter,
+    /// state of the input or output after the request, format is specific for the identifier
+    pub control_status_record: Vec<u8>,
+}
+
+/// Returned by [UdsClient::take_input_output_control]. Control of the identifier is returned to
+/// the ECU by [InputOutputControlGuard::return_control], or in background when the guard is
+/// dropped without the control being returned.
+///
+/// The background task is spawned on the runtime which took the control. Task spawned while the
+/// runtime is shutting down never runs, e.g. when the guard is dropped at the end of `main`,
+/// so [InputOutputControlGuard::return_control] should be awaited there.
+#[must_use = "control is returned to the ECU when the guard is dropped"]
+pub struct InputOutputControlGuard {
+    stop: StopOnDrop<ReturnControl>,
+}
+
+impl InputOutputControlGuard {
+    pub fn data_identifier(&self) -> u16 {
+        self.stop.request().data_identifier
+    }
+
+    /// Return control to the ECU and wait for the response
+    pub async fn return_control(mut self) -> EcuResponseResult {
+        self.stop.stop().await
+    }
+}
+
+struct ReturnControl {
+    data_identifier: u16,
+    control_enable_mask: Vec<u8>,
+}
+
+impl StopRequest for ReturnControl {
+    fn send<'a>(&'a self, client: &'a UdsClient) -> BoxFuture<'a, EcuResponseResult> {
+        client
+            .input_output_control_by_identifier(
+                self.data_identifier,
+                InputOutputControlParameter::ReturnControlToEcu,
+                &[],
+                &self.control_enable_mask,
+            )
+            .boxed()
+    }
+
+    fn describe(&self) -> String {
+        format!("Returning control of {:#x} to ECU", self.data_identifier)
+    }
+}
+
+impl UdsClient {
+    /// `control_state` is sent only with [InputOutputControlParameter::ShortTermAdjustment],
+    /// `control_enable_mask` only when not empty - for identifiers packing multiple inputs or
+    /// outputs.
+    pub async fn input_output_control_by_identifier(
+        &self,
+        data_identifier: u16,
+        control_parameter: InputOutputControlParameter,
+        control_state: &[u8],
+        control_enable_mask: &[u8],
+    ) -> EcuResponseResult {
+        let request = compose_input_output_control_request(
+            data_identifier,
+            control_parameter,
+            control_state,
+            control_enable_mask,
+        );
+        let raw_response = self.send_and_receive(&request).await?;
+        let response = parse_input_output_control_response(&raw_response)?;
+        if let UdsResponse::InputOutputControlByIdentifier(DataFormat::Parsed(control)) = &response
+        {
+            if control.data_identifier != data_identifier {
+                return Err(UdsError::DidMismatch {
+                    expected: data_identifier,
+                    received: control.data_identifier,
+                    raw_message: raw_response,
+                });
+            }
+        }
+        Ok(response)
+    }
+
+    /// Take control of the identifier by [InputOutputControlParameter::ShortTermAdjustment],
+    /// [InputOutputControlParameter::FreezeCurrentState] or
+    /// [InputOutputControlParameter::ResetToDefault]. Control is returned to the ECU when the
+    /// returned guard is dropped.
+    pub async fn take_input_output_control(
+        &self,
+        data_identifier: u16,
+        control_parameter: InputOutputControlParameter,
+        control_state: &[u8],
+        control_enable_mask: &[u8],
+    ) -> Result<InputOutputControlGuard, UdsError> {
+        if control_parameter == InputOutputControlParameter::ReturnControlToEcu {
+            error!("Control can not be taken by returnControlToECU");
+            return Err(UdsError::InvalidArgument);
+        }
+        self.input_output_control_by_identifier(
+            data_identifier,
+            control_parameter,
+            control_state,
+            control_enable_mask,
+        )
+        .await?;
+        Ok(InputOutputControlGuard {
+            stop: StopOnDrop::new(
+                self.clone(),
+                ReturnControl {
+                    data_identifier,
+                    control_enable_mask: control_enable_mask.to_vec(),
+                },
+            ),
+        })
+    }
+}
+
+fn compose_input_output_control_request(
+    data_identifier: u16,
+    control_parameter: InputOutputControlParameter,
+    control_state: &[u8],
+    control_enable_mask: &[u8],
+) -> Vec<u8> {
+    let mut request = vec![INPUT_OUTPUT_CONTROL_BY_IDENTIFIER_SID];
+    request.extend_from_slice(&data_identifier.to_be_bytes());
+    request.push(control_parameter.into());
+    if control_parameter == InputOutputControlParameter::ShortTermAdjustment {
+        request.extend_from_slice(control_state);
+    }
+    request.extend_from_slice(control_enable_mask);
+    request
+}
+
+pub(super) fn parse_input_output_control_response(raw_response: &[u8]) -> EcuResponseResult {
+    let mut response_iter = raw_response.iter();
+    let sid = *response_iter.next().ok_or(UdsError::ResponseEmpty)?;
+    if sid != INPUT_OUTPUT_CONTROL_BY_IDENTIFIER_SID + SEND_RECEIVE_SID_OFFSET {
+        return Err(UdsError::SidMismatch {
+            expected: INPUT_OUTPUT_CONTROL_BY_IDENTIFIER_SID + SEND_RECEIVE_SID_OFFSET,
+            received: sid,
+            raw_message: raw_response.to_vec(),
+        });
+    }
+    let [did_hi, did_lo, control_parameter, ..] = *response_iter.as_slice() else {
+        return Err(UdsError::InvalidLength {
+            raw_message: raw_response.to_vec(),
+        });
+    };
+    let control_parameter = InputOutputControlParameter::try_from_primitive(control_parameter)
+        .map_err(|_| UdsError::ResponseIncorrect {
+            raw_message: raw_response.to_vec(),
+        })?;
+    Ok(UdsResponse::InputOutputControlByIdentifier(
+        DataFormat::Parsed(InputOutputControlResponse {
+            data_identifier: u16::from_be_bytes([did_hi, did_lo]),
+            control_parameter,
+            control_status_record: raw_response[4..].to_vec(),
+        }),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compose_request() {
+        assert_eq!(
+            compose_input_output_control_request(
+                0x9b00,
+                InputOutputControlParameter::ShortTermAdjustment,
+                &[0x3c],
+                &[]
+            ),
+            vec![0x2f, 0x9b, 0x00, 0x03, 0x3c]
+        );
+        assert_eq!(
+            compose_input_output_control_request(
+                0x0155,
+                InputOutputControlParameter::FreezeCurrentState,
+                &[0x3c],
+                &[0x80]
+            ),
+            vec![0x2f, 0x01, 0x55, 0x02, 0x80]
+        );
+    }
+
+    #[test]
+    fn test_parse_response() {
+        assert_eq!(
+            parse_input_output_control_response(&[0x6f, 0x9b, 0x00, 0x03, 0x0c]),
+            Ok(UdsResponse::InputOutputControlByIdentifier(
+                DataFormat::Parsed(InputOutputControlResponse {
+                    data_identifier: 0x9b00,
+                    control_parameter: InputOutputControlParameter::ShortTermAdjustment,
+                    control_status_record: vec![0x0c],
+                })
+            ))
+        );
+        assert_eq!(
+            parse_input_output_control_response(&[0x6f, 0x9b, 0x00]),
+            Err(UdsError::InvalidLength {
+                raw_message: vec![0x6f, 0x9b, 0x00]
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_control_returned_when_guard_dropped() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x2f, 0x9b, 0x00, 0x03, 0x3c])
+            .respond(&[0x6f, 0x9b, 0x00, 0x03, 0x3c]);
+        mock.expect(&[0x2f, 0x9b, 0x00, 0x00])
+            .respond(&[0x6f, 0x9b, 0x00, 0x00, 0x0c]);
+        let client = UdsClient::new_from_transport(mock.clone());
+
+        let guard = client
+            .take_input_output_control(
+                0x9b00,
+                InputOutputControlParameter::ShortTermAdjustment,
+                &[0x3c],
+                &[],
+            )
+            .await
+            .unwrap();
+        drop(guard);
+        tokio::time::timeout(Duration::from_secs(1), mock.wait_for_expectations())
+            .await
+            .unwrap();
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_return_control() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x2f, 0x01, 0x55, 0x02, 0x80])
+            .respond(&[0x6f, 0x01, 0x55, 0x02]);
+        mock.expect(&[0x2f, 0x01, 0x55, 0x00, 0x80])
+            .respond(&[0x6f, 0x01, 0x55, 0x00]);
+        let client = UdsClient::new_from_transport(mock.clone());
+
+        let guard = client
+            .take_input_output_control(
+                0x0155,
+                InputOutputControlParameter::FreezeCurrentState,
+                &[],
+                &[0x80],
+            )
+            .await
+            .unwrap();
+        assert!(guard.return_control().await.is_ok());
+        mock.assert_done();
+    }
+}
@@ -0,0 +1,1412 @@
+//! # Userspace ISO-TP (ISO 15765-2) implementation
+//!
+//! Alternative to the kernel `can-isotp` module, for machines where the module (or the patch
+//! required by [UdsSocket]) is not available. Segmentation and reassembly is done in userspace
+//! over any source and sink of raw CAN frames implementing [CanFrameIo]. [RawCanSocket] provides
+//! such implementation over SocketCAN raw socket.
+//!
+//! [UserspaceIsoTp] implements [UdsTransport], it can be passed to
+//! [UdsClient::new_from_transport](crate::UdsClient::new_from_transport) directly, or used as the
+//! backend of [UdsSocket] trough [UdsSocket::new_userspace].
+//!
+//! Supported are single, first, consecutive and flow control frames including the escape
+//! sequences for CAN FD and messages longer than 4095 bytes, block size, STmin, padding, FC.WAIT
+//! and N_As, N_Bs and N_Cr timeouts.
+//!
+//! Frame parsing and reassembly ([IsoTpFrame], [IsoTpReassembler]) is independent on any I/O, so
+//! it can be used for decoding of recorded CAN traffic as well.
+use crate::uds::communication::{
+    ExtendedId, Id, StandardId, UdsCommunicationError, UdsSocket, UdsTransport,
+};
+use futures::future::BoxFuture;
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::unix::AsyncFd;
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+const CAN_EFF_FLAG: u32 = 0x8000_0000;
+const CAN_EFF_MASK: u32 = 0x1FFF_FFFF;
+const CAN_SFF_MASK: u32 = 0x0000_07FF;
+const CLASSIC_CAN_FRAME_LEN: usize = 8;
+/// Payload lengths of CAN FD frames longer than classic CAN frame
+const CAN_FD_FRAME_LENS: [usize; 7] = [12, 16, 20, 24, 32, 48, 64];
+/// Largest message length, which can be encoded in 12 bit first frame length
+const FIRST_FRAME_MAX_SHORT_LEN: usize = 0xFFF;
+
+/// Single CAN frame, data length is 0-8 for classic CAN and up to 64 for CAN FD
+#[derive(Debug, Clone, PartialEq)]
+pub struct CanFrame {
+    pub id: Id,
+    pub data: Vec<u8>,
+}
+
+/// Source and sink of raw CAN frames used by [UserspaceIsoTp]
+pub trait CanFrameIo: Send + Sync {
+    fn send_frame<'a>(
+        &'a self,
+        frame: &'a CanFrame,
+    ) -> BoxFuture<'a, Result<(), UdsCommunicationError>>;
+    fn receive_frame(&self) -> BoxFuture<'_, Result<CanFrame, UdsCommunicationError>>;
+}
+
+/// Raw CAN identifier as used by SocketCAN - extended identifiers have bit 31 set
+pub fn id_to_raw(id: Id) -> u32 {
+    match id {
+        Id::Standard(id) => id.as_raw() as u32,
+        Id::Extended(id) => id.as_raw() | CAN_EFF_FLAG,
+    }
+}
+
+/// Inverse of [id_to_raw]
+pub fn id_from_raw(raw: u32) -> Id {
+    if raw & CAN_EFF_FLAG != 0 {
+        Id::Extended(ExtendedId::new(raw & CAN_EFF_MASK).unwrap())
+    } else {
+        Id::Standard(StandardId::new((raw & CAN_SFF_MASK) as u16).unwrap())
+    }
+}
+
+#[derive(IntoPrimitive, TryFromPrimitive, Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum FlowStatus {
+    ContinueToSend = 0,
+    Wait = 1,
+    Overflow = 2,
+}
+
+/// Single ISO-TP protocol data unit
+#[derive(Debug, Clone, PartialEq)]
+pub enum IsoTpFrame {
+    Single {
+        data: Vec<u8>,
+    },
+    First {
+        message_len: usize,
+        data: Vec<u8>,
+    },
+    /// data can contain padding, it is cut off during reassembly
+    Consecutive {
+        sequence_number: u8,
+        data: Vec<u8>,
+    },
+    FlowControl {
+        flow_status: FlowStatus,
+        block_size: u8,
+        st_min: u8,
+    },
+}
+
+impl IsoTpFrame {
+    pub fn parse(raw_frame: &[u8]) -> Result<IsoTpFrame, UdsCommunicationError> {
+        let pci = *raw_frame
+            .first()
+            .ok_or(UdsCommunicationError::InvalidFrame)?;
+        let frame = match pci >> 4 {
+            0x0 => {
+                let (len, offset) = match pci & 0x0F {
+                    // CAN FD escape sequence
+                    0 => (
+                        *raw_frame
+                            .get(1)
+                            .ok_or(UdsCommunicationError::InvalidFrame)?
+                            as usize,
+                        2,
+                    ),
+                    len => (len as usize, 1),
+                };
+                if len == 0 {
+                    return Err(UdsCommunicationError::InvalidFrame);
+                }
+                let data = raw_frame
+                    .get(offset..offset + len)
+                    .ok_or(UdsCommunicationError::InvalidFrame)?;
+                IsoTpFrame::Single {
+                    data: data.to_vec(),
+                }
+            }
+            0x1 => {
+                let len_byte = *raw_frame
+                    .get(1)
+                    .ok_or(UdsCommunicationError::InvalidFrame)?;
+                let short_len = (((pci & 0x0F) as usize) << 8) + len_byte as usize;
+                let (message_len, offset) = if short_len == 0 {
+                    let long_len = raw_frame
+                        .get(2..6)
+                        .ok_or(UdsCommunicationError::InvalidFrame)?;
+                    let long_len =
+                        u32::from_be_bytes([long_len[0], long_len[1], long_len[2], long_len[3]]);
+                    (long_len as usize, 6)
+                } else {
+                    (short_len, 2)
+                };
+                IsoTpFrame::First {
+                    message_len,
+                    data: raw_frame[offset..].to_vec(),
+                }
+            }
+            0x2 => IsoTpFrame::Consecutive {
+                sequence_number: pci & 0x0F,
+                data: raw_frame[1..].to_vec(),
+            },
+            0x3 => {
+                if raw_frame.len() < 3 {
+                    return Err(UdsCommunicationError::InvalidFrame);
+                }
+                IsoTpFrame::FlowControl {
+                    flow_status: FlowStatus::try_from(pci & 0x0F)
+                        .map_err(|_| UdsCommunicationError::InvalidFrame)?,
+                    block_size: raw_frame[1],
+                    st_min: raw_frame[2],
+                }
+            }
+            _ => return Err(UdsCommunicationError::InvalidFrame),
+        };
+        Ok(frame)
+    }
+
+    /// Serialize frame without padding
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            IsoTpFrame::Single { data } => {
+                let mut frame = if data.len() < CLASSIC_CAN_FRAME_LEN {
+                    vec![data.len() as u8]
+                } else {
+                    vec![0x00, data.len() as u8]
+                };
+                frame.extend_from_slice(data);
+                frame
+            }
+            IsoTpFrame::First { message_len, data } => {
+                let mut frame = if *message_len <= FIRST_FRAME_MAX_SHORT_LEN {
+                    vec![0x10 | (*message_len >> 8) as u8, *message_len as u8]
+                } else {
+                    let mut frame = vec![0x10, 0x00];
+                    frame.extend_from_slice(&(*message_len as u32).to_be_bytes());
+                    frame
+                };
+                frame.extend_from_slice(data);
+                frame
+            }
+            IsoTpFrame::Consecutive {
+                sequence_number,
+                data,
+            } => {
+                let mut frame = vec![0x20 | (sequence_number & 0x0F)];
+                frame.extend_from_slice(data);
+                frame
+            }
+            IsoTpFrame::FlowControl {
+                flow_status,
+                block_size,
+                st_min,
+            } => vec![0x30 | *flow_status as u8, *block_size, *st_min],
+        }
+    }
+}
+
+/// Split message into frames fitting into CAN frames of `frame_len` bytes, which has to be 8 or
+/// one of CAN FD frame lengths. Sequence numbers of consecutive frames start at 1 and wrap around after 15.
+pub fn segment(payload: &[u8], frame_len: usize) -> Vec<IsoTpFrame> {
+    let single_frame_capacity = if frame_len <= CLASSIC_CAN_FRAME_LEN {
+        frame_len - 1
+    } else {
+        frame_len - 2
+    };
+    if payload.len() <= single_frame_capacity {
+        return vec![IsoTpFrame::Single {
+            data: payload.to_vec(),
+        }];
+    }
+    let first_frame_header = if payload.len() <= FIRST_FRAME_MAX_SHORT_LEN {
+        2
+    } else {
+        6
+    };
+    let (first, rest) = payload.split_at(frame_len - first_frame_header);
+    let mut frames = vec![IsoTpFrame::First {
+        message_len: payload.len(),
+        data: first.to_vec(),
+    }];
+    for (i, chunk) in rest.chunks(frame_len - 1).enumerate() {
+        frames.push(IsoTpFrame::Consecutive {
+            sequence_number: ((i + 1) % 16) as u8,
+            data: chunk.to_vec(),
+        });
+    }
+    frames
+}
+
+/// Decode STmin value from flow control frame
+pub fn st_min_to_duration(st_min: u8) -> Duration {
+    match st_min {
+        0x00..=0x7F => Duration::from_millis(st_min as u64),
+        0xF1..=0xF9 => Duration::from_micros((st_min - 0xF0) as u64 * 100),
+        // reserved values shall be interpreted as the longest STmin
+        _ => Duration::from_millis(0x7F),
+    }
+}
+
+/// Reassembles messages from received single, first and consecutive frames
+#[derive(Debug, Default)]
+pub struct IsoTpReassembler {
+    buffer: Vec<u8>,
+    message_len: usize,
+    next_sequence_number: u8,
+    in_progress: bool,
+}
+
+impl IsoTpReassembler {
+    /// Returns complete message, when the frame finished its reception.
+    /// Flow control frames are ignored.
+    pub fn push(&mut self, frame: &IsoTpFrame) -> Result<Option<Vec<u8>>, UdsCommunicationError> {
+        match frame {
+            IsoTpFrame::Single { data } => {
+                if self.in_progress {
+                    warn!("Single frame received during multi-frame reception, dropping reception");
+                    self.reset();
+                }
+                Ok(Some(data.clone()))
+            }
+            IsoTpFrame::First { message_len, data } => {
+                if self.in_progress {
+                    warn!("First frame received during multi-frame reception, restarting");
+                }
+                self.buffer = data.clone();
+                self.message_len = *message_len;
+                self.next_sequence_number = 1;
+                self.in_progress = true;
+                Ok(self.take_if_complete())
+            }
+            IsoTpFrame::Consecutive {
+                sequence_number,
+                data,
+            } => {
+                if !self.in_progress {
+                    debug!("Ignoring unexpected consecutive frame");
+                    return Ok(None);
+                }
+                if *sequence_number != self.next_sequence_number {
+                    warn!(
+                        "Wrong sequence number, expected {} received {}",
+                        self.next_sequence_number, sequence_number
+                    );
+                    self.reset();
+                    return Err(UdsCommunicationError::InvalidFrame);
+                }
+                self.next_sequence_number = (self.next_sequence_number + 1) % 16;
+                self.buffer.extend_from_slice(data);
+                Ok(self.take_if_complete())
+            }
+            IsoTpFrame::FlowControl { .. } => Ok(None),
+        }
+    }
+
+    pub fn in_progress(&self) -> bool {
+        self.in_progress
+    }
+
+    pub fn reset(&mut self) {
+        self.buffer.clear();
+        self.in_progress = false;
+    }
+
+    fn take_if_complete(&mut self) -> Option<Vec<u8>> {
+        if self.buffer.len() < self.message_len {
+            return None;
+        }
+        self.in_progress = false;
+        let mut message = std::mem::take(&mut self.buffer);
+        message.truncate(self.message_len);
+        Some(message)
+    }
+}
+
+/// Addressing, flow control and timing parameters of [UserspaceIsoTp]
+#[derive(Debug, Clone, PartialEq)]
+pub struct IsoTpConfig {
+    /// CAN identifier of received frames
+    pub rx_id: Id,
+    /// CAN identifier of transmitted frames
+    pub tx_id: Id,
+    /// Block size sent in our flow control frames, 0 means no further flow control
+    pub block_size: u8,
+    /// STmin sent in our flow control frames, encoded as defined by ISO 15765-2
+    pub st_min: u8,
+    /// When set, transmitted frames are padded to `frame_len` with this byte
+    pub padding: Option<u8>,
+    /// Length of CAN frame payload, 8 for classic CAN, 12, 16, 20, 24, 32, 48 or 64 for CAN FD
+    pub frame_len: usize,
+    /// Maximal number of FC.WAIT frames accepted in a row
+    pub max_wait_frames: u8,
+    /// Time for transmission of a frame
+    pub n_as: Duration,
+    /// Time until reception of the next flow control frame
+    pub n_bs: Duration,
+    /// Time until reception of the next consecutive frame
+    pub n_cr: Duration,
+}
+
+impl IsoTpConfig {
+    /// Same order of identifiers as in [UdsSocket::new] - src is the received identifier
+    pub fn new(src: impl Into<Id>, dst: impl Into<Id>) -> IsoTpConfig {
+        IsoTpConfig {
+            rx_id: src.into(),
+            tx_id: dst.into(),
+            block_size: 0,
+            st_min: 0,
+            padding: None,
+            frame_len: CLASSIC_CAN_FRAME_LEN,
+            max_wait_frames: 10,
+            n_as: Duration::from_millis(1000),
+            n_bs: Duration::from_millis(1000),
+            n_cr: Duration::from_millis(1000),
+        }
+    }
+}
+
+/// ISO-TP transport running in userspace over [CanFrameIo]
+///
+/// Background task reads all incoming frames, reassembles messages and answers first frames
+/// with flow control. Must be created inside of tokio runtime.
+pub struct UserspaceIsoTp {
+    config: IsoTpConfig,
+    io: Arc<dyn CanFrameIo>,
+    message_rx: Mutex<mpsc::UnboundedReceiver<Result<Vec<u8>, UdsCommunicationError>>>,
+    flow_control_rx: Mutex<mpsc::UnboundedReceiver<IsoTpFrame>>,
+    reader_task: JoinHandle<()>,
+}
+
+impl UserspaceIsoTp {
+    /// Fails with [UdsCommunicationError::InvalidArgument], if `config.frame_len` is not a valid
+    /// CAN frame length
+    pub fn new(
+        io: impl CanFrameIo + 'static,
+        config: IsoTpConfig,
+    ) -> Result<UserspaceIsoTp, UdsCommunicationError> {
+        if config.frame_len != CLASSIC_CAN_FRAME_LEN
+            && !CAN_FD_FRAME_LENS.contains(&config.frame_len)
+        {
+            error!("Invalid CAN frame length {}", config.frame_len);
+            return Err(UdsCommunicationError::InvalidArgument);
+        }
+        let io: Arc<dyn CanFrameIo> = Arc::new(io);
+        let (message_tx, message_rx) = mpsc::unbounded_channel();
+        let (flow_control_tx, flow_control_rx) = mpsc::unbounded_channel();
+        let reader_task = tokio::spawn(isotp_reader_task(
+            io.clone(),
+            config.clone(),
+            message_tx,
+            flow_control_tx,
+        ));
+        Ok(UserspaceIsoTp {
+            config,
+            io,
+            message_rx: Mutex::new(message_rx),
+            flow_control_rx: Mutex::new(flow_control_rx),
+            reader_task,
+        })
+    }
+
+    pub async fn send(&self, payload: &[u8]) -> Result<(), UdsCommunicationError> {
+        if payload.is_empty() {
+            return Err(UdsCommunicationError::InvalidFrame);
+        }
+        let mut flow_control_rx = self.flow_control_rx.lock().await;
+        // flow control frames received outside of transmission are not meant for us
+        while flow_control_rx.try_recv().is_ok() {}
+
+        let mut frames = segment(payload, self.config.frame_len)
+            .into_iter()
+            .peekable();
+        let first = frames.next().unwrap();
+        let single = matches!(first, IsoTpFrame::Single { .. });
+        send_isotp_frame(self.io.as_ref(), &self.config, &first).await?;
+        if single {
+            return Ok(());
+        }
+
+        loop {
+            let (block_size, st_min) = self.wait_for_flow_control(&mut flow_control_rx).await?;
+            let separation_time = st_min_to_duration(st_min);
+            let mut sent_in_block = 0;
+            while let Some(frame) = frames.next() {
+                if sent_in_block > 0 && !separation_time.is_zero() {
+                    tokio::time::sleep(separation_time).await;
+                }
+                send_isotp_frame(self.io.as_ref(), &self.config, &frame).await?;
+                sent_in_block += 1;
+                if frames.peek().is_none() {
+                    return Ok(());
+                }
+                if block_size != 0 && sent_in_block == block_size {
+                    break;
+                }
+            }
+        }
+    }
+
+    pub async fn receive(&self) -> Result<Vec<u8>, UdsCommunicationError> {
+        self.message_rx
+            .lock()
+            .await
+            .recv()
+            .await
+            .ok_or(UdsCommunicationError::ConnectionClosed)?
+    }
+
+    /// Returns block size and STmin of the received FC.CTS
+    async fn wait_for_flow_control(
+        &self,
+        flow_control_rx: &mut mpsc::UnboundedReceiver<IsoTpFrame>,
+    ) -> Result<(u8, u8), UdsCommunicationError> {
+        let mut wait_frames = 0;
+        loop {
+            let frame = tokio::time::timeout(self.config.n_bs, flow_control_rx.recv())
+                .await
+                .map_err(|_| {
+                    warn!("N_Bs timeout while waiting for flow control");
+                    UdsCommunicationError::Timeout
+                })?
+                .ok_or(UdsCommunicationError::ConnectionClosed)?;
+            if let IsoTpFrame::FlowControl {
+                flow_status,
+                block_size,
+                st_min,
+            } = frame
+            {
+                match flow_status {
+                    FlowStatus::ContinueToSend => return Ok((block_size, st_min)),
+                    FlowStatus::Wait => {
+                        wait_frames += 1;
+                        if wait_frames > self.config.max_wait_frames {
+                            warn!("Maximal number of FC.WAIT frames exceeded");
+                            return Err(UdsCommunicationError::Timeout);
+                        }
+                    }
+                    FlowStatus::Overflow => return Err(UdsCommunicationError::BufferOverflow),
+                }
+            }
+        }
+    }
+}
+
+impl Drop for UserspaceIsoTp {
+    fn drop(&mut self) {
+        self.reader_task.abort();
+    }
+}
+
+impl UdsTransport for UserspaceIsoTp {
+    fn send<'a>(&'a self, payload: &'a [u8]) -> BoxFuture<'a, Result<(), UdsCommunicationError>> {
+        Box::pin(UserspaceIsoTp::send(self, payload))
+    }
+
+    fn receive(&self) -> BoxFuture<'_, Result<Vec<u8>, UdsCommunicationError>> {
+        Box::pin(UserspaceIsoTp::receive(self))
+    }
+}
+
+async fn send_isotp_frame(
+    io: &dyn CanFrameIo,
+    config: &IsoTpConfig,
+    frame: &IsoTpFrame,
+) -> Result<(), UdsCommunicationError> {
+    let mut data = frame.to_bytes();
+    if let Some(padding) = config.padding {
+        data.resize(config.frame_len.max(data.len()), padding);
+    }
+    let frame = CanFrame {
+        id: config.tx_id,
+        data,
+    };
+    tokio::time::timeout(config.n_as, io.send_frame(&frame))
+        .await
+        .map_err(|_| {
+            warn!("N_As timeout while sending frame");
+            UdsCommunicationError::Timeout
+        })?
+}
+
+async fn isotp_reader_task(
+    io: Arc<dyn CanFrameIo>,
+    config: IsoTpConfig,
+    message_tx: mpsc::UnboundedSender<Result<Vec<u8>, UdsCommunicationError>>,
+    flow_control_tx: mpsc::UnboundedSender<IsoTpFrame>,
+) {
+    let mut reassembler = IsoTpReassembler::default();
+    let mut frames_in_block = 0;
+    let flow_control = IsoTpFrame::FlowControl {
+        flow_status: FlowStatus::ContinueToSend,
+        block_size: config.block_size,
+        st_min: config.st_min,
+    };
+    // N_Cr runs from the last frame of the message, frames with other ids do not restart it
+    let mut n_cr_deadline = Instant::now();
+    loop {
+        let received = if reassembler.in_progress() {
+            match tokio::time::timeout_at(n_cr_deadline, io.receive_frame()).await {
+                Ok(received) => received,
+                Err(_) => {
+                    warn!("N_Cr timeout, dropping incomplete message");
+                    reassembler.reset();
+                    let _ = message_tx.send(Err(UdsCommunicationError::Timeout));
+                    continue;
+                }
+            }
+        } else {
+            io.receive_frame().await
+        };
+        let can_frame = match received {
+            Ok(can_frame) => can_frame,
+            Err(e) => {
+                error!("Failed to receive CAN frame: {:?}", e);
+                let _ = message_tx.send(Err(e));
+                break;
+            }
+        };
+        if can_frame.id != config.rx_id {
+            continue;
+        }
+        n_cr_deadline = Instant::now() + config.n_cr;
+        let frame = match IsoTpFrame::parse(&can_frame.data) {
+            Ok(frame) => frame,
+            Err(_) => {
+                debug!("Ignoring invalid ISO-TP frame {:x?}", can_frame.data);
+                continue;
+            }
+        };
+        if let IsoTpFrame::FlowControl { .. } = frame {
+            let _ = flow_control_tx.send(frame);
+            continue;
+        }
+        match reassembler.push(&frame) {
+            Ok(Some(message)) => {
+                let _ = message_tx.send(Ok(message));
+            }
+            Ok(None) if reassembler.in_progress() => {
+                let send_flow_control = match frame {
+                    IsoTpFrame::First { .. } => {
+                        frames_in_block = 0;
+                        true
+                    }
+                    _ => {
+                        frames_in_block += 1;
+                        config.block_size != 0 && frames_in_block == config.block_size
+                    }
+                };
+                if send_flow_control {
+                    frames_in_block = 0;
+                    if let Err(e) = send_isotp_frame(io.as_ref(), &config, &flow_control).await {
+                        error!("Failed to send flow control: {:?}", e);
+                        reassembler.reset();
+                        let _ = message_tx.send(Err(e));
+                    }
+                }
+            }
+            Ok(None) => {}
+            Err(e) => {
+                let _ = message_tx.send(Err(e));
+            }
+        }
+    }
+}
+
+/// struct can_frame from linux/can.h
+#[repr(C)]
+struct RawCanFrame {
+    can_id: u32,
+    can_dlc: u8,
+    _pad: u8,
+    _res0: u8,
+    _res1: u8,
+    data: [u8; CLASSIC_CAN_FRAME_LEN],
+}
+
+/// struct sockaddr_can from linux/can.h
+#[repr(C)]
+struct CanAddr {
+    can_family: libc::sa_family_t,
+    can_ifindex: libc::c_int,
+    rx_id: u32,
+    tx_id: u32,
+    _reserved: [u8; 8],
+}
+
+/// Classic CAN raw socket, implementation of [CanFrameIo] for SocketCAN interfaces
+pub struct RawCanSocket {
+    fd: AsyncFd<OwnedFd>,
+}
+
+impl RawCanSocket {
+    pub fn open(ifname: &str) -> Result<RawCanSocket, UdsCommunicationError> {
+        let ifname = std::ffi::CString::new(ifname)
+            .map_err(|_| UdsCommunicationError::FailedToFindCanDevice)?;
+        let if_index = unsafe { libc::if_nametoindex(ifname.as_ptr()) };
+        if if_index == 0 {
+            return Err(UdsCommunicationError::FailedToFindCanDevice);
+        }
+        let raw_fd = unsafe {
+            libc::socket(
+                libc::PF_CAN,
+                libc::SOCK_RAW | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+                libc::CAN_RAW,
+            )
+        };
+        if raw_fd == -1 {
+            return Err(UdsCommunicationError::SocketCreationError);
+        }
+        // from now on the descriptor is closed on drop
+        let fd = unsafe { OwnedFd::from_raw_fd(raw_fd) };
+        let address = CanAddr {
+            can_family: libc::AF_CAN as libc::sa_family_t,
+            can_ifindex: if_index as libc::c_int,
+            rx_id: 0,
+            tx_id: 0,
+            _reserved: [0; 8],
+        };
+        let bind_result = unsafe {
+            libc::bind(
+                fd.as_raw_fd(),
+                &address as *const CanAddr as *const libc::sockaddr,
+                std::mem::size_of::<CanAddr>() as libc::socklen_t,
+            )
+        };
+        if bind_result == -1 {
+            return Err(UdsCommunicationError::SocketCreationError);
+        }
+        Ok(RawCanSocket {
+            fd: AsyncFd::new(fd)?,
+        })
+    }
+}
+
+impl CanFrameIo for RawCanSocket {
+    fn send_frame<'a>(
+        &'a self,
+        frame: &'a CanFrame,
+    ) -> BoxFuture<'a, Result<(), UdsCommunicationError>> {
+        Box::pin(async move {
+            if frame.data.len() > CLASSIC_CAN_FRAME_LEN {
+                return Err(UdsCommunicationError::InvalidFrame);
+            }
+            let mut raw_frame = RawCanFrame {
+                can_id: id_to_raw(frame.id),
+                can_dlc: frame.data.len() as u8,
+                _pad: 0,
+                _res0: 0,
+                _res1: 0,
+                data: [0; CLASSIC_CAN_FRAME_LEN],
+            };
+            raw_frame.data[..frame.data.len()].copy_from_slice(&frame.data);
+            loop {
+                let mut guard = self.fd.writable().await?;
+                let result = guard.try_io(|fd| {
+                    let written = unsafe {
+                        libc::write(
+                            fd.as_raw_fd(),
+                            &raw_frame as *const RawCanFrame as *const libc::c_void,
+                            std::mem::size_of::<RawCanFrame>(),
+                        )
+                    };
+                    if written == -1 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                    Ok(())
+                });
+                match result {
+                    Ok(written) => return Ok(written?),
+                    Err(_would_block) => continue,
+                }
+            }
+        })
+    }
+
+    fn receive_frame(&self) -> BoxFuture<'_, Result<CanFrame, UdsCommunicationError>> {
+        Box::pin(async move {
+            loop {
+                let mut guard = self.fd.readable().await?;
+                let result = guard.try_io(|fd| {
+                    let mut raw_frame = RawCanFrame {
+                        can_id: 0,
+                        can_dlc: 0,
+                        _pad: 0,
+                        _res0: 0,
+                        _res1: 0,
+                        data: [0; CLASSIC_CAN_FRAME_LEN],
+                    };
+                    let read = unsafe {
+                        libc::read(
+                            fd.as_raw_fd(),
+                            &mut raw_frame as *mut RawCanFrame as *mut libc::c_void,
+                            std::mem::size_of::<RawCanFrame>(),
+                        )
+                    };
+                    if read == -1 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                    Ok(raw_frame)
+                });
+                let raw_frame = match result {
+                    Ok(raw_frame) => raw_frame?,
+                    Err(_would_block) => continue,
+                };
+                // error and remote frames do not carry ISO-TP data
+                if raw_frame.can_id & (libc::CAN_ERR_FLAG | libc::CAN_RTR_FLAG) != 0 {
+                    continue;
+                }
+                let len = (raw_frame.can_dlc as usize).min(CLASSIC_CAN_FRAME_LEN);
+                return Ok(CanFrame {
+                    id: id_from_raw(raw_frame.can_id),
+                    data: raw_frame.data[..len].to_vec(),
+                });
+            }
+        })
+    }
+}
+
+impl UdsSocket {
+    /// Create socket using [UserspaceIsoTp] over [RawCanSocket] instead of kernel ISO-TP module
+    pub fn new_userspace(
+        ifname: &str,
+        src: impl Into<Id>,
+        dst: impl Into<Id>,
+    ) -> Result<UdsSocket, UdsCommunicationError> {
+        UdsSocket::new_userspace_with_config(ifname, IsoTpConfig::new(src, dst))
+    }
+
+    pub fn new_userspace_with_config(
+        ifname: &str,
+        config: IsoTpConfig,
+    ) -> Result<UdsSocket, UdsCommunicationError> {
+        let can_socket = RawCanSocket::open(ifname)?;
+        Ok(UdsSocket::new_from_userspace_isotp(UserspaceIsoTp::new(
+            can_socket, config,
+        )?))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// One end of in-memory CAN bus, frames sent on one end are received on the other
+    pub(crate) struct ChannelCanIo {
+        tx: mpsc::UnboundedSender<CanFrame>,
+        rx: Mutex<mpsc::UnboundedReceiver<CanFrame>>,
+    }
+
+    pub(crate) fn channel_can_pair() -> (ChannelCanIo, ChannelCanIo) {
+        let (a_tx, b_rx) = mpsc::unbounded_channel();
+        let (b_tx, a_rx) = mpsc::unbounded_channel();
+        (
+            ChannelCanIo {
+                tx: a_tx,
+                rx: Mutex::new(a_rx),
+            },
+            ChannelCanIo {
+                tx: b_tx,
+                rx: Mutex::new(b_rx),
+            },
+        )
+    }
+
+    impl CanFrameIo for ChannelCanIo {
+        fn send_frame<'a>(
+            &'a self,
+            frame: &'a CanFrame,
+        ) -> BoxFuture<'a, Result<(), UdsCommunicationError>> {
+            Box::pin(async move {
+                self.tx
+                    .send(frame.clone())
+                    .map_err(|_| UdsCommunicationError::ConnectionClosed)
+            })
+        }
+
+        fn receive_frame(&self) -> BoxFuture<'_, Result<CanFrame, UdsCommunicationError>> {
+            Box::pin(async move {
+                self.rx
+                    .lock()
+                    .await
+                    .recv()
+                    .await
+                    .ok_or(UdsCommunicationError::ConnectionClosed)
+            })
+        }
+    }
+
+    fn tester_id() -> Id {
+        Id::Standard(StandardId::new(0x7E0).unwrap())
+    }
+
+    fn ecu_id() -> Id {
+        Id::Standard(StandardId::new(0x7E8).unwrap())
+    }
+
+    fn test_config() -> IsoTpConfig {
+        let mut config = IsoTpConfig::new(ecu_id(), tester_id());
+        config.n_as = Duration::from_millis(100);
+        config.n_bs = Duration::from_millis(100);
+        config.n_cr = Duration::from_millis(100);
+        config
+    }
+
+    fn ecu_frame(data: &[u8]) -> CanFrame {
+        CanFrame {
+            id: ecu_id(),
+            data: data.to_vec(),
+        }
+    }
+
+    async fn next_frame(peer: &ChannelCanIo) -> Vec<u8> {
+        let frame = tokio::time::timeout(Duration::from_millis(500), peer.receive_frame())
+            .await
+            .expect("no frame received")
+            .unwrap();
+        assert_eq!(frame.id, tester_id());
+        frame.data
+    }
+
+    fn payload(len: usize) -> Vec<u8> {
+        (0..len).map(|i| i as u8).collect()
+    }
+
+    #[test]
+    fn test_id_raw_conversion() {
+        let extended = Id::Extended(ExtendedId::new(0x18DA_F110).unwrap());
+        assert_eq!(id_to_raw(extended), 0x98DA_F110);
+        assert_eq!(id_from_raw(0x98DA_F110), extended);
+        assert_eq!(id_from_raw(0x7E8), ecu_id());
+    }
+
+    #[test]
+    fn test_parse_single_frame() {
+        let frame = IsoTpFrame::parse(&[0x03, 0x22, 0xf1, 0x90, 0xaa, 0xaa, 0xaa, 0xaa]);
+        assert_eq!(
+            frame,
+            Ok(IsoTpFrame::Single {
+                data: vec![0x22, 0xf1, 0x90]
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_single_frame_escape() {
+        let mut raw = vec![0x00, 10];
+        raw.extend_from_slice(&payload(10));
+        assert_eq!(
+            IsoTpFrame::parse(&raw),
+            Ok(IsoTpFrame::Single { data: payload(10) })
+        );
+    }
+
+    #[test]
+    fn test_parse_single_frame_invalid() {
+        assert_eq!(
+            IsoTpFrame::parse(&[0x00]),
+            Err(UdsCommunicationError::InvalidFrame)
+        );
+        assert_eq!(
+            IsoTpFrame::parse(&[0x05, 0x01]),
+            Err(UdsCommunicationError::InvalidFrame)
+        );
+        assert_eq!(
+            IsoTpFrame::parse(&[]),
+            Err(UdsCommunicationError::InvalidFrame)
+        );
+    }
+
+    #[test]
+    fn test_parse_first_frame() {
+        let frame = IsoTpFrame::parse(&[0x10, 0x14, 0x62, 0xf1, 0x90, 0x01, 0x02, 0x03]);
+        assert_eq!(
+            frame,
+            Ok(IsoTpFrame::First {
+                message_len: 0x14,
+                data: vec![0x62, 0xf1, 0x90, 0x01, 0x02, 0x03]
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_first_frame_escape() {
+        let frame = IsoTpFrame::parse(&[0x10, 0x00, 0x00, 0x01, 0x00, 0x00, 0x36, 0x01]);
+        assert_eq!(
+            frame,
+            Ok(IsoTpFrame::First {
+                message_len: 0x10000,
+                data: vec![0x36, 0x01]
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_consecutive_frame() {
+        let frame = IsoTpFrame::parse(&[0x2f, 1, 2, 3]);
+        assert_eq!(
+            frame,
+            Ok(IsoTpFrame::Consecutive {
+                sequence_number: 0xf,
+                data: vec![1, 2, 3]
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_flow_control() {
+        assert_eq!(
+            IsoTpFrame::parse(&[0x30, 0x08, 0xf5]),
+            Ok(IsoTpFrame::FlowControl {
+                flow_status: FlowStatus::ContinueToSend,
+                block_size: 8,
+                st_min: 0xf5
+            })
+        );
+        assert_eq!(
+            IsoTpFrame::parse(&[0x34, 0x08, 0x00]),
+            Err(UdsCommunicationError::InvalidFrame)
+        );
+        assert_eq!(
+            IsoTpFrame::parse(&[0x30, 0x08]),
+            Err(UdsCommunicationError::InvalidFrame)
+        );
+    }
+
+    #[test]
+    fn test_parse_unknown_pci() {
+        assert_eq!(
+            IsoTpFrame::parse(&[0x40, 0x00]),
+            Err(UdsCommunicationError::InvalidFrame)
+        );
+    }
+
+    #[test]
+    fn test_frame_roundtrip() {
+        let frames = vec![
+            IsoTpFrame::Single { data: payload(7) },
+            IsoTpFrame::Single { data: payload(20) },
+            IsoTpFrame::First {
+                message_len: 4095,
+                data: payload(6),
+            },
+            IsoTpFrame::First {
+                message_len: 4096,
+                data: payload(2),
+            },
+            IsoTpFrame::Consecutive {
+                sequence_number: 0,
+                data: payload(7),
+            },
+            IsoTpFrame::FlowControl {
+                flow_status: FlowStatus::Wait,
+                block_size: 0,
+                st_min: 0x7f,
+            },
+        ];
+        for frame in frames {
+            assert_eq!(IsoTpFrame::parse(&frame.to_bytes()), Ok(frame));
+        }
+    }
+
+    #[test]
+    fn test_segment_single_frame() {
+        assert_eq!(
+            segment(&payload(7), 8),
+            vec![IsoTpFrame::Single { data: payload(7) }]
+        );
+    }
+
+    #[test]
+    fn test_segment_two_frames() {
+        let data = payload(8);
+        assert_eq!(
+            segment(&data, 8),
+            vec![
+                IsoTpFrame::First {
+                    message_len: 8,
+                    data: data[..6].to_vec()
+                },
+                IsoTpFrame::Consecutive {
+                    sequence_number: 1,
+                    data: data[6..].to_vec()
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_segment_sequence_number_wraps() {
+        let frames = segment(&payload(6 + 7 * 17), 8);
+        let sequence_numbers: Vec<u8> = frames
+            .iter()
+            .filter_map(|f| match f {
+                IsoTpFrame::Consecutive {
+                    sequence_number, ..
+                } => Some(*sequence_number),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            sequence_numbers,
+            vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 0, 1]
+        );
+    }
+
+    #[test]
+    fn test_segment_long_message_uses_escape() {
+        let frames = segment(&payload(5000), 8);
+        assert_eq!(
+            frames[0],
+            IsoTpFrame::First {
+                message_len: 5000,
+                data: payload(2)
+            }
+        );
+        assert_eq!(frames.len(), 1 + (5000 - 2_usize).div_ceil(7));
+    }
+
+    #[test]
+    fn test_segment_can_fd() {
+        assert_eq!(
+            segment(&payload(62), 64),
+            vec![IsoTpFrame::Single { data: payload(62) }]
+        );
+        assert_eq!(segment(&payload(63), 64).len(), 2);
+    }
+
+    #[test]
+    fn test_st_min_to_duration() {
+        assert_eq!(st_min_to_duration(0x00), Duration::ZERO);
+        assert_eq!(st_min_to_duration(0x7f), Duration::from_millis(127));
+        assert_eq!(st_min_to_duration(0xf1), Duration::from_micros(100));
+        assert_eq!(st_min_to_duration(0xf9), Duration::from_micros(900));
+        assert_eq!(st_min_to_duration(0x80), Duration::from_millis(127));
+        assert_eq!(st_min_to_duration(0xfa), Duration::from_millis(127));
+    }
+
+    #[test]
+    fn test_reassemble_segmented_message() {
+        let data = payload(300);
+        let mut reassembler = IsoTpReassembler::default();
+        let mut result = None;
+        for frame in segment(&data, 8) {
+            assert_eq!(result, None);
+            result = reassembler.push(&frame).unwrap();
+        }
+        assert_eq!(result, Some(data));
+        assert!(!reassembler.in_progress());
+    }
+
+    #[test]
+    fn test_reassemble_cuts_padding() {
+        let mut reassembler = IsoTpReassembler::default();
+        let first = IsoTpFrame::parse(&[0x10, 0x08, 1, 2, 3, 4, 5, 6]).unwrap();
+        let consecutive = IsoTpFrame::parse(&[0x21, 7, 8, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa]).unwrap();
+        assert_eq!(reassembler.push(&first), Ok(None));
+        assert_eq!(
+            reassembler.push(&consecutive),
+            Ok(Some(vec![1, 2, 3, 4, 5, 6, 7, 8]))
+        );
+    }
+
+    #[test]
+    fn test_reassemble_wrong_sequence_number() {
+        let mut reassembler = IsoTpReassembler::default();
+        let frames = segment(&payload(20), 8);
+        reassembler.push(&frames[0]).unwrap();
+        assert_eq!(
+            reassembler.push(&frames[2]),
+            Err(UdsCommunicationError::InvalidFrame)
+        );
+        assert!(!reassembler.in_progress());
+    }
+
+    #[test]
+    fn test_reassemble_ignores_unexpected_consecutive_frame() {
+        let mut reassembler = IsoTpReassembler::default();
+        let frame = IsoTpFrame::Consecutive {
+            sequence_number: 1,
+            data: payload(7),
+        };
+        assert_eq!(reassembler.push(&frame), Ok(None));
+    }
+
+    #[test]
+    fn test_reassemble_single_frame_aborts_reception() {
+        let mut reassembler = IsoTpReassembler::default();
+        let frames = segment(&payload(20), 8);
+        reassembler.push(&frames[0]).unwrap();
+        let single = IsoTpFrame::Single { data: vec![1] };
+        assert_eq!(reassembler.push(&single), Ok(Some(vec![1])));
+        assert!(!reassembler.in_progress());
+    }
+
+    #[tokio::test]
+    async fn test_send_single_frame_with_padding() {
+        let (io, peer) = channel_can_pair();
+        let mut config = test_config();
+        config.padding = Some(0xcc);
+        let isotp = UserspaceIsoTp::new(io, config).unwrap();
+        isotp.send(&[0x3e, 0x00]).await.unwrap();
+        assert_eq!(
+            next_frame(&peer).await,
+            vec![0x02, 0x3e, 0x00, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalid_frame_len_rejected() {
+        for frame_len in [0, 1, 2, 5, 7, 9, 13, 63, 65, 128] {
+            let (io, _peer) = channel_can_pair();
+            let mut config = test_config();
+            config.frame_len = frame_len;
+            assert!(
+                matches!(
+                    UserspaceIsoTp::new(io, config),
+                    Err(UdsCommunicationError::InvalidArgument)
+                ),
+                "frame length {} accepted",
+                frame_len
+            );
+        }
+        for frame_len in [8, 12, 16, 20, 24, 32, 48, 64] {
+            let (io, _peer) = channel_can_pair();
+            let mut config = test_config();
+            config.frame_len = frame_len;
+            assert!(UserspaceIsoTp::new(io, config).is_ok());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_send_empty_payload() {
+        let (io, _peer) = channel_can_pair();
+        let isotp = UserspaceIsoTp::new(io, test_config()).unwrap();
+        assert_eq!(
+            isotp.send(&[]).await,
+            Err(UdsCommunicationError::InvalidFrame)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_send_multi_frame_with_block_size() {
+        let (io, peer) = channel_can_pair();
+        let isotp = UserspaceIsoTp::new(io, test_config()).unwrap();
+        let data = payload(30);
+        let peer_task = tokio::spawn(async move {
+            let mut reassembler = IsoTpReassembler::default();
+            let first = IsoTpFrame::parse(&next_frame(&peer).await).unwrap();
+            assert!(matches!(
+                first,
+                IsoTpFrame::First {
+                    message_len: 30,
+                    ..
+                }
+            ));
+            reassembler.push(&first).unwrap();
+            let mut message = None;
+            while message.is_none() {
+                peer.send_frame(&ecu_frame(&[0x30, 2, 0])).await.unwrap();
+                for _ in 0..2 {
+                    let frame = IsoTpFrame::parse(&next_frame(&peer).await).unwrap();
+                    message = reassembler.push(&frame).unwrap();
+                    if message.is_some() {
+                        break;
+                    }
+                }
+            }
+            message.unwrap()
+        });
+        isotp.send(&data).await.unwrap();
+        assert_eq!(peer_task.await.unwrap(), data);
+    }
+
+    #[tokio::test]
+    async fn test_send_respects_st_min() {
+        let (io, peer) = channel_can_pair();
+        let isotp = UserspaceIsoTp::new(io, test_config()).unwrap();
+        let peer_task = tokio::spawn(async move {
+            next_frame(&peer).await;
+            peer.send_frame(&ecu_frame(&[0x30, 0, 10])).await.unwrap();
+            let start = Instant::now();
+            for _ in 0..3 {
+                next_frame(&peer).await;
+            }
+            start.elapsed()
+        });
+        isotp.send(&payload(6 + 7 * 3)).await.unwrap();
+        assert!(peer_task.await.unwrap() >= Duration::from_millis(20));
+    }
+
+    #[tokio::test]
+    async fn test_send_waits_on_fc_wait() {
+        let (io, peer) = channel_can_pair();
+        let isotp = UserspaceIsoTp::new(io, test_config()).unwrap();
+        let peer_task = tokio::spawn(async move {
+            next_frame(&peer).await;
+            peer.send_frame(&ecu_frame(&[0x31, 0, 0])).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            peer.send_frame(&ecu_frame(&[0x30, 0, 0])).await.unwrap();
+            next_frame(&peer).await
+        });
+        isotp.send(&payload(10)).await.unwrap();
+        assert_eq!(peer_task.await.unwrap()[0], 0x21);
+    }
+
+    #[tokio::test]
+    async fn test_send_too_many_fc_wait() {
+        let (io, peer) = channel_can_pair();
+        let mut config = test_config();
+        config.max_wait_frames = 1;
+        let isotp = UserspaceIsoTp::new(io, config).unwrap();
+        tokio::spawn(async move {
+            next_frame(&peer).await;
+            peer.send_frame(&ecu_frame(&[0x31, 0, 0])).await.unwrap();
+            peer.send_frame(&ecu_frame(&[0x31, 0, 0])).await.unwrap();
+            peer
+        });
+        assert_eq!(
+            isotp.send(&payload(10)).await,
+            Err(UdsCommunicationError::Timeout)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_send_overflow() {
+        let (io, peer) = channel_can_pair();
+        let isotp = UserspaceIsoTp::new(io, test_config()).unwrap();
+        tokio::spawn(async move {
+            next_frame(&peer).await;
+            peer.send_frame(&ecu_frame(&[0x32, 0, 0])).await.unwrap();
+            peer
+        });
+        assert_eq!(
+            isotp.send(&payload(10)).await,
+            Err(UdsCommunicationError::BufferOverflow)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_send_n_bs_timeout() {
+        let (io, _peer) = channel_can_pair();
+        let isotp = UserspaceIsoTp::new(io, test_config()).unwrap();
+        assert_eq!(
+            isotp.send(&payload(10)).await,
+            Err(UdsCommunicationError::Timeout)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_receive_single_frame_ignores_other_ids() {
+        let (io, peer) = channel_can_pair();
+        let isotp = UserspaceIsoTp::new(io, test_config()).unwrap();
+        let other = CanFrame {
+            id: Id::Standard(StandardId::new(0x123).unwrap()),
+            data: vec![0x01, 0x00],
+        };
+        peer.send_frame(&other).await.unwrap();
+        peer.send_frame(&ecu_frame(&[0x02, 0x7e, 0x00, 0x55, 0x55]))
+            .await
+            .unwrap();
+        assert_eq!(isotp.receive().await, Ok(vec![0x7e, 0x00]));
+    }
+
+    #[tokio::test]
+    async fn test_receive_multi_frame_with_block_size() {
+        let (io, peer) = channel_can_pair();
+        let mut config = test_config();
+        config.block_size = 2;
+        config.st_min = 5;
+        let isotp = UserspaceIsoTp::new(io, config).unwrap();
+        let data = payload(6 + 7 * 4);
+        let frames = segment(&data, 8);
+        peer.send_frame(&ecu_frame(&frames[0].to_bytes()))
+            .await
+            .unwrap();
+        for block in frames[1..].chunks(2) {
+            assert_eq!(next_frame(&peer).await, vec![0x30, 2, 5]);
+            for frame in block {
+                peer.send_frame(&ecu_frame(&frame.to_bytes()))
+                    .await
+                    .unwrap();
+            }
+        }
+        assert_eq!(isotp.receive().await, Ok(data));
+    }
+
+    #[tokio::test]
+    async fn test_receive_n_cr_timeout() {
+        let (io, peer) = channel_can_pair();
+        let isotp = UserspaceIsoTp::new(io, test_config()).unwrap();
+        let frames = segment(&payload(20), 8);
+        peer.send_frame(&ecu_frame(&frames[0].to_bytes()))
+            .await
+            .unwrap();
+        assert_eq!(isotp.receive().await, Err(UdsCommunicationError::Timeout));
+        // reception is restarted correctly after timeout
+        peer.send_frame(&ecu_frame(&[0x01, 0x51])).await.unwrap();
+        assert_eq!(isotp.receive().await, Ok(vec![0x51]));
+    }
+
+    #[tokio::test]
+    async fn test_receive_n_cr_timeout_with_other_ids_on_bus() {
+        let (io, peer) = channel_can_pair();
+        let isotp = UserspaceIsoTp::new(io, test_config()).unwrap();
+        let frames = segment(&payload(20), 8);
+        peer.send_frame(&ecu_frame(&frames[0].to_bytes()))
+            .await
+            .unwrap();
+        let other = CanFrame {
+            id: Id::Standard(StandardId::new(0x123).unwrap()),
+            data: vec![0x01, 0x00],
+        };
+        let busy_bus = async {
+            loop {
+                peer.send_frame(&other).await.unwrap();
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        };
+        let result = tokio::select! {
+            result = tokio::time::timeout(Duration::from_millis(500), isotp.receive()) => result,
+            _ = busy_bus => unreachable!(),
+        };
+        assert_eq!(result, Ok(Err(UdsCommunicationError::Timeout)));
+    }
+
+    #[tokio::test]
+    async fn test_receive_wrong_sequence_number() {
+        let (io, peer) = channel_can_pair();
+        let isotp = UserspaceIsoTp::new(io, test_config()).unwrap();
+        let frames = segment(&payload(20), 8);
+        peer.send_frame(&ecu_frame(&frames[0].to_bytes()))
+            .await
+            .unwrap();
+        peer.send_frame(&ecu_frame(&frames[2].to_bytes()))
+            .await
+            .unwrap();
+        assert_eq!(
+            isotp.receive().await,
+            Err(UdsCommunicationError::InvalidFrame)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_uds_socket_with_userspace_isotp() {
+        let (io, peer) = channel_can_pair();
+        let socket =
+            UdsSocket::new_from_userspace_isotp(UserspaceIsoTp::new(io, test_config()).unwrap());
+        socket.send(&[0x10, 0x03]).await.unwrap();
+        assert_eq!(next_frame(&peer).await, vec![0x02, 0x10, 0x03]);
+        peer.send_frame(&ecu_frame(&[0x06, 0x50, 0x03, 0x00, 0x32, 0x01, 0xf4]))
+            .await
+            .unwrap();
+        assert_eq!(
+            socket.receive().await,
+            Ok(vec![0x50, 0x03, 0x00, 0x32, 0x01, 0xf4])
+        );
+    }
+}
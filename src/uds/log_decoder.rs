@@ -0,0 +1,501 @@
+//! # Offline decoding of CAN logs
+//!
+//! Reads CAN traffic captured by `candump -l` or Vector CANalyzer/CANoe (ASC format), reassembles
+//! ISO-TP messages for each configured pair of request and response CAN IDs, pairs the requests
+//! with responses and decodes the responses by the same parse functions used by
+//! [UdsClient](crate::UdsClient).
+//!
+//! Supported log lines:
+//! ```text
+//! (1697443200.012345) can0 7E0#0322F19000000000
+//! (1697443200.012345) can0 18DA10F1##10322F190
+//!    1.012345 1  7E8             Rx   d 8 10 14 62 F1 90 57 41 5A
+//!    1.012345 1  18DAF110x       Rx   d 8 03 7F 22 31 00 00 00 00
+//! ```
+//! Remote, error and other frames are skipped, as are the ASC header lines except `base hex` /
+//! `base dec`.
+//!
+//! The result is a list of [TranscriptEntry], which can be printed as human readable line by
+//! [Display](std::fmt::Display) or as JSON object by [TranscriptEntry::to_json]. The binary
+//! `uds_log_decoder` does exactly that for a log file.
+//!
+use crate::uds::communication::{ExtendedId, Id, StandardId};
+use crate::uds::isotp::{id_to_raw, CanFrame, IsoTpFrame, IsoTpReassembler};
+use crate::uds::recording::{format_can_id, format_hex, format_timestamp, Direction, RecordedPdu};
+use crate::uds::uds_definitions::{
+    NegativeResponseCode, ServiceIdentifier, NEGATIVE_RESPONSE_SID, SEND_RECEIVE_SID_OFFSET,
+};
+use crate::uds::{
+    clear_diagnostic_information, communication_control, control_dtc_setting,
+    diagnostic_session_control, dynamically_define_data_identifier, ecu_reset,
+    input_output_control_by_identifier, parse_for_error, read_data_by_identifier,
+    read_data_by_periodic_identifier, read_dtc_information, read_memory_by_address,
+    read_scaling_data_by_identifier, request_download, request_upload, response_on_event,
+    routine_control, security_access, tester_present, transfer_data, write_data_by_identifier,
+    write_memory_by_address, EcuResponseResult, UdsError,
+};
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::BufRead;
+use std::time::Duration;
+
+/// Single CAN frame read from the log
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogFrame {
+    /// timestamp as written in the log, absolute for candump, relative for ASC usually
+    pub timestamp: Duration,
+    pub frame: CanFrame,
+}
+
+/// Decoded UDS message
+#[derive(Debug)]
+pub struct TranscriptEntry {
+    pub pdu: RecordedPdu,
+    /// service of the request, or of the request the response belongs to
+    pub service: Option<ServiceIdentifier>,
+    /// decoded response, None for requests
+    pub decoded: Option<EcuResponseResult>,
+}
+
+/// Parse line of `candump -l` log, None if the line is not a data frame
+pub fn parse_candump_line(line: &str) -> Option<LogFrame> {
+    let mut fields = line.split_whitespace();
+    let timestamp = fields
+        .next()?
+        .strip_prefix('(')?
+        .strip_suffix(')')
+        .and_then(parse_timestamp)?;
+    let _interface = fields.next()?;
+    let (raw_id, raw_data) = fields.next()?.split_once('#')?;
+    let raw_data = match raw_data.strip_prefix('#') {
+        // CAN FD frame, the data are preceded by single digit with flags
+        Some(fd_data) => fd_data.get(1..)?,
+        None if raw_data.starts_with('R') => return None,
+        None => raw_data,
+    };
+    let id_value = u32::from_str_radix(raw_id, 16).ok()?;
+    let id = if raw_id.len() > 3 {
+        Id::Extended(ExtendedId::new(id_value)?)
+    } else {
+        Id::Standard(StandardId::new(id_value as u16)?)
+    };
+    if !raw_data.is_ascii() || raw_data.len() % 2 == 1 {
+        return None;
+    }
+    let data = (0..raw_data.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&raw_data[i..i + 2], 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    Some(LogFrame {
+        timestamp,
+        frame: CanFrame { id, data },
+    })
+}
+
+/// Parse line of Vector ASC log with classic CAN frame, None if the line is not a data frame.
+/// `hex` is false when the log header contains `base dec`.
+pub fn parse_asc_line(line: &str, hex: bool) -> Option<LogFrame> {
+    let radix = if hex { 16 } else { 10 };
+    let mut fields = line.split_whitespace();
+    let timestamp = parse_timestamp(fields.next()?)?;
+    let _channel: u8 = fields.next()?.parse().ok()?;
+    let raw_id = fields.next()?;
+    let id = match raw_id.strip_suffix('x') {
+        Some(raw_id) => Id::Extended(ExtendedId::new(u32::from_str_radix(raw_id, radix).ok()?)?),
+        None => Id::Standard(StandardId::new(u16::from_str_radix(raw_id, radix).ok()?)?),
+    };
+    if !matches!(fields.next()?, "Rx" | "Tx") || fields.next()? != "d" {
+        return None;
+    }
+    let dlc = usize::from_str_radix(fields.next()?, radix).ok()?;
+    let data = fields
+        .take(dlc)
+        .map(|byte| u8::from_str_radix(byte, radix).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    if data.len() != dlc {
+        return None;
+    }
+    Some(LogFrame {
+        timestamp,
+        frame: CanFrame { id, data },
+    })
+}
+
+/// Read all data frames from candump or ASC log, the format is detected for each line
+pub fn read_can_log(reader: impl BufRead) -> Result<Vec<LogFrame>, std::io::Error> {
+    let mut frames = vec![];
+    let mut asc_hex = true;
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.starts_with('(') {
+            frames.extend(parse_candump_line(line));
+            continue;
+        }
+        match line.split_whitespace().collect::<Vec<_>>().as_slice() {
+            ["base", "hex", ..] => asc_hex = true,
+            ["base", "dec", ..] => asc_hex = false,
+            _ => match parse_asc_line(line, asc_hex) {
+                Some(frame) => frames.push(frame),
+                None => trace!("Skipping log line: {}", line),
+            },
+        }
+    }
+    Ok(frames)
+}
+
+/// Reassemble and decode UDS messages exchanged on provided pairs of (request ID, response ID).
+/// Frames with other IDs are skipped. Multiple pairs can share the request ID, e.g. functional
+/// request answered by several ECUs.
+pub fn decode_log(frames: &[LogFrame], id_pairs: &[(Id, Id)]) -> Vec<TranscriptEntry> {
+    let mut reassemblers: HashMap<u32, IsoTpReassembler> = HashMap::new();
+    // last request for each response ID
+    let mut pending_requests: HashMap<u32, Vec<u8>> = HashMap::new();
+    let mut transcript = vec![];
+    for log_frame in frames {
+        let raw_id = id_to_raw(log_frame.frame.id);
+        let responses_to: Vec<u32> = id_pairs
+            .iter()
+            .filter(|(request_id, _)| id_to_raw(*request_id) == raw_id)
+            .map(|(_, response_id)| id_to_raw(*response_id))
+            .collect();
+        let direction = if !responses_to.is_empty() {
+            Direction::Tx
+        } else if id_pairs
+            .iter()
+            .any(|(_, response_id)| id_to_raw(*response_id) == raw_id)
+        {
+            Direction::Rx
+        } else {
+            continue;
+        };
+        let message = match IsoTpFrame::parse(&log_frame.frame.data)
+            .and_then(|frame| reassemblers.entry(raw_id).or_default().push(&frame))
+        {
+            Ok(Some(message)) => message,
+            Ok(None) => continue,
+            Err(e) => {
+                warn!(
+                    "Dropping reception on {} at {}: {:?}",
+                    format_can_id(log_frame.frame.id),
+                    format_timestamp(log_frame.timestamp),
+                    e
+                );
+                continue;
+            }
+        };
+        let pdu = RecordedPdu {
+            timestamp: log_frame.timestamp,
+            direction,
+            can_id: log_frame.frame.id,
+            data: message,
+        };
+        let entry = match direction {
+            Direction::Tx => {
+                for response_id in responses_to {
+                    pending_requests.insert(response_id, pdu.data.clone());
+                }
+                TranscriptEntry {
+                    service: pdu.data.first().and_then(|sid| (*sid).try_into().ok()),
+                    decoded: None,
+                    pdu,
+                }
+            }
+            Direction::Rx => {
+                let request = pending_requests.get(&raw_id).map(Vec::as_slice);
+                let decoded = decode_response(request, &pdu.data);
+                let response_pending = matches!(
+                    pdu.data.as_slice(),
+                    [NEGATIVE_RESPONSE_SID, _, nrc]
+                        if *nrc == NegativeResponseCode::RequestCorrectlyReceivedResponsePending as u8
+                );
+                if !response_pending {
+                    pending_requests.remove(&raw_id);
+                }
+                TranscriptEntry {
+                    service: response_service(&pdu.data),
+                    decoded: Some(decoded),
+                    pdu,
+                }
+            }
+        };
+        transcript.push(entry);
+    }
+    transcript
+}
+
+/// Decode response by the parse function of its service. Request is used for services where
+/// the response can not be decoded without it, e.g. ReadDataByIdentifier with single DID.
+pub fn decode_response(request: Option<&[u8]>, response: &[u8]) -> EcuResponseResult {
+    parse_for_error(response)?;
+    let service = response_service(response).ok_or(UdsError::NotImplemented)?;
+    match service {
+        ServiceIdentifier::DiagnosticSessionControl => {
+            diagnostic_session_control::parse_diagnostic_session_control_response(response)
+        }
+        ServiceIdentifier::CommunicationControl => {
+            communication_control::parse_communication_control_response(response)
+        }
+        ServiceIdentifier::ControlDtcSettings => {
+            control_dtc_setting::parse_control_dtc_setting_response(response)
+        }
+        ServiceIdentifier::DynamicallyDefineDataIdentifier => {
+            dynamically_define_data_identifier::parse_dynamically_define_data_identifier_response(
+                response,
+            )
+        }
+        ServiceIdentifier::EcuReset => ecu_reset::parse_ecu_reset_response(response),
+        ServiceIdentifier::ClearDiagnosticInformation => {
+            clear_diagnostic_information::parse_clear_diagnostic_information_response(response)
+        }
+        ServiceIdentifier::ReadDtcInformation => {
+            read_dtc_information::parse_read_dtc_information_response(response)
+        }
+        ServiceIdentifier::ReadDataByIdentifier => match request {
+            Some([_, did_hi, did_lo]) => {
+                let did = ((*did_hi as u16) << 8) + *did_lo as u16;
+                read_data_by_identifier::parse_read_data_by_identifier_tuple_response(
+                    &[(did, u32::MAX)],
+                    response,
+                )
+            }
+            _ => read_data_by_identifier::parse_read_data_by_identifier_response(response),
+        },
+        ServiceIdentifier::ReadDataByPeriodicIdentifier => {
+            read_data_by_periodic_identifier::parse_read_data_by_periodic_identifier_response(
+                response,
+            )
+        }
+        ServiceIdentifier::ReadMemoryByAddress => read_memory_by_address::parse_response(response),
+        ServiceIdentifier::ReadScalingDataByIdentifier => {
+            read_scaling_data_by_identifier::parse_read_scaling_data_by_identifier_response(
+                response,
+            )
+        }
+        ServiceIdentifier::WriteDataByIdentifier => {
+            write_data_by_identifier::parse_write_data_by_identifier_response(response)
+        }
+        ServiceIdentifier::WriteMemoryByAddress => {
+            write_memory_by_address::parse_write_memory_by_address_response(response)
+        }
+        ServiceIdentifier::RequestDownload => {
+            request_download::parse_request_download_response(response)
+        }
+        ServiceIdentifier::RequestUpload => request_upload::parse_request_upload_response(response),
+        ServiceIdentifier::ResponseOnEvent => {
+            response_on_event::parse_response_on_event_response(response)
+        }
+        ServiceIdentifier::TransferData => transfer_data::parse_transfer_data_response(response),
+        ServiceIdentifier::RequestTransferExit => {
+            transfer_data::parse_request_transfer_exit_response(response)
+        }
+        ServiceIdentifier::InputOutputControlByIdentifier => {
+            input_output_control_by_identifier::parse_input_output_control_response(response)
+        }
+        ServiceIdentifier::RoutineControl => {
+            routine_control::parse_routine_control_response(response)
+        }
+        ServiceIdentifier::SecurityAccess => {
+            security_access::parse_security_access_response(response)
+        }
+        ServiceIdentifier::TesterPresent => tester_present::parse_tester_present_response(response),
+        _ => Err(UdsError::NotImplemented),
+    }
+}
+
+/// Service of the request the response belongs to
+fn response_service(response: &[u8]) -> Option<ServiceIdentifier> {
+    let sid = match response {
+        [NEGATIVE_RESPONSE_SID, rejected_sid, ..] => *rejected_sid,
+        [sid, ..] => sid.checked_sub(SEND_RECEIVE_SID_OFFSET)?,
+        [] => return None,
+    };
+    ServiceIdentifier::try_from(sid).ok()
+}
+
+/// Parse seconds with up to nanosecond resolution without rounding errors of f64
+fn parse_timestamp(raw: &str) -> Option<Duration> {
+    let (secs, fraction) = raw.split_once('.').unwrap_or((raw, ""));
+    if fraction.len() > 9 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let nanos = if fraction.is_empty() {
+        0
+    } else {
+        fraction.parse::<u32>().ok()? * 10u32.pow(9 - fraction.len() as u32)
+    };
+    Some(Duration::new(secs.parse().ok()?, nanos))
+}
+
+impl TranscriptEntry {
+    fn service_name(&self) -> String {
+        match self.service {
+            Some(service) => format!("{:?}", service),
+            None => "Unknown".to_string(),
+        }
+    }
+
+    /// Single line JSON object, decoded response is in `decoded` or `error` field
+    pub fn to_json(&self) -> String {
+        let mut json = format!(
+            "{{\"timestamp\":{},\"direction\":\"{}\",\"can_id\":\"{}\",\"data\":\"{}\",\"service\":\"{}\"",
+            format_timestamp(self.pdu.timestamp),
+            self.pdu.direction,
+            format_can_id(self.pdu.can_id),
+            format_hex(&self.pdu.data),
+            self.service_name()
+        );
+        match &self.decoded {
+            Some(Ok(response)) => {
+                json += &format!(
+                    ",\"decoded\":\"{}\"",
+                    json_escape(&format!("{:x?}", response))
+                )
+            }
+            Some(Err(e)) => json += &format!(",\"error\":\"{}\"", json_escape(&e.to_string())),
+            None => {}
+        }
+        json.push('}');
+        json
+    }
+}
+
+impl fmt::Display for TranscriptEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.pdu, self.service_name())?;
+        match &self.decoded {
+            Some(Ok(response)) => write!(f, " {:x?}", response),
+            Some(Err(e)) => write!(f, " error: {}", e),
+            None => Ok(()),
+        }
+    }
+}
+
+fn json_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uds::{DataFormat, UdsResponse};
+
+    fn standard(id: u16) -> Id {
+        Id::Standard(StandardId::new(id).unwrap())
+    }
+
+    #[test]
+    fn test_parse_candump_line() {
+        assert_eq!(
+            parse_candump_line("(1697443200.012345) can0 7E0#0322F190"),
+            Some(LogFrame {
+                timestamp: Duration::new(1697443200, 12_345_000),
+                frame: CanFrame {
+                    id: standard(0x7e0),
+                    data: vec![0x03, 0x22, 0xf1, 0x90],
+                },
+            })
+        );
+        let fd = parse_candump_line("(0.5) can0 18DA10F1##10322F190").unwrap();
+        assert_eq!(
+            fd.frame.id,
+            Id::Extended(ExtendedId::new(0x18da10f1).unwrap())
+        );
+        assert_eq!(fd.frame.data, vec![0x03, 0x22, 0xf1, 0x90]);
+        assert_eq!(parse_candump_line("(0.5) can0 7E0#R"), None);
+        assert_eq!(parse_candump_line("(0.5) can0 7E0#03é1"), None);
+    }
+
+    #[test]
+    fn test_read_asc_log() {
+        let log = "date Mon Oct 16 10:00:00.000 am 2023\n\
+                   base hex  timestamps absolute\n\
+                   Begin Triggerblock Mon Oct 16 10:00:00.000 am 2023\n\
+                   \x20  0.000000 Start of measurement\n\
+                   \x20  1.000100 1  7E0             Tx   d 8 03 22 F1 90 00 00 00 00  Length = 0\n\
+                   \x20  1.010000 1  18DAF110x       Rx   d 4 03 7F 22 31\n\
+                   \x20  1.020000 1  ErrorFrame\n\
+                   End TriggerBlock\n";
+        let frames = read_can_log(log.as_bytes()).unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].timestamp, Duration::from_micros(1_000_100));
+        assert_eq!(
+            frames[0].frame.data,
+            vec![0x03, 0x22, 0xf1, 0x90, 0, 0, 0, 0]
+        );
+        assert_eq!(
+            frames[1].frame.id,
+            Id::Extended(ExtendedId::new(0x18daf110).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_decode_multi_frame_conversation() {
+        let log = "(1.000000) can0 7E0#0322F190AAAAAAAA\n\
+                   (1.010000) can0 7E8#037F2278AAAAAAAA\n\
+                   (1.020000) can0 7E8#100862F190575A5A\n\
+                   (1.021000) can0 7E0#300000AAAAAAAAAA\n\
+                   (1.022000) can0 7E8#215A5AAAAAAAAAAA\n\
+                   (1.030000) can0 123#0102030405060708\n";
+        let frames = read_can_log(log.as_bytes()).unwrap();
+        let transcript = decode_log(&frames, &[(standard(0x7e0), standard(0x7e8))]);
+
+        assert_eq!(transcript.len(), 3);
+        assert_eq!(transcript[0].pdu.direction, Direction::Tx);
+        assert_eq!(transcript[0].pdu.data, vec![0x22, 0xf1, 0x90]);
+        assert_eq!(
+            transcript[0].service,
+            Some(ServiceIdentifier::ReadDataByIdentifier)
+        );
+        assert!(matches!(
+            transcript[1].decoded,
+            Some(Err(UdsError::NRC { .. }))
+        ));
+        assert_eq!(
+            transcript[2].pdu.data,
+            vec![0x62, 0xf1, 0x90, 0x57, 0x5a, 0x5a, 0x5a, 0x5a]
+        );
+        assert!(matches!(
+            transcript[2].decoded,
+            Some(Ok(UdsResponse::ReadDataByIdentifier(DataFormat::Parsed(_))))
+        ));
+        assert_eq!(
+            transcript[0].to_string(),
+            "1.000000 TX 7E0 22F190 ReadDataByIdentifier"
+        );
+    }
+
+    #[test]
+    fn test_json_output() {
+        let entry = TranscriptEntry {
+            pdu: RecordedPdu {
+                timestamp: Duration::from_millis(1500),
+                direction: Direction::Rx,
+                can_id: standard(0x7e8),
+                data: vec![0x7f, 0x11, 0x22],
+            },
+            service: Some(ServiceIdentifier::EcuReset),
+            decoded: Some(decode_response(None, &[0x7f, 0x11, 0x22])),
+        };
+        let json = entry.to_json();
+        assert!(json.starts_with(
+            "{\"timestamp\":1.500000,\"direction\":\"RX\",\"can_id\":\"7E8\",\"data\":\"7F1122\",\"service\":\"EcuReset\",\"error\":\""
+        ));
+        assert!(json.ends_with("\"}"));
+        assert_eq!(json_escape("a\"b\\c\n"), "a\\\"b\\\\c\\n");
+    }
+}
@@ -0,0 +1,352 @@
+//! # In-memory mock of the transport layer
+//!
+//! [MockTransport] implements [UdsTransport] without any underlying network, so the code using
+//! [UdsClient] can be tested in plain `cargo test`.
+//!
+//! Test declares expected requests in the order they should be sent, together with canned
+//! responses. Each response may be delayed to simulate slow ECU. When request not matching the
+//! next expectation is sent, it is recorded and error is returned to the client.
+//! [MockTransport::assert_done] then reports both unexpected requests and expectations which were
+//! never consumed.
+//!
+//! ```rust
+//! use uds_rs::{MockTransport, UdsClient};
+//!
+//! #[tokio::main(flavor = "current_thread")]
+//! async fn main() {
+//!     let mock = MockTransport::new();
+//!     mock.expect(&[0x22, 0xf1, 0x90])
+//!         .respond_pending(0x22)
+//!         .respond(&[0x62, 0xf1, 0x90, 0x41, 0x42]);
+//!     let client = UdsClient::new_from_transport(mock.clone());
+//!
+//!     let response = client.read_data_by_identifier(&[0xf190]).await;
+//!     assert!(response.is_ok());
+//!     mock.assert_done();
+//! }
+//! ```
+use crate::uds::communication::{UdsCommunicationError, UdsTransport};
+use crate::uds::functional::FunctionalTransport;
+use crate::uds::uds_definitions::NEGATIVE_RESPONSE_SID;
+use futures::future::BoxFuture;
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, Notify};
+
+/// Single response scheduled by the mock after matching request was received
+#[derive(Debug, Clone, PartialEq)]
+struct MockResponse {
+    delay: Duration,
+    data: Result<Vec<u8>, UdsCommunicationError>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Expectation {
+    request: Vec<u8>,
+    responses: Vec<MockResponse>,
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    /// expectations which were not yet matched by sent request
+    expectations: VecDeque<Expectation>,
+    /// total number of expectations ever declared, used for indexing by [ExpectationBuilder]
+    declared: usize,
+    unexpected_requests: Vec<Vec<u8>>,
+}
+
+/// Scriptable transport for unit-testing code using [UdsClient](crate::UdsClient).
+///
+/// Cloning the mock creates new handle to the same script, so one handle can be passed to the
+/// client and the other kept in the test for declaring expectations and final check.
+#[derive(Clone)]
+pub struct MockTransport {
+    state: Arc<Mutex<MockState>>,
+    response_tx: mpsc::UnboundedSender<MockResponse>,
+    response_rx: Arc<tokio::sync::Mutex<mpsc::UnboundedReceiver<MockResponse>>>,
+    /// notified whenever an expectation is matched
+    consumed: Arc<Notify>,
+}
+
+/// Returned by [MockTransport::expect], appends responses to the declared expectation
+pub struct ExpectationBuilder<'a> {
+    mock: &'a MockTransport,
+    index: usize,
+}
+
+impl Default for MockTransport {
+    fn default() -> Self {
+        MockTransport::new()
+    }
+}
+
+impl MockTransport {
+    pub fn new() -> MockTransport {
+        let (response_tx, response_rx) = mpsc::unbounded_channel();
+        MockTransport {
+            state: Arc::new(Mutex::new(MockState::default())),
+            response_tx,
+            response_rx: Arc::new(tokio::sync::Mutex::new(response_rx)),
+            consumed: Arc::new(Notify::new()),
+        }
+    }
+
+    /// Declare next expected request. Expectations are matched in order of declaration.
+    pub fn expect(&self, request: &[u8]) -> ExpectationBuilder<'_> {
+        let mut state = self.state.lock().unwrap();
+        state.expectations.push_back(Expectation {
+            request: request.to_vec(),
+            responses: vec![],
+        });
+        let index = state.declared;
+        state.declared += 1;
+        ExpectationBuilder { mock: self, index }
+    }
+
+    /// Requests which were already declared, but not sent by the client yet
+    pub fn unconsumed_expectations(&self) -> Vec<Vec<u8>> {
+        let state = self.state.lock().unwrap();
+        state
+            .expectations
+            .iter()
+            .map(|e| e.request.clone())
+            .collect()
+    }
+
+    /// Requests sent by the client, which did not match the next expectation
+    pub fn unexpected_requests(&self) -> Vec<Vec<u8>> {
+        self.state.lock().unwrap().unexpected_requests.clone()
+    }
+
+    /// Panics if any unexpected request was sent or if any expectation was not consumed
+    pub fn assert_done(&self) {
+        let unexpected = self.unexpected_requests();
+        let unconsumed = self.unconsumed_expectations();
+        if !unexpected.is_empty() || !unconsumed.is_empty() {
+            panic!(
+                "MockTransport script not fulfilled. Unexpected requests: {:x?}, unconsumed expectations: {:x?}",
+                unexpected, unconsumed
+            );
+        }
+    }
+
+    /// Wait until all declared expectations are matched, e.g. by requests sent from background
+    /// tasks. Never returns when the script is not followed, so wrap it in a timeout.
+    pub async fn wait_for_expectations(&self) {
+        loop {
+            let consumed = self.consumed.notified();
+            if self.state.lock().unwrap().expectations.is_empty() {
+                return;
+            }
+            consumed.await;
+        }
+    }
+
+    /// Deliver message to the client without any request, e.g. periodic data or event
+    pub fn push(&self, response: &[u8]) {
+        let _ = self.response_tx.send(MockResponse {
+            delay: Duration::ZERO,
+            data: Ok(response.to_vec()),
+        });
+    }
+
+    /// Fail the next receive with provided error, e.g. to simulate broken connection
+    pub fn push_error(&self, error: UdsCommunicationError) {
+        let _ = self.response_tx.send(MockResponse {
+            delay: Duration::ZERO,
+            data: Err(error),
+        });
+    }
+
+    fn add_response(&self, index: usize, response: MockResponse) {
+        let mut state = self.state.lock().unwrap();
+        let consumed = state.declared - state.expectations.len();
+        match index.checked_sub(consumed) {
+            Some(position) => state.expectations[position].responses.push(response),
+            None => {
+                // expectation was already matched, response can be served right away
+                let _ = self.response_tx.send(response);
+            }
+        }
+    }
+}
+
+impl ExpectationBuilder<'_> {
+    /// Respond immediately with provided data
+    pub fn respond(self, response: &[u8]) -> Self {
+        self.respond_after(Duration::ZERO, response)
+    }
+
+    /// Respond with provided data after delay. Delay is measured from the previous response of
+    /// this expectation, or from the request if this is the first one.
+    pub fn respond_after(self, delay: Duration, response: &[u8]) -> Self {
+        self.mock.add_response(
+            self.index,
+            MockResponse {
+                delay,
+                data: Ok(response.to_vec()),
+            },
+        );
+        self
+    }
+
+    /// Respond with negative response containing provided NRC
+    pub fn respond_nrc(self, rejected_sid: u8, nrc: u8) -> Self {
+        self.respond(&[NEGATIVE_RESPONSE_SID, rejected_sid, nrc])
+    }
+
+    /// Respond with NRC 0x78 RequestCorrectlyReceivedResponsePending
+    pub fn respond_pending(self, rejected_sid: u8) -> Self {
+        self.respond_nrc(rejected_sid, 0x78)
+    }
+
+    /// Respond with NRC 0x21 BusyRepeatRequest
+    pub fn respond_busy(self, rejected_sid: u8) -> Self {
+        self.respond_nrc(rejected_sid, 0x21)
+    }
+}
+
+impl UdsTransport for MockTransport {
+    fn send<'a>(&'a self, payload: &'a [u8]) -> BoxFuture<'a, Result<(), UdsCommunicationError>> {
+        Box::pin(async move {
+            let mut state = self.state.lock().unwrap();
+            match state.expectations.front() {
+                Some(expectation) if expectation.request == payload => {
+                    let expectation = state.expectations.pop_front().unwrap();
+                    for response in expectation.responses {
+                        let _ = self.response_tx.send(response);
+                    }
+                    self.consumed.notify_waiters();
+                    Ok(())
+                }
+                _ => {
+                    error!("MockTransport received unexpected request: {:x?}", payload);
+                    state.unexpected_requests.push(payload.to_vec());
+                    Err(UdsCommunicationError::GeneralError)
+                }
+            }
+        })
+    }
+
+    fn receive(&self) -> BoxFuture<'_, Result<Vec<u8>, UdsCommunicationError>> {
+        Box::pin(async move {
+            let mut response_rx = self.response_rx.lock().await;
+            let response = response_rx
+                .recv()
+                .await
+                .ok_or(UdsCommunicationError::GeneralError)?;
+            tokio::time::sleep(response.delay).await;
+            response.data
+        })
+    }
+}
+
+/// Mock used as sender of [FunctionalUdsClient](crate::FunctionalUdsClient) only checks the
+/// functional requests, responses of the ECUs are scripted by their own mocks
+impl FunctionalTransport for MockTransport {
+    fn send<'a>(&'a self, payload: &'a [u8]) -> BoxFuture<'a, Result<(), UdsCommunicationError>> {
+        UdsTransport::send(self, payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uds::{DataFormat, DataRecord, ReadDataByIdentifierResponse, UdsClient};
+    use crate::uds::{NegativeResponseCode, NrcData, UdsError, UdsResponse};
+
+    #[tokio::test]
+    async fn test_read_data_by_identifier_with_response_pending() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x22, 0xf1, 0x90])
+            .respond_pending(0x22)
+            .respond_after(Duration::from_millis(10), &[0x62, 0xf1, 0x90, 0x41]);
+        let client = UdsClient::new_from_transport(mock.clone());
+
+        let result = client.read_data_by_identifier(&[0xf190]).await;
+        let expected =
+            UdsResponse::ReadDataByIdentifier(DataFormat::Parsed(ReadDataByIdentifierResponse {
+                data_records: vec![DataRecord {
+                    data_identifier: 0xf190,
+                    data: vec![0x41],
+                }],
+            }));
+        assert_eq!(result, Ok(expected));
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_busy_repeat_request_resends() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x14, 0xff, 0xff, 0xff]).respond_busy(0x14);
+        mock.expect(&[0x14, 0xff, 0xff, 0xff]).respond(&[0x54]);
+        let client = UdsClient::new_from_transport(mock.clone());
+
+        let result = client.clear_diagnostic_information(0xffffff).await;
+        assert_eq!(result, Ok(UdsResponse::ClearDiagnosticInformation));
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_negative_response() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x19, 0x02, 0xff]).respond_nrc(0x19, 0x31);
+        let client = UdsClient::new_from_transport(mock.clone());
+
+        let result = client.report_dtc_by_status_mask(0xff).await;
+        assert_eq!(
+            result,
+            Err(UdsError::NRC {
+                nrc: NrcData {
+                    rejected_sid: 0x19,
+                    nrc: NegativeResponseCode::RequestOutOfRange
+                }
+            })
+        );
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_unexpected_request_is_reported() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x11, 0x01]).respond(&[0x51, 0x01]);
+        let client = UdsClient::new_from_transport(mock.clone());
+
+        let result = client.clear_diagnostic_information(0xffffff).await;
+        assert!(result.is_err());
+        assert_eq!(
+            mock.unexpected_requests(),
+            vec![vec![0x14, 0xff, 0xff, 0xff]]
+        );
+        assert_eq!(mock.unconsumed_expectations(), vec![vec![0x11, 0x01]]);
+    }
+
+    #[tokio::test]
+    async fn test_periodic_data_during_request_is_unsolicited() {
+        use futures::StreamExt;
+
+        let mock = MockTransport::new();
+        mock.expect(&[0x22, 0xf1, 0x90])
+            .respond_pending(0x22)
+            .respond(&[0x6a, 0x01, 0x11, 0x22])
+            .respond(&[0x62, 0xf1, 0x90, 0x41]);
+        let client = UdsClient::new_from_transport(mock.clone());
+        let mut unsolicited = client.unsolicited_responses();
+
+        let result = client.read_data_by_identifier(&[0xf190]).await;
+        assert!(result.is_ok());
+        assert_eq!(unsolicited.next().await, Some(vec![0x6a, 0x01, 0x11, 0x22]));
+        mock.assert_done();
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_assert_done_panics_on_unconsumed_expectation() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x11, 0x01]).respond(&[0x51, 0x01]);
+        mock.assert_done();
+    }
+}
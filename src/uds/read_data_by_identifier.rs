@@ -6,6 +6,10 @@
 //!
 //! [UdsClient::read_data_by_identifier]
 //!
+//! and following methods for FunctionalUdsClient:
+//!
+//! [FunctionalUdsClient::read_data_by_identifier]
+//!
 //! Problem with [UdsClient::read_data_by_identifier] is that there can be multiple identifiers and multiple
 //! responses in single message. Response needs apriori information about the size of each data_record.
 //!
@@ -43,8 +47,7 @@ impl UdsClient {
         }
         let request = compose_read_data_by_identifier_request(data_identifiers);
         let raw_response = self.send_and_receive(&request).await?;
-        let response = parse_read_data_by_identifier_response(&raw_response);
-        response
+        parse_read_data_by_identifier_response(&raw_response)
     }
     /// Method takes slice of tuples, first element stands for data identifier and second for
     /// data length. Do not like adding another Struct just for this.
@@ -59,15 +62,59 @@ impl UdsClient {
         }
         let request: Vec<u8> = compose_read_data_by_identifier_request(&data_identifiers);
         let response = self.send_and_receive(&request).await?;
-        let parsed_response =
-            parse_read_data_by_identifier_tuple_response(data_identifiers_and_lengths, &response);
-        return parsed_response;
+        parse_read_data_by_identifier_tuple_response(data_identifiers_and_lengths, &response)
     }
 
     async fn read_single_data_by_identifier(&self, data_identifier: u16) -> EcuResponseResult {
         self.read_data_by_identifier_tuple(&[(data_identifier, u32::MAX)])
             .await
     }
+
+    /// Read single data identifier and return its data record, used by the procedures which
+    /// interpret the data further
+    pub(super) async fn read_data_record(
+        &self,
+        data_identifier: u16,
+    ) -> Result<DataRecord, UdsError> {
+        let request = compose_read_data_by_identifier_request(&[data_identifier]);
+        let raw_response = self.send_and_receive(&request).await?;
+        expect_parsed(
+            &raw_response,
+            |raw_response| {
+                parse_read_data_by_identifier_tuple_response(
+                    &[(data_identifier, u32::MAX)],
+                    raw_response,
+                )
+            },
+            |response| match response {
+                UdsResponse::ReadDataByIdentifier(DataFormat::Parsed(mut response)) => {
+                    response.data_records.pop()
+                }
+                _ => None,
+            },
+        )
+    }
+}
+
+impl FunctionalUdsClient {
+    pub async fn read_data_by_identifier(
+        &self,
+        data_identifiers: &[u16],
+    ) -> Result<FunctionalResponses, UdsError> {
+        let request = compose_read_data_by_identifier_request(data_identifiers);
+        if let [data_identifier] = data_identifiers {
+            return self
+                .send_and_collect(&request, |raw_response| {
+                    parse_read_data_by_identifier_tuple_response(
+                        &[(*data_identifier, u32::MAX)],
+                        raw_response,
+                    )
+                })
+                .await;
+        }
+        self.send_and_collect(&request, parse_read_data_by_identifier_response)
+            .await
+    }
 }
 
 fn compose_read_data_by_identifier_request(data_identifiers: &[u16]) -> Vec<u8> {
@@ -78,10 +125,10 @@ fn compose_read_data_by_identifier_request(data_identifiers: &[u16]) -> Vec<u8>
         let lsb = i as u8;
         request.push(lsb);
     }
-    return request;
+    request
 }
 
-fn parse_read_data_by_identifier_response(raw_response: &[u8]) -> EcuResponseResult {
+pub(super) fn parse_read_data_by_identifier_response(raw_response: &[u8]) -> EcuResponseResult {
     let mut response_iter = raw_response.iter();
     let sid = *response_iter.next().ok_or(UdsError::ResponseEmpty)?;
     if sid != READ_DATA_BY_IDENTIFIER_SID + SEND_RECEIVE_SID_OFFSET {
@@ -97,7 +144,7 @@ fn parse_read_data_by_identifier_response(raw_response: &[u8]) -> EcuResponseRes
 
 /// When u32::MAX is passed as data len, it reads the whole message - should be used only with single
 /// data identifier.
-fn parse_read_data_by_identifier_tuple_response(
+pub(super) fn parse_read_data_by_identifier_tuple_response(
     data_identifiers_and_lengths: &[(u16, u32)],
     raw_response: &[u8],
 ) -> EcuResponseResult {
@@ -162,7 +209,7 @@ fn parse_read_data_by_identifier_tuple_response(
 
     let ret =
         UdsResponse::ReadDataByIdentifier(DataFormat::Parsed(read_data_by_identifier_response));
-    return Ok(ret);
+    Ok(ret)
 }
 #[cfg(test)]
 mod tests {
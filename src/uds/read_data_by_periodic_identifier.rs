@@ -0,0 +1,463 @@
+//! # Implementation of ReadDataByPeriodicIdentifier 0x2A service
+//!
+//! This module provides following methods for UdsClient:
+//!
+//! [UdsClient::read_data_by_periodic_identifier]
+//! [UdsClient::subscribe_periodic_data]
+//! [UdsClient::subscribe_periodic_data_uudt]
+//!
+//! Periodic identifier is the low byte of the data identifier 0xF2XX. After the positive response
+//! the server sends the data periodically without any further request, either on the diagnostic
+//! channel as 0x6A message with the periodic identifier, or as single CAN frame starting with the
+//! periodic identifier on separate CAN ID (UUDT).
+//!
+//! [PeriodicDataStream] returned by the subscribe methods yields [PeriodicData] of the subscribed
+//! identifiers and stops the sending when dropped. Stopping would end also other streams of the
+//! same identifier, so each identifier can be subscribed by single live stream of the client.
+//!
+use super::*;
+use crate::uds::dispatcher::{
+    is_persistent_error, MAX_CONSECUTIVE_RECEIVE_ERRORS, RECEIVE_ERROR_BACKOFF,
+};
+use crate::uds::isotp::CanFrameIo;
+use crate::uds::stop_on_drop::{StopOnDrop, StopRequest};
+use crate::uds::uds_definitions::SEND_RECEIVE_SID_OFFSET;
+use futures::future::{BoxFuture, FutureExt};
+use futures::stream::{BoxStream, Stream, StreamExt};
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+const READ_DATA_BY_PERIODIC_IDENTIFIER_SID: u8 = 0x2A;
+/// SID of the positive response as well as of the periodic data
+const PERIODIC_DATA_SID: u8 = READ_DATA_BY_PERIODIC_IDENTIFIER_SID + SEND_RECEIVE_SID_OFFSET;
+
+#[derive(IntoPrimitive, TryFromPrimitive, Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum TransmissionMode {
+    SendAtSlowRate = 1,
+    SendAtMediumRate = 2,
+    SendAtFastRate = 3,
+    /// Stops sending of the listed periodic identifiers, or all of them if none is listed
+    StopSending = 4,
+}
+
+/// Positive response to the request has no periodic identifier, periodic data message sent on
+/// the diagnostic channel has one
+#[derive(Debug, PartialEq)]
+pub struct ReadDataByPeriodicIdentifierResponse {
+    pub periodic_identifier: Option<u8>,
+    pub data: Vec<u8>,
+}
+
+/// Item of [PeriodicDataStream]
+#[derive(Debug, PartialEq)]
+pub struct PeriodicData {
+    pub periodic_identifier: u8,
+    pub data: Vec<u8>,
+    /// time of reception
+    pub timestamp: Instant,
+}
+
+/// Stream of periodic data of the subscribed identifiers. Sending of the identifiers is stopped by
+/// [PeriodicDataStream::stop], or in background when the stream is dropped without successful
+/// stop.
+#[must_use = "sending of the periodic data is stopped when the stream is dropped"]
+pub struct PeriodicDataStream {
+    inner: BoxStream<'static, PeriodicData>,
+    stop: StopOnDrop<StopPeriodicData>,
+}
+
+impl PeriodicDataStream {
+    pub fn periodic_identifiers(&self) -> &[u8] {
+        &self.stop.request().periodic_identifiers
+    }
+
+    /// Stop sending of the subscribed identifiers and wait for the response
+    pub async fn stop(mut self) -> EcuResponseResult {
+        self.stop.stop().await
+    }
+}
+
+impl Stream for PeriodicDataStream {
+    type Item = PeriodicData;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<PeriodicData>> {
+        self.inner.as_mut().poll_next(cx)
+    }
+}
+
+struct StopPeriodicData {
+    periodic_identifiers: Vec<u8>,
+}
+
+impl StopRequest for StopPeriodicData {
+    fn send<'a>(&'a self, client: &'a UdsClient) -> BoxFuture<'a, EcuResponseResult> {
+        client
+            .read_data_by_periodic_identifier(
+                TransmissionMode::StopSending,
+                &self.periodic_identifiers,
+            )
+            .boxed()
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "Stopping periodic identifiers {:x?}",
+            self.periodic_identifiers
+        )
+    }
+
+    fn finished(&self, client: &UdsClient) {
+        // released only now, so the stop does not end new subscription of the identifiers
+        client.release_periodic_identifiers(&self.periodic_identifiers);
+    }
+}
+
+impl UdsClient {
+    /// Periodic data are published as unsolicited responses, see [UdsClient::subscribe_periodic_data]
+    /// for filtered stream
+    pub async fn read_data_by_periodic_identifier(
+        &self,
+        transmission_mode: TransmissionMode,
+        periodic_identifiers: &[u8],
+    ) -> EcuResponseResult {
+        let request = compose_read_data_by_periodic_identifier_request(
+            transmission_mode,
+            periodic_identifiers,
+        )?;
+        let raw_response = self.send_and_receive(&request).await?;
+        parse_read_data_by_periodic_identifier_response(&raw_response)
+    }
+
+    /// Request sending of the periodic identifiers and return stream of their data received on
+    /// the diagnostic channel. Identifiers subscribed by other live stream are rejected by
+    /// [UdsError::InvalidArgument].
+    pub async fn subscribe_periodic_data(
+        &self,
+        transmission_mode: TransmissionMode,
+        periodic_identifiers: &[u8],
+    ) -> Result<PeriodicDataStream, UdsError> {
+        // subscribed before the request, so the first data can not be missed
+        let subscribed = periodic_identifiers.to_vec();
+        let inner = self
+            .dispatcher
+            .subscribe_timestamped()
+            .filter_map(move |(timestamp, response)| {
+                let data = match response.as_slice() {
+                    [PERIODIC_DATA_SID, periodic_identifier, data @ ..]
+                        if subscribed.contains(periodic_identifier) =>
+                    {
+                        Some(PeriodicData {
+                            periodic_identifier: *periodic_identifier,
+                            data: data.to_vec(),
+                            timestamp,
+                        })
+                    }
+                    _ => None,
+                };
+                futures::future::ready(data)
+            })
+            .boxed();
+        self.start_periodic_data(transmission_mode, periodic_identifiers, inner)
+            .await
+    }
+
+    /// Same as [UdsClient::subscribe_periodic_data], but the data are received by `io` as single
+    /// frames on `periodic_id`, each starting with the periodic identifier
+    pub async fn subscribe_periodic_data_uudt(
+        &self,
+        transmission_mode: TransmissionMode,
+        periodic_identifiers: &[u8],
+        io: impl CanFrameIo + 'static,
+        periodic_id: impl Into<Id>,
+    ) -> Result<PeriodicDataStream, UdsError> {
+        let subscribed = periodic_identifiers.to_vec();
+        let periodic_id = periodic_id.into();
+        let inner = futures::stream::unfold(io, move |io| {
+            let subscribed = subscribed.clone();
+            async move {
+                let mut consecutive_errors = 0;
+                loop {
+                    let frame = match io.receive_frame().await {
+                        Ok(frame) => frame,
+                        Err(e) if is_persistent_error(&e) => {
+                            error!("Receiving of periodic data stopped by {:?}", e);
+                            return None;
+                        }
+                        Err(e) => {
+                            warn!("Receiving of periodic data failed: {:?}", e);
+                            consecutive_errors += 1;
+                            if consecutive_errors >= MAX_CONSECUTIVE_RECEIVE_ERRORS {
+                                error!(
+                                    "Receiving of periodic data failed {} times in a row",
+                                    consecutive_errors
+                                );
+                                return None;
+                            }
+                            tokio::time::sleep(RECEIVE_ERROR_BACKOFF * consecutive_errors).await;
+                            continue;
+                        }
+                    };
+                    consecutive_errors = 0;
+                    match frame.data.as_slice() {
+                        [periodic_identifier, data @ ..]
+                            if frame.id == periodic_id
+                                && subscribed.contains(periodic_identifier) =>
+                        {
+                            let periodic_data = PeriodicData {
+                                periodic_identifier: *periodic_identifier,
+                                data: data.to_vec(),
+                                timestamp: Instant::now(),
+                            };
+                            return Some((periodic_data, io));
+                        }
+                        _ => trace!("Ignoring frame {:x?}", frame),
+                    }
+                }
+            }
+        })
+        .boxed();
+        self.start_periodic_data(transmission_mode, periodic_identifiers, inner)
+            .await
+    }
+
+    async fn start_periodic_data(
+        &self,
+        transmission_mode: TransmissionMode,
+        periodic_identifiers: &[u8],
+        inner: BoxStream<'static, PeriodicData>,
+    ) -> Result<PeriodicDataStream, UdsError> {
+        if transmission_mode == TransmissionMode::StopSending || periodic_identifiers.is_empty() {
+            error!("Periodic data can be subscribed only by sending mode and identifiers");
+            return Err(UdsError::InvalidArgument);
+        }
+        {
+            let mut active = self.periodic_identifiers.lock().unwrap();
+            if let Some(periodic_identifier) =
+                periodic_identifiers.iter().find(|id| active.contains(id))
+            {
+                error!(
+                    "Periodic identifier {:#x} is already subscribed by other stream",
+                    periodic_identifier
+                );
+                return Err(UdsError::InvalidArgument);
+            }
+            active.extend(periodic_identifiers);
+        }
+        // created before the request, so the identifiers are released also when it fails
+        let mut stream = PeriodicDataStream {
+            inner,
+            stop: StopOnDrop::new_stopped(
+                self.clone(),
+                StopPeriodicData {
+                    periodic_identifiers: periodic_identifiers.to_vec(),
+                },
+            ),
+        };
+        self.read_data_by_periodic_identifier(transmission_mode, periodic_identifiers)
+            .await?;
+        stream.stop.mark_started();
+        Ok(stream)
+    }
+
+    fn release_periodic_identifiers(&self, periodic_identifiers: &[u8]) {
+        let mut active = self.periodic_identifiers.lock().unwrap();
+        for periodic_identifier in periodic_identifiers {
+            active.remove(periodic_identifier);
+        }
+    }
+}
+
+fn compose_read_data_by_periodic_identifier_request(
+    transmission_mode: TransmissionMode,
+    periodic_identifiers: &[u8],
+) -> Result<Vec<u8>, UdsError> {
+    if transmission_mode != TransmissionMode::StopSending && periodic_identifiers.is_empty() {
+        error!("At least one periodic identifier has to be requested");
+        return Err(UdsError::InvalidArgument);
+    }
+    let mut request = vec![
+        READ_DATA_BY_PERIODIC_IDENTIFIER_SID,
+        transmission_mode.into(),
+    ];
+    request.extend_from_slice(periodic_identifiers);
+    Ok(request)
+}
+
+pub(super) fn parse_read_data_by_periodic_identifier_response(
+    raw_response: &[u8],
+) -> EcuResponseResult {
+    let mut response_iter = raw_response.iter();
+    let sid = *response_iter.next().ok_or(UdsError::ResponseEmpty)?;
+    if sid != PERIODIC_DATA_SID {
+        return Err(UdsError::SidMismatch {
+            expected: PERIODIC_DATA_SID,
+            received: sid,
+            raw_message: raw_response.to_vec(),
+        });
+    }
+    Ok(UdsResponse::ReadDataByPeriodicIdentifier(
+        DataFormat::Parsed(ReadDataByPeriodicIdentifierResponse {
+            periodic_identifier: response_iter.next().copied(),
+            data: response_iter.copied().collect(),
+        }),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uds::isotp::tests::channel_can_pair;
+    use crate::uds::isotp::CanFrame;
+    use futures::future::BoxFuture;
+
+    #[test]
+    fn test_compose_request() {
+        assert_eq!(
+            compose_read_data_by_periodic_identifier_request(
+                TransmissionMode::SendAtFastRate,
+                &[0x01, 0x02]
+            ),
+            Ok(vec![0x2a, 0x03, 0x01, 0x02])
+        );
+        assert_eq!(
+            compose_read_data_by_periodic_identifier_request(TransmissionMode::StopSending, &[]),
+            Ok(vec![0x2a, 0x04])
+        );
+        assert_eq!(
+            compose_read_data_by_periodic_identifier_request(TransmissionMode::SendAtSlowRate, &[]),
+            Err(UdsError::InvalidArgument)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_periodic_data() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x2a, 0x02, 0x01, 0x02]).respond(&[0x6a]);
+        mock.expect(&[0x2a, 0x04, 0x01, 0x02]).respond(&[0x6a]);
+        let client = UdsClient::new_from_transport(mock.clone());
+
+        let mut stream = client
+            .subscribe_periodic_data(TransmissionMode::SendAtMediumRate, &[0x01, 0x02])
+            .await
+            .unwrap();
+        mock.push(&[0x6a, 0x01, 0xaa]);
+        mock.push(&[0x6a, 0x05, 0xbb]);
+        mock.push(&[0x6a, 0x02, 0xcc, 0xdd]);
+        let first = stream.next().await.unwrap();
+        assert_eq!((first.periodic_identifier, first.data), (0x01, vec![0xaa]));
+        let second = stream.next().await.unwrap();
+        assert_eq!(
+            (second.periodic_identifier, second.data),
+            (0x02, vec![0xcc, 0xdd])
+        );
+        assert!(second.timestamp >= first.timestamp);
+
+        drop(stream);
+        tokio::time::timeout(Duration::from_secs(1), mock.wait_for_expectations())
+            .await
+            .unwrap();
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_overlapping_subscription_is_rejected() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x2a, 0x01, 0x01]).respond(&[0x6a]);
+        mock.expect(&[0x2a, 0x04, 0x01]).respond(&[0x6a]);
+        mock.expect(&[0x2a, 0x01, 0x01, 0x02]).respond(&[0x6a]);
+        let client = UdsClient::new_from_transport(mock.clone());
+
+        let stream = client
+            .subscribe_periodic_data(TransmissionMode::SendAtSlowRate, &[0x01])
+            .await
+            .unwrap();
+        assert!(matches!(
+            client
+                .subscribe_periodic_data(TransmissionMode::SendAtSlowRate, &[0x01, 0x02])
+                .await,
+            Err(UdsError::InvalidArgument)
+        ));
+        assert!(stream.stop().await.is_ok());
+        let _stream = client
+            .subscribe_periodic_data(TransmissionMode::SendAtSlowRate, &[0x01, 0x02])
+            .await
+            .unwrap();
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_periodic_data_uudt() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x2a, 0x03, 0x10]).respond(&[0x6a]);
+        mock.expect(&[0x2a, 0x04, 0x10]).respond(&[0x6a]);
+        let client = UdsClient::new_from_transport(mock.clone());
+        let (io, ecu) = channel_can_pair();
+        let periodic_id = StandardId::new(0x5e8).unwrap();
+
+        let mut stream = client
+            .subscribe_periodic_data_uudt(
+                TransmissionMode::SendAtFastRate,
+                &[0x10],
+                io,
+                periodic_id,
+            )
+            .await
+            .unwrap();
+        for (id, data) in [(0x5e9, vec![0x10, 0x01]), (0x5e8, vec![0x10, 0x02, 0x03])] {
+            ecu.send_frame(&CanFrame {
+                id: Id::Standard(StandardId::new(id).unwrap()),
+                data,
+            })
+            .await
+            .unwrap();
+        }
+        let periodic_data = stream.next().await.unwrap();
+        assert_eq!(periodic_data.periodic_identifier, 0x10);
+        assert_eq!(periodic_data.data, vec![0x02, 0x03]);
+
+        assert!(stream.stop().await.is_ok());
+        mock.assert_done();
+    }
+
+    struct FailingCanIo;
+
+    impl CanFrameIo for FailingCanIo {
+        fn send_frame<'a>(
+            &'a self,
+            _frame: &'a CanFrame,
+        ) -> BoxFuture<'a, Result<(), UdsCommunicationError>> {
+            Box::pin(async { Err(UdsCommunicationError::InvalidFrame) })
+        }
+
+        fn receive_frame(&self) -> BoxFuture<'_, Result<CanFrame, UdsCommunicationError>> {
+            Box::pin(async { Err(UdsCommunicationError::InvalidFrame) })
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_uudt_stream_ends_after_repeated_errors() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x2a, 0x03, 0x10]).respond(&[0x6a]);
+        mock.expect(&[0x2a, 0x04, 0x10]).respond(&[0x6a]);
+        let client = UdsClient::new_from_transport(mock.clone());
+
+        let mut stream = client
+            .subscribe_periodic_data_uudt(
+                TransmissionMode::SendAtFastRate,
+                &[0x10],
+                FailingCanIo,
+                StandardId::new(0x5e8).unwrap(),
+            )
+            .await
+            .unwrap();
+        let start = Instant::now();
+        assert_eq!(stream.next().await, None);
+        let backoff_steps: u32 = (1..MAX_CONSECUTIVE_RECEIVE_ERRORS).sum();
+        assert!(start.elapsed() >= RECEIVE_ERROR_BACKOFF * backoff_steps);
+
+        assert!(stream.stop().await.is_ok());
+        mock.assert_done();
+    }
+}
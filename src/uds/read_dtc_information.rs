@@ -8,8 +8,19 @@
 //! [UdsClient::report_number_of_dtc_by_status_mask]  subfunction 0x06  
 //! [UdsClient::report_most_recent_confirmed_dtc]  subfunction 0x0e  
 //!
+//! and following methods for FunctionalUdsClient:
+//!
+//! [FunctionalUdsClient::report_number_of_dtc_by_status_mask]  subfunction 0x01  
+//! [FunctionalUdsClient::report_dtc_by_status_mask]  subfunction 0x02  
+//!
+//! Each public method has `_suppressed` variant sending the request with suppressed positive
+//! response, which returns [UdsResponse::PositiveResponseSuppressed] unless the server responds
+//! with NRC.
+//!
 use super::*;
-use crate::uds::uds_definitions::SEND_RECEIVE_SID_OFFSET;
+use crate::uds::uds_definitions::{
+    compose_sub_function, parse_sub_function, SEND_RECEIVE_SID_OFFSET,
+};
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 
 const READ_DTC_INFORMATION_SID: u8 = 0x19;
@@ -81,7 +92,7 @@ enum SubFunction {
 
 #[derive(IntoPrimitive, TryFromPrimitive, Debug, PartialEq, Clone, Copy)]
 #[repr(u8)]
-#[allow(non_camel_case_types)]
+#[allow(non_camel_case_types, clippy::enum_variant_names)]
 enum DTCFormat {
     SAE_J2012_DA_DTCFormat_00 = 0x00,
     ISO_14229_1_DTCFormat = 0x01,
@@ -95,25 +106,69 @@ impl UdsClient {
     pub async fn report_number_of_dtc_by_status_mask(
         &self,
         dtc_status_mask: u8,
+    ) -> EcuResponseResult {
+        self.report_number_of_dtc_by_status_mask_suppressible(dtc_status_mask, false)
+            .await
+    }
+
+    pub async fn report_number_of_dtc_by_status_mask_suppressed(
+        &self,
+        dtc_status_mask: u8,
+    ) -> EcuResponseResult {
+        self.report_number_of_dtc_by_status_mask_suppressible(dtc_status_mask, true)
+            .await
+    }
+
+    async fn report_number_of_dtc_by_status_mask_suppressible(
+        &self,
+        dtc_status_mask: u8,
+        suppress_positive_response: bool,
     ) -> EcuResponseResult {
         let request = compose_report_number_of_dtc_by_status_mask_request(
             SubFunction::ReportNumberOfDTCbyStatusMask,
             dtc_status_mask,
+            suppress_positive_response,
         );
-        let raw_response = self.send_and_receive(&request).await?;
-        let response = parse_report_number_of_dtc_by_status_mask_response(&raw_response);
-        response
+        let Some(raw_response) = self
+            .send_and_receive_suppressible(&request, suppress_positive_response)
+            .await?
+        else {
+            return Ok(UdsResponse::PositiveResponseSuppressed);
+        };
+        parse_report_number_of_dtc_by_status_mask_response(&raw_response)
     }
 
     /// 0x02
     pub async fn report_dtc_by_status_mask(&self, dtc_status_mask: u8) -> EcuResponseResult {
+        self.report_dtc_by_status_mask_suppressible(dtc_status_mask, false)
+            .await
+    }
+
+    pub async fn report_dtc_by_status_mask_suppressed(
+        &self,
+        dtc_status_mask: u8,
+    ) -> EcuResponseResult {
+        self.report_dtc_by_status_mask_suppressible(dtc_status_mask, true)
+            .await
+    }
+
+    async fn report_dtc_by_status_mask_suppressible(
+        &self,
+        dtc_status_mask: u8,
+        suppress_positive_response: bool,
+    ) -> EcuResponseResult {
         let request = compose_report_number_of_dtc_by_status_mask_request(
             SubFunction::ReportDTCByStatusMask,
             dtc_status_mask,
+            suppress_positive_response,
         );
-        let raw_response = self.send_and_receive(&request).await?;
-        let response = parse_report_dtcs(&raw_response);
-        response
+        let Some(raw_response) = self
+            .send_and_receive_suppressible(&request, suppress_positive_response)
+            .await?
+        else {
+            return Ok(UdsResponse::PositiveResponseSuppressed);
+        };
+        parse_report_dtcs(&raw_response)
     }
 
     // /// 0x03
@@ -135,10 +190,10 @@ impl UdsClient {
             SubFunction::ReportDTCSnapshotRecordByDTCNumber,
             dtc_mask_record,
             dtc_snapshot_record_number,
+            false,
         );
         let raw_response = self.send_and_receive(&request).await?;
-        let response = parse_report_dtc_snapshot_record_by_dtc_number_response(&raw_response);
-        response
+        parse_report_dtc_snapshot_record_by_dtc_number_response(&raw_response)
     }
 
     // /// 0x05
@@ -152,15 +207,47 @@ impl UdsClient {
         &self,
         dtc_mask_record: u32,
         dtc_ext_data_record_number: u8,
+    ) -> EcuResponseResult {
+        self.report_dtc_ext_data_record_by_dtc_number_suppressible(
+            dtc_mask_record,
+            dtc_ext_data_record_number,
+            false,
+        )
+        .await
+    }
+
+    pub async fn report_dtc_ext_data_record_by_dtc_number_suppressed(
+        &self,
+        dtc_mask_record: u32,
+        dtc_ext_data_record_number: u8,
+    ) -> EcuResponseResult {
+        self.report_dtc_ext_data_record_by_dtc_number_suppressible(
+            dtc_mask_record,
+            dtc_ext_data_record_number,
+            true,
+        )
+        .await
+    }
+
+    async fn report_dtc_ext_data_record_by_dtc_number_suppressible(
+        &self,
+        dtc_mask_record: u32,
+        dtc_ext_data_record_number: u8,
+        suppress_positive_response: bool,
     ) -> EcuResponseResult {
         let request = compose_report_dtc_ext_data_by_dtc_number_request(
             SubFunction::ReportDTCExtDataRecordByDTCNumber,
             dtc_mask_record,
             dtc_ext_data_record_number,
+            suppress_positive_response,
         );
-        let raw_response = self.send_and_receive(&request).await?;
-        let response = parse_report_dtc_ext_data_by_dtc_number_response(&raw_response);
-        response
+        let Some(raw_response) = self
+            .send_and_receive_suppressible(&request, suppress_positive_response)
+            .await?
+        else {
+            return Ok(UdsResponse::PositiveResponseSuppressed);
+        };
+        parse_report_dtc_ext_data_by_dtc_number_response(&raw_response)
     }
 
     // /// 0x07
@@ -207,10 +294,30 @@ impl UdsClient {
 
     /// 0x0E
     pub async fn report_most_recent_confirmed_dtc(&self) -> EcuResponseResult {
-        let request = compose_request_short(SubFunction::ReportMostRecentConfirmedDTC);
-        let raw_response = self.send_and_receive(&request).await?;
-        let response = parse_report_dtcs(&raw_response);
-        response
+        self.report_most_recent_confirmed_dtc_suppressible(false)
+            .await
+    }
+
+    pub async fn report_most_recent_confirmed_dtc_suppressed(&self) -> EcuResponseResult {
+        self.report_most_recent_confirmed_dtc_suppressible(true)
+            .await
+    }
+
+    async fn report_most_recent_confirmed_dtc_suppressible(
+        &self,
+        suppress_positive_response: bool,
+    ) -> EcuResponseResult {
+        let request = compose_request_short(
+            SubFunction::ReportMostRecentConfirmedDTC,
+            suppress_positive_response,
+        );
+        let Some(raw_response) = self
+            .send_and_receive_suppressible(&request, suppress_positive_response)
+            .await?
+        else {
+            return Ok(UdsResponse::PositiveResponseSuppressed);
+        };
+        parse_report_dtcs(&raw_response)
     }
 
     // /// 0x0F
@@ -286,6 +393,109 @@ impl UdsClient {
     // }
 }
 
+impl FunctionalUdsClient {
+    /// 0x01
+    pub async fn report_number_of_dtc_by_status_mask(
+        &self,
+        dtc_status_mask: u8,
+    ) -> Result<FunctionalResponses, UdsError> {
+        self.report_number_of_dtc_by_status_mask_suppressible(dtc_status_mask, false)
+            .await
+    }
+
+    pub async fn report_number_of_dtc_by_status_mask_suppressed(
+        &self,
+        dtc_status_mask: u8,
+    ) -> Result<FunctionalResponses, UdsError> {
+        self.report_number_of_dtc_by_status_mask_suppressible(dtc_status_mask, true)
+            .await
+    }
+
+    async fn report_number_of_dtc_by_status_mask_suppressible(
+        &self,
+        dtc_status_mask: u8,
+        suppress_positive_response: bool,
+    ) -> Result<FunctionalResponses, UdsError> {
+        let request = compose_report_number_of_dtc_by_status_mask_request(
+            SubFunction::ReportNumberOfDTCbyStatusMask,
+            dtc_status_mask,
+            suppress_positive_response,
+        );
+        self.send_and_collect(&request, parse_report_number_of_dtc_by_status_mask_response)
+            .await
+    }
+
+    /// 0x02
+    pub async fn report_dtc_by_status_mask(
+        &self,
+        dtc_status_mask: u8,
+    ) -> Result<FunctionalResponses, UdsError> {
+        self.report_dtc_by_status_mask_suppressible(dtc_status_mask, false)
+            .await
+    }
+
+    pub async fn report_dtc_by_status_mask_suppressed(
+        &self,
+        dtc_status_mask: u8,
+    ) -> Result<FunctionalResponses, UdsError> {
+        self.report_dtc_by_status_mask_suppressible(dtc_status_mask, true)
+            .await
+    }
+
+    async fn report_dtc_by_status_mask_suppressible(
+        &self,
+        dtc_status_mask: u8,
+        suppress_positive_response: bool,
+    ) -> Result<FunctionalResponses, UdsError> {
+        let request = compose_report_number_of_dtc_by_status_mask_request(
+            SubFunction::ReportDTCByStatusMask,
+            dtc_status_mask,
+            suppress_positive_response,
+        );
+        self.send_and_collect(&request, parse_report_dtcs).await
+    }
+}
+
+/// Parses response of any implemented subfunction based on the echoed report type, used when
+/// the request is not known, e.g. when decoding logs
+pub(super) fn parse_read_dtc_information_response(raw_response: &[u8]) -> EcuResponseResult {
+    if raw_response.is_empty() {
+        return Err(UdsError::ResponseEmpty);
+    }
+    let report_type = *raw_response.get(1).ok_or(UdsError::InvalidLength {
+        raw_message: raw_response.to_vec(),
+    })?;
+    match SubFunction::try_from(parse_sub_function(report_type)) {
+        Ok(
+            SubFunction::ReportNumberOfDTCbyStatusMask
+            | SubFunction::ReportNumberOfDTCBySeverityMaskRecord
+            | SubFunction::ReportNumberOfMirrorMemoryDTCByStatusMask
+            | SubFunction::ReportNumberOfEmissionsOBDDTCByStatusMask,
+        ) => parse_report_number_of_dtc_by_status_mask_response(raw_response),
+        Ok(
+            SubFunction::ReportDTCByStatusMask
+            | SubFunction::ReportSupportedDTC
+            | SubFunction::ReportFirstTestFailedDTC
+            | SubFunction::ReportFirstConfirmedDTC
+            | SubFunction::ReportMostRecentTestFailedDTC
+            | SubFunction::ReportMostRecentConfirmedDTC
+            | SubFunction::ReportMirrorMemoryDTCByStatusMask
+            | SubFunction::ReportEmissionsOBDDTCByStatusMask
+            | SubFunction::ReportDTCWithPermanentStatus,
+        ) => parse_report_dtcs(raw_response),
+        Ok(SubFunction::ReportDTCSnapshotRecordByDTCNumber) => {
+            parse_report_dtc_snapshot_record_by_dtc_number_response(raw_response)
+        }
+        Ok(
+            SubFunction::ReportDTCExtDataRecordByDTCNumber
+            | SubFunction::ReportMirrorMemoryDTCExtDataRecordByDTCNumber,
+        ) => parse_report_dtc_ext_data_by_dtc_number_response(raw_response),
+        _ => Ok(UdsResponse::ReadDTCInformation(DataFormat::Raw(
+            raw_response[1..].to_vec(),
+        ))),
+    }
+}
+
 #[derive(Debug, PartialEq)]
 struct DTCSeverityMaskRecord {
     dtc_status_mask: u8,
@@ -296,8 +506,13 @@ struct DTCSeverityMaskRecord {
 fn compose_report_number_of_dtc_by_status_mask_request(
     subfunction: SubFunction,
     dtc_status_mask: u8,
+    suppress_positive_response: bool,
 ) -> Vec<u8> {
-    vec![READ_DTC_INFORMATION_SID, subfunction as u8, dtc_status_mask]
+    vec![
+        READ_DTC_INFORMATION_SID,
+        compose_sub_function(subfunction as u8, suppress_positive_response),
+        dtc_status_mask,
+    ]
 }
 
 /// Shared between subfunctions 0x01, 0x07, 0x11, 0x12
@@ -319,13 +534,14 @@ fn parse_report_number_of_dtc_by_status_mask_response(raw_response: &[u8]) -> Ec
             raw_message: raw_response.to_vec(),
         });
     }
-    let report_type: SubFunction =
-        SubFunction::try_from(*response_iter.next().ok_or(UdsError::InvalidLength {
-            raw_message: raw_response.to_vec(),
-        })?)
-        .map_err(|_| UdsError::ResponseIncorrect {
+    let report_type: SubFunction = SubFunction::try_from(parse_sub_function(
+        *response_iter.next().ok_or(UdsError::InvalidLength {
             raw_message: raw_response.to_vec(),
-        })?;
+        })?,
+    ))
+    .map_err(|_| UdsError::ResponseIncorrect {
+        raw_message: raw_response.to_vec(),
+    })?;
     let dtc_status_availability_mask: u8 =
         *response_iter.next().ok_or(UdsError::InvalidLength {
             raw_message: raw_response.to_vec(),
@@ -427,10 +643,11 @@ fn parse_report_dtcs(raw_response: &[u8]) -> EcuResponseResult {
         dtc_and_status_records,
     };
 
-    let sub_function =
-        SubFunction::try_from(report_type).map_err(|_| UdsError::ResponseIncorrect {
+    let sub_function = SubFunction::try_from(parse_sub_function(report_type)).map_err(|_| {
+        UdsError::ResponseIncorrect {
             raw_message: raw_response.to_vec(),
-        })?;
+        }
+    })?;
 
     let response = match sub_function {
         SubFunction::ReportDTCByStatusMask => {
@@ -471,10 +688,11 @@ fn compose_report_dtc_snapshot_request(
     sub_function: SubFunction,
     dtc_mask_record: u32,
     dtc_snapshot_record_number: u8,
+    suppress_positive_response: bool,
 ) -> Vec<u8> {
     vec![
         READ_DTC_INFORMATION_SID,
-        sub_function as u8,
+        compose_sub_function(sub_function as u8, suppress_positive_response),
         (dtc_mask_record >> 16) as u8,
         (dtc_mask_record >> 8) as u8,
         dtc_mask_record as u8,
@@ -520,8 +738,11 @@ fn parse_report_dtc_snapshot_record_by_dtc_number_response(
 }
 
 /// Shared between 0x0A, 0x0B, 0x0C, 0x0D, 0x0E, 0x14, 0x15
-fn compose_request_short(sub_function: SubFunction) -> Vec<u8> {
-    vec![READ_DTC_INFORMATION_SID, sub_function as u8]
+fn compose_request_short(sub_function: SubFunction, suppress_positive_response: bool) -> Vec<u8> {
+    vec![
+        READ_DTC_INFORMATION_SID,
+        compose_sub_function(sub_function as u8, suppress_positive_response),
+    ]
 }
 
 /// Shared between 0x06, 0x10
@@ -529,10 +750,11 @@ fn compose_report_dtc_ext_data_by_dtc_number_request(
     sub_function: SubFunction,
     dtc_mask_record: u32,
     dtc_ext_data_record_number: u8,
+    suppress_positive_response: bool,
 ) -> Vec<u8> {
     vec![
         READ_DTC_INFORMATION_SID,
-        sub_function as u8,
+        compose_sub_function(sub_function as u8, suppress_positive_response),
         (dtc_mask_record >> 16) as u8,
         (dtc_mask_record >> 8) as u8,
         dtc_mask_record as u8,
@@ -567,6 +789,7 @@ mod tests {
         let result = compose_report_number_of_dtc_by_status_mask_request(
             SubFunction::ReportNumberOfDTCbyStatusMask,
             dtc_status_mask,
+            false,
         );
         let expected = vec![
             READ_DTC_INFORMATION_SID,
@@ -616,6 +839,7 @@ mod tests {
         let result = compose_report_number_of_dtc_by_status_mask_request(
             SubFunction::ReportDTCByStatusMask,
             dtc_status_mask,
+            false,
         );
         assert_eq!(result, expected);
     }
@@ -661,6 +885,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(unused_variables)]
     fn test_parse_empty_response_0x02() {
         let sid = READ_DTC_INFORMATION_SID + SEND_RECEIVE_SID_OFFSET;
         let report_type = SubFunction::try_from(0x2).unwrap();
@@ -688,6 +913,7 @@ mod tests {
             sub_function,
             dtc_mask_record,
             dtc_snapshot_record_number,
+            false,
         );
         let expected = vec![
             sid,
@@ -718,6 +944,7 @@ mod tests {
             sub_function,
             dtc_mask_record,
             dtc_ext_data_record_number,
+            false,
         );
         let expected = vec![
             sid,
@@ -737,10 +964,11 @@ mod tests {
         assert_eq!(Err(UdsError::ResponseEmpty), result);
     }
 
+    #[allow(dead_code)]
     fn test_compose_request_0x0e() {
         let sid = READ_DTC_INFORMATION_SID;
         let subfunction = SubFunction::try_from(0x0e).unwrap();
-        let result = compose_request_short(subfunction);
+        let result = compose_request_short(subfunction, false);
         assert_eq!(vec![sid, 0x0e], result);
     }
 
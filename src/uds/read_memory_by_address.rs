@@ -41,8 +41,7 @@ impl UdsClient {
             memory_size,
         );
         let response = self.send_and_receive(&request).await?;
-        let parsed_response = parse_response(&response);
-        return parsed_response;
+        parse_response(&response)
     }
     /// Simplified method, where address_and_memory_length_format_identifier will be assumed from
     /// provided arguments if not specified.
@@ -70,23 +69,26 @@ impl UdsClient {
     }
 }
 
-fn convert_from_simple_to_normal(
+/// Returns address_and_memory_length_format_identifier, memory address and memory size, shared by
+/// all services addressing the memory
+pub(super) fn convert_from_simple_to_normal(
     memory_address: u64,
     memory_size: u64,
     memory_address_len: Option<u8>,
     memory_size_len: Option<u8>,
 ) -> Result<(u8, Vec<u8>, Vec<u8>), UdsError> {
-    let mut address_encode_bytes = 0;
-    let mut size_encode_bytes = 0;
+    // zero address or size is still encoded in one byte
+    let mut address_encode_bytes = 1;
+    let mut size_encode_bytes = 1;
 
-    let mut i = memory_address;
+    let mut i = memory_address >> 8;
     while i > 0 {
-        i = i >> 8;
+        i >>= 8;
         address_encode_bytes += 1;
     }
-    let mut i = memory_size;
+    let mut i = memory_size >> 8;
     while i > 0 {
-        i = i >> 8;
+        i >>= 8;
         size_encode_bytes += 1;
     }
 
@@ -148,7 +150,7 @@ fn formulate_request(
 
     request
 }
-fn parse_response(raw_response: &[u8]) -> EcuResponseResult {
+pub(super) fn parse_response(raw_response: &[u8]) -> EcuResponseResult {
     let sid = raw_response[0];
     if sid != READ_MEMORY_BY_ADDRESS_SID + SEND_RECEIVE_SID_OFFSET {
         return Err(UdsError::SidMismatch {
@@ -235,6 +237,37 @@ mod tests {
         assert_eq!(result, Ok(expected));
     }
 
+    #[test]
+    fn test_ok_convert_from_simple_to_normal_zero_address_and_size() {
+        // zero length is not valid in addressAndLengthFormatIdentifier, so zero is one byte
+        let memory_address: u64 = 0x0;
+        let memory_size: u64 = 0x0;
+        let memory_address_len: Option<u8> = None;
+        let memory_size_len: Option<u8> = None;
+        let expected: (u8, Vec<u8>, Vec<u8>) = (0x11, vec![0x0], vec![0x0]);
+        let result = convert_from_simple_to_normal(
+            memory_address,
+            memory_size,
+            memory_address_len,
+            memory_size_len,
+        );
+        assert_eq!(result, Ok(expected));
+    }
+
+    #[test]
+    fn test_err_convert_from_simple_to_normal_specified_zero_len() {
+        let memory_address: u64 = 0x0;
+        let memory_size: u64 = 0x10;
+        assert_eq!(
+            convert_from_simple_to_normal(memory_address, memory_size, Some(0), None),
+            Err(UdsError::InvalidArgument)
+        );
+        assert_eq!(
+            convert_from_simple_to_normal(memory_address, memory_size, None, Some(0)),
+            Err(UdsError::InvalidArgument)
+        );
+    }
+
     #[test]
     fn test_err_convert_from_simple_to_normal_specified_memory_address_len_too_small() {
         let memory_address: u64 = 0x12345678;
@@ -0,0 +1,631 @@
+//! # Implementation of ReadScalingDataByIdentifier 0x24 service
+//!
+//! This module provides following methods for UdsClient:
+//!
+//! [UdsClient::read_scaling_data_by_identifier]
+//! [UdsClient::read_scaled_data_by_identifier]
+//!
+//! Scaling of the data identifier is sequence of scalingBytes (ISO 14229-1 Annex C). Each data
+//! scalingByte describes next parameter of the data record, formula and unit/format scalingBytes
+//! apply to the preceding parameter. [apply_scaling] converts [DataRecord] to physical values.
+//!
+use super::*;
+use crate::uds::uds_definitions::SEND_RECEIVE_SID_OFFSET;
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+
+const READ_SCALING_DATA_BY_IDENTIFIER_SID: u8 = 0x24;
+
+/// High nibble of the scalingByte
+#[derive(IntoPrimitive, TryFromPrimitive, Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum ScalingType {
+    UnsignedNumeric = 0x0,
+    SignedNumeric = 0x1,
+    BitMappedReportedWithoutMask = 0x2,
+    BitMappedReportedWithMask = 0x3,
+    BinaryCodedDecimal = 0x4,
+    StateEncodedVariable = 0x5,
+    Ascii = 0x6,
+    SignedFloatingPoint = 0x7,
+    Packet = 0x8,
+    Formula = 0x9,
+    UnitFormat = 0xA,
+    StateAndConnectionType = 0xB,
+}
+
+/// Single scalingByte with its scalingByteExtension
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScalingRecord {
+    /// Parameter of `length` bytes in the data record
+    Data {
+        scaling_type: ScalingType,
+        length: u8,
+    },
+    /// bitMappedReportedWithoutMask parameter of `length` bytes
+    BitMapped {
+        length: u8,
+        validity_mask: Vec<u8>,
+    },
+    Formula(Formula),
+    Unit(Unit),
+}
+
+/// Formula applied to the preceding numeric parameter
+#[derive(Debug, Clone, PartialEq)]
+pub struct Formula {
+    pub identifier: u8,
+    pub constants: Vec<f64>,
+}
+
+impl Formula {
+    /// None for reserved and vehicle manufacturer specific formulas, or when constants are missing
+    pub fn apply(&self, x: f64) -> Option<f64> {
+        let c = |i: usize| self.constants.get(i).copied();
+        Some(match self.identifier {
+            0x00 => c(0)? * x + c(1)?,
+            0x01 => c(0)? * (x + c(1)?),
+            0x02 => c(0)? / (x + c(1)?) + c(2)?,
+            0x03 => x / c(0)? + c(1)?,
+            0x04 => (x + c(0)?) / c(1)?,
+            0x05 => (x + c(0)?) / c(1)? + c(2)?,
+            0x06 => c(0)? * x,
+            0x07 => x / c(0)?,
+            0x08 => x + c(0)?,
+            0x09 => x * c(0)? / c(1)?,
+            _ => return None,
+        })
+    }
+}
+
+/// Unit, unit prefix or date/time format code of unit/format scalingByte
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Unit(pub u8);
+
+impl Unit {
+    /// Symbol of the unit or prefix, None for no unit, date/time formats and reserved codes
+    pub fn symbol(&self) -> Option<&'static str> {
+        let symbol = match self.0 {
+            0x01 => "m",
+            0x02 => "ft",
+            0x03 => "in",
+            0x04 => "yd",
+            0x05 => "mi",
+            0x06 => "g",
+            0x07 => "t",
+            0x08 => "s",
+            0x09 => "min",
+            0x0A => "h",
+            0x0B => "d",
+            0x0C => "y",
+            0x0D => "A",
+            0x0E => "V",
+            0x0F => "C",
+            0x10 => "Ω",
+            0x11 => "F",
+            0x12 => "H",
+            0x13 => "S",
+            0x14 => "Wb",
+            0x15 => "T",
+            0x16 => "K",
+            0x17 => "°C",
+            0x18 => "°F",
+            0x19 => "cd",
+            0x1A => "rad",
+            0x1B => "°",
+            0x1C => "Hz",
+            0x1D => "J",
+            0x1E => "N",
+            0x1F => "kp",
+            0x20 => "lbf",
+            0x21 => "W",
+            0x22 => "hk",
+            0x23 => "hp",
+            0x24 => "Pa",
+            0x25 => "bar",
+            0x26 => "atm",
+            0x27 => "psi",
+            0x28 => "Bq",
+            0x29 => "lm",
+            0x2A => "lx",
+            0x2B => "l",
+            0x2C => "gal (UK)",
+            0x2D => "gal (US)",
+            0x2E => "cu in",
+            0x2F => "m/s",
+            0x30 => "km/h",
+            0x31 => "mph",
+            0x32 => "rps",
+            0x33 => "rpm",
+            0x34 => "counts",
+            0x35 => "%",
+            0x36 => "mg/stroke",
+            0x37 => "m/s²",
+            0x38 => "Nm",
+            0x39 => "l/min",
+            0x3A => "W/m²",
+            0x3B => "bar/s",
+            0x3C => "rad/s",
+            0x3D => "rad/s²",
+            0x3E => "kg/m²",
+            0x40 => "E",
+            0x41 => "P",
+            0x42 => "T",
+            0x43 => "G",
+            0x44 => "M",
+            0x45 => "k",
+            0x46 => "h",
+            0x47 => "da",
+            0x48 => "d",
+            0x49 => "c",
+            0x4A => "m",
+            0x4B => "µ",
+            0x4C => "n",
+            0x4D => "p",
+            0x4E => "f",
+            0x4F => "a",
+            0x53 => "W",
+            _ => return None,
+        };
+        Some(symbol)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ReadScalingDataByIdentifierResponse {
+    pub data_identifier: u16,
+    pub scaling_records: Vec<ScalingRecord>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PhysicalValue {
+    Numeric(f64),
+    Text(String),
+    /// validity_mask is None for bitMappedReportedWithMask, where the mask is part of the value
+    BitMapped {
+        value: Vec<u8>,
+        validity_mask: Option<Vec<u8>>,
+    },
+    State(u64),
+    /// packet parameters are not decoded
+    Raw(Vec<u8>),
+}
+
+/// Parameter of the data record converted by [apply_scaling]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScaledValue {
+    pub value: PhysicalValue,
+    /// prefix and unit symbols joined, e.g. "km"
+    pub unit: Option<String>,
+}
+
+impl UdsClient {
+    pub async fn read_scaling_data_by_identifier(&self, data_identifier: u16) -> EcuResponseResult {
+        let raw_response = self
+            .send_read_scaling_data_by_identifier(data_identifier)
+            .await?;
+        parse_read_scaling_data_by_identifier_response(&raw_response)
+    }
+
+    /// Read scaling and data of the data identifier and convert data to physical values
+    pub async fn read_scaled_data_by_identifier(
+        &self,
+        data_identifier: u16,
+    ) -> Result<Vec<ScaledValue>, UdsError> {
+        let raw_response = self
+            .send_read_scaling_data_by_identifier(data_identifier)
+            .await?;
+        let scaling = expect_parsed(
+            &raw_response,
+            parse_read_scaling_data_by_identifier_response,
+            |response| match response {
+                UdsResponse::ReadScalingDataByIdentifier(DataFormat::Parsed(scaling)) => {
+                    Some(scaling)
+                }
+                _ => None,
+            },
+        )?;
+        let record = self.read_data_record(data_identifier).await?;
+        apply_scaling(&scaling, &record)
+    }
+
+    /// Send request and check the echoed data identifier
+    async fn send_read_scaling_data_by_identifier(
+        &self,
+        data_identifier: u16,
+    ) -> Result<Vec<u8>, UdsError> {
+        let request = compose_read_scaling_data_by_identifier_request(data_identifier);
+        let raw_response = self.send_and_receive(&request).await?;
+        if let [_, did_hi, did_lo, ..] = raw_response.as_slice() {
+            let received = u16::from_be_bytes([*did_hi, *did_lo]);
+            if received != data_identifier {
+                return Err(UdsError::DidMismatch {
+                    expected: data_identifier,
+                    received,
+                    raw_message: raw_response,
+                });
+            }
+        }
+        Ok(raw_response)
+    }
+}
+
+/// Convert data record to physical values, one for each data scalingByte
+pub fn apply_scaling(
+    scaling: &ReadScalingDataByIdentifierResponse,
+    record: &DataRecord,
+) -> Result<Vec<ScaledValue>, UdsError> {
+    if scaling.data_identifier != record.data_identifier {
+        return Err(UdsError::DidMismatch {
+            expected: scaling.data_identifier,
+            received: record.data_identifier,
+            raw_message: record.data.clone(),
+        });
+    }
+    let incorrect = || UdsError::ResponseIncorrect {
+        raw_message: record.data.clone(),
+    };
+    let mut values: Vec<ScaledValue> = vec![];
+    let mut data = record.data.as_slice();
+    for scaling_record in &scaling.scaling_records {
+        match scaling_record {
+            ScalingRecord::Data {
+                scaling_type,
+                length,
+            } => {
+                let parameter =
+                    split_parameter(&mut data, *length).ok_or_else(|| UdsError::InvalidLength {
+                        raw_message: record.data.clone(),
+                    })?;
+                let value = decode_parameter(*scaling_type, parameter).ok_or_else(|| {
+                    error!(
+                        "Parameter {:02x?} can not be decoded as {:?}",
+                        parameter, scaling_type
+                    );
+                    incorrect()
+                })?;
+                values.push(ScaledValue { value, unit: None });
+            }
+            ScalingRecord::BitMapped {
+                length,
+                validity_mask,
+            } => {
+                let parameter =
+                    split_parameter(&mut data, *length).ok_or_else(|| UdsError::InvalidLength {
+                        raw_message: record.data.clone(),
+                    })?;
+                values.push(ScaledValue {
+                    value: PhysicalValue::BitMapped {
+                        value: parameter.to_vec(),
+                        validity_mask: Some(validity_mask.clone()),
+                    },
+                    unit: None,
+                });
+            }
+            ScalingRecord::Formula(formula) => {
+                let Some(ScaledValue {
+                    value: PhysicalValue::Numeric(x),
+                    ..
+                }) = values.last_mut()
+                else {
+                    error!("Formula {:?} does not follow numeric parameter", formula);
+                    return Err(incorrect());
+                };
+                *x = formula.apply(*x).ok_or_else(|| {
+                    error!("Formula {:02x} is not supported", formula.identifier);
+                    UdsError::NotImplemented
+                })?;
+            }
+            ScalingRecord::Unit(unit) => {
+                let Some(last) = values.last_mut() else {
+                    error!("Unit {:?} does not follow any parameter", unit);
+                    return Err(incorrect());
+                };
+                if let Some(symbol) = unit.symbol() {
+                    last.unit.get_or_insert_with(String::new).push_str(symbol);
+                }
+            }
+        }
+    }
+    if !data.is_empty() {
+        return Err(UdsError::InvalidLength {
+            raw_message: record.data.clone(),
+        });
+    }
+    Ok(values)
+}
+
+fn split_parameter<'a>(data: &mut &'a [u8], length: u8) -> Option<&'a [u8]> {
+    let (parameter, rest) = data.split_at_checked(length as usize)?;
+    *data = rest;
+    Some(parameter)
+}
+
+fn decode_parameter(scaling_type: ScalingType, parameter: &[u8]) -> Option<PhysicalValue> {
+    let value = match scaling_type {
+        ScalingType::UnsignedNumeric => PhysicalValue::Numeric(to_u64(parameter)? as f64),
+        ScalingType::SignedNumeric => {
+            // converted first, so the shift is computed only for at most 8 bytes
+            let value = to_u64(parameter)?;
+            let shift = 64 - 8 * parameter.len() as u32;
+            PhysicalValue::Numeric(((value << shift) as i64 >> shift) as f64)
+        }
+        ScalingType::BinaryCodedDecimal => {
+            let value = parameter.iter().try_fold(0u64, |value, byte| {
+                let (high, low) = ((byte >> 4) as u64, (byte & 0x0F) as u64);
+                if high > 9 || low > 9 {
+                    return None;
+                }
+                value.checked_mul(100)?.checked_add(high * 10 + low)
+            })?;
+            PhysicalValue::Numeric(value as f64)
+        }
+        ScalingType::SignedFloatingPoint => match *parameter {
+            [a, b, c, d] => PhysicalValue::Numeric(f32::from_be_bytes([a, b, c, d]) as f64),
+            [a, b, c, d, e, f, g, h] => {
+                PhysicalValue::Numeric(f64::from_be_bytes([a, b, c, d, e, f, g, h]))
+            }
+            _ => return None,
+        },
+        ScalingType::Ascii => PhysicalValue::Text(String::from_utf8_lossy(parameter).into_owned()),
+        ScalingType::StateEncodedVariable | ScalingType::StateAndConnectionType => {
+            PhysicalValue::State(to_u64(parameter)?)
+        }
+        ScalingType::BitMappedReportedWithMask => PhysicalValue::BitMapped {
+            value: parameter.to_vec(),
+            validity_mask: None,
+        },
+        ScalingType::Packet
+        | ScalingType::BitMappedReportedWithoutMask
+        | ScalingType::Formula
+        | ScalingType::UnitFormat => PhysicalValue::Raw(parameter.to_vec()),
+    };
+    Some(value)
+}
+
+/// Big endian value of 1 to 8 bytes
+fn to_u64(bytes: &[u8]) -> Option<u64> {
+    if bytes.is_empty() || bytes.len() > 8 {
+        return None;
+    }
+    Some(
+        bytes
+            .iter()
+            .fold(0, |value, byte| (value << 8) | *byte as u64),
+    )
+}
+
+/// Two byte real number - 4 bit signed exponent followed by 12 bit signed mantissa
+fn parse_two_byte_real(raw: u16) -> f64 {
+    let exponent = (raw as i16) >> 12;
+    let mantissa = (((raw << 4) as i16) >> 4) as f64;
+    if exponent < 0 {
+        mantissa / 10f64.powi(-exponent as i32)
+    } else {
+        mantissa * 10f64.powi(exponent as i32)
+    }
+}
+
+fn parse_formula(extension: &[u8]) -> Option<Formula> {
+    let [identifier, ref constants @ ..] = *extension else {
+        return None;
+    };
+    if constants.len() % 2 == 1 {
+        return None;
+    }
+    Some(Formula {
+        identifier,
+        constants: constants
+            .chunks_exact(2)
+            .map(|constant| parse_two_byte_real(u16::from_be_bytes([constant[0], constant[1]])))
+            .collect(),
+    })
+}
+
+fn compose_read_scaling_data_by_identifier_request(data_identifier: u16) -> Vec<u8> {
+    let mut request = vec![READ_SCALING_DATA_BY_IDENTIFIER_SID];
+    request.extend_from_slice(&data_identifier.to_be_bytes());
+    request
+}
+
+pub(super) fn parse_read_scaling_data_by_identifier_response(
+    raw_response: &[u8],
+) -> EcuResponseResult {
+    let mut response_iter = raw_response.iter();
+    let sid = *response_iter.next().ok_or(UdsError::ResponseEmpty)?;
+    if sid != READ_SCALING_DATA_BY_IDENTIFIER_SID + SEND_RECEIVE_SID_OFFSET {
+        return Err(UdsError::SidMismatch {
+            expected: READ_SCALING_DATA_BY_IDENTIFIER_SID + SEND_RECEIVE_SID_OFFSET,
+            received: sid,
+            raw_message: raw_response.to_vec(),
+        });
+    }
+    let invalid_length = || UdsError::InvalidLength {
+        raw_message: raw_response.to_vec(),
+    };
+    let [did_high, did_low, ref scaling @ ..] = *response_iter.as_slice() else {
+        return Err(invalid_length());
+    };
+    let mut scaling_records = vec![];
+    let mut scaling = scaling;
+    while let [scaling_byte, ref rest @ ..] = *scaling {
+        scaling = rest;
+        let length = scaling_byte & 0x0F;
+        let scaling_type = ScalingType::try_from_primitive(scaling_byte >> 4).map_err(|_| {
+            UdsError::ResponseIncorrect {
+                raw_message: raw_response.to_vec(),
+            }
+        })?;
+        let scaling_record = match scaling_type {
+            ScalingType::BitMappedReportedWithoutMask => ScalingRecord::BitMapped {
+                length,
+                validity_mask: split_parameter(&mut scaling, length)
+                    .ok_or_else(invalid_length)?
+                    .to_vec(),
+            },
+            ScalingType::Formula => ScalingRecord::Formula(
+                split_parameter(&mut scaling, length)
+                    .and_then(parse_formula)
+                    .ok_or_else(invalid_length)?,
+            ),
+            ScalingType::UnitFormat => match split_parameter(&mut scaling, length) {
+                Some(&[unit]) => ScalingRecord::Unit(Unit(unit)),
+                _ => return Err(invalid_length()),
+            },
+            _ => ScalingRecord::Data {
+                scaling_type,
+                length,
+            },
+        };
+        scaling_records.push(scaling_record);
+    }
+    Ok(UdsResponse::ReadScalingDataByIdentifier(
+        DataFormat::Parsed(ReadScalingDataByIdentifierResponse {
+            data_identifier: u16::from_be_bytes([did_high, did_low]),
+            scaling_records,
+        }),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// unsigned 1 byte, formula 0.75 * x - 30, km/h
+    const VEHICLE_SPEED_SCALING: [u8; 11] = [
+        0x64, 0x01, 0x05, 0x01, 0x95, 0x00, 0xe0, 0x4b, 0x1f, 0xfd, 0xa1,
+    ];
+
+    fn vehicle_speed_scaling() -> ReadScalingDataByIdentifierResponse {
+        ReadScalingDataByIdentifierResponse {
+            data_identifier: 0x0105,
+            scaling_records: vec![
+                ScalingRecord::Data {
+                    scaling_type: ScalingType::UnsignedNumeric,
+                    length: 1,
+                },
+                ScalingRecord::Formula(Formula {
+                    identifier: 0x00,
+                    constants: vec![0.75, -30.0],
+                }),
+                ScalingRecord::Unit(Unit(0x30)),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let mut raw_response = VEHICLE_SPEED_SCALING.to_vec();
+        raw_response.push(0x30);
+        assert_eq!(
+            parse_read_scaling_data_by_identifier_response(&raw_response),
+            Ok(UdsResponse::ReadScalingDataByIdentifier(
+                DataFormat::Parsed(vehicle_speed_scaling())
+            ))
+        );
+        // missing unit byte
+        assert_eq!(
+            parse_read_scaling_data_by_identifier_response(&VEHICLE_SPEED_SCALING),
+            Err(UdsError::InvalidLength {
+                raw_message: VEHICLE_SPEED_SCALING.to_vec()
+            })
+        );
+    }
+
+    #[test]
+    fn test_apply_scaling() {
+        let scaling = ReadScalingDataByIdentifierResponse {
+            data_identifier: 0x0200,
+            scaling_records: vec![
+                ScalingRecord::Data {
+                    scaling_type: ScalingType::SignedNumeric,
+                    length: 2,
+                },
+                ScalingRecord::Unit(Unit(0x45)),
+                ScalingRecord::Unit(Unit(0x01)),
+                ScalingRecord::Data {
+                    scaling_type: ScalingType::Ascii,
+                    length: 2,
+                },
+                ScalingRecord::BitMapped {
+                    length: 1,
+                    validity_mask: vec![0x0f],
+                },
+            ],
+        };
+        let record = DataRecord {
+            data_identifier: 0x0200,
+            data: vec![0xff, 0x9c, 0x4f, 0x4b, 0x05],
+        };
+        assert_eq!(
+            apply_scaling(&scaling, &record),
+            Ok(vec![
+                ScaledValue {
+                    value: PhysicalValue::Numeric(-100.0),
+                    unit: Some("km".to_string()),
+                },
+                ScaledValue {
+                    value: PhysicalValue::Text("OK".to_string()),
+                    unit: None,
+                },
+                ScaledValue {
+                    value: PhysicalValue::BitMapped {
+                        value: vec![0x05],
+                        validity_mask: Some(vec![0x0f]),
+                    },
+                    unit: None,
+                },
+            ])
+        );
+
+        let short_record = DataRecord {
+            data_identifier: 0x0200,
+            data: vec![0xff, 0x9c, 0x4f],
+        };
+        assert_eq!(
+            apply_scaling(&scaling, &short_record),
+            Err(UdsError::InvalidLength {
+                raw_message: vec![0xff, 0x9c, 0x4f]
+            })
+        );
+    }
+
+    #[test]
+    fn test_signed_numeric_longer_than_8_bytes() {
+        // scalingByte 0x19 - signed numeric of 9 bytes
+        let Ok(UdsResponse::ReadScalingDataByIdentifier(DataFormat::Parsed(scaling))) =
+            parse_read_scaling_data_by_identifier_response(&[0x64, 0x02, 0x00, 0x19])
+        else {
+            panic!("scaling response not parsed");
+        };
+        let record = DataRecord {
+            data_identifier: 0x0200,
+            data: vec![0xff; 9],
+        };
+        assert_eq!(
+            apply_scaling(&scaling, &record),
+            Err(UdsError::ResponseIncorrect {
+                raw_message: vec![0xff; 9]
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_scaled_data_by_identifier() {
+        let mock = MockTransport::new();
+        let mut scaling_response = VEHICLE_SPEED_SCALING.to_vec();
+        scaling_response.push(0x30);
+        mock.expect(&[0x24, 0x01, 0x05]).respond(&scaling_response);
+        mock.expect(&[0x22, 0x01, 0x05])
+            .respond(&[0x62, 0x01, 0x05, 0x64]);
+        let client = UdsClient::new_from_transport(mock.clone());
+
+        assert_eq!(
+            client.read_scaled_data_by_identifier(0x0105).await,
+            Ok(vec![ScaledValue {
+                value: PhysicalValue::Numeric(45.0),
+                unit: Some("km/h".to_string()),
+            }])
+        );
+        mock.assert_done();
+    }
+}
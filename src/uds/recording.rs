@@ -0,0 +1,381 @@
+//! # Recording and replaying the UDS conversation
+//!
+//! [RecordingTransport] wraps any [UdsTransport] and writes every sent and received PDU into a
+//! text file. [ReplayTransport] reads such file and serves the recorded responses back to the
+//! [UdsClient](crate::UdsClient), so the issue captured in the field can be reproduced offline.
+//!
+//! ## File format
+//! One PDU per line, lines starting with `#` are comments:
+//! ```text
+//! # uds-rs recording v1
+//! 0.000000 TX 7E0 22F190
+//! 0.012345 RX 7E8 62F19057415A5A5A
+//! ```
+//! - seconds since the start of the recording with microsecond resolution
+//! - direction, TX for requests and RX for responses
+//! - CAN ID in hex, 3 digits for standard and 8 digits for extended identifiers
+//! - whole PDU in hex, starting with SID
+//!
+use crate::uds::communication::{ExtendedId, Id, StandardId, UdsCommunicationError, UdsTransport};
+use crate::uds::mock_transport::MockTransport;
+use futures::future::BoxFuture;
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const RECORDING_HEADER: &str = "# uds-rs recording v1";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// request sent by the client
+    Tx,
+    /// response received from the server
+    Rx,
+}
+
+/// Single line of the recording
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedPdu {
+    /// time since the start of the recording
+    pub timestamp: Duration,
+    pub direction: Direction,
+    pub can_id: Id,
+    pub data: Vec<u8>,
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Direction::Tx => write!(f, "TX"),
+            Direction::Rx => write!(f, "RX"),
+        }
+    }
+}
+
+/// Seconds with microsecond resolution
+pub(crate) fn format_timestamp(timestamp: Duration) -> String {
+    format!("{}.{:06}", timestamp.as_secs(), timestamp.subsec_micros())
+}
+
+/// 3 hex digits for standard and 8 hex digits for extended identifier, as used by candump
+pub(crate) fn format_can_id(can_id: Id) -> String {
+    match can_id {
+        Id::Standard(id) => format!("{:03X}", id.as_raw()),
+        Id::Extended(id) => format!("{:08X}", id.as_raw()),
+    }
+}
+
+pub(crate) fn format_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+impl fmt::Display for RecordedPdu {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {}",
+            format_timestamp(self.timestamp),
+            self.direction,
+            format_can_id(self.can_id),
+            format_hex(&self.data)
+        )
+    }
+}
+
+impl FromStr for RecordedPdu {
+    type Err = std::io::Error;
+
+    fn from_str(line: &str) -> Result<RecordedPdu, std::io::Error> {
+        let invalid = || {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Invalid recording line: {}", line),
+            )
+        };
+        let mut fields = line.split_whitespace();
+        let timestamp = fields
+            .next()
+            .and_then(|t| t.parse().ok())
+            .and_then(|t| Duration::try_from_secs_f64(t).ok())
+            .ok_or_else(invalid)?;
+        let direction = match fields.next() {
+            Some("TX") => Direction::Tx,
+            Some("RX") => Direction::Rx,
+            _ => return Err(invalid()),
+        };
+        let raw_id = fields.next().ok_or_else(invalid)?;
+        let id_value = u32::from_str_radix(raw_id, 16).map_err(|_| invalid())?;
+        let can_id = if raw_id.len() > 3 {
+            Id::Extended(ExtendedId::new(id_value).ok_or_else(invalid)?)
+        } else {
+            Id::Standard(StandardId::new(id_value as u16).ok_or_else(invalid)?)
+        };
+        let raw_data = fields.next().unwrap_or("");
+        if !raw_data.is_ascii() || raw_data.len() % 2 == 1 || fields.next().is_some() {
+            return Err(invalid());
+        }
+        let data = (0..raw_data.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&raw_data[i..i + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| invalid())?;
+        Ok(RecordedPdu {
+            timestamp,
+            direction,
+            can_id,
+            data,
+        })
+    }
+}
+
+/// Read whole recording, skipping empty lines and comments
+pub fn read_recording(reader: impl BufRead) -> Result<Vec<RecordedPdu>, std::io::Error> {
+    let mut records = vec![];
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        records.push(line.parse()?);
+    }
+    Ok(records)
+}
+
+/// Transport wrapper writing all sent and received PDUs into the recording, see module
+/// documentation for the format.
+///
+/// CAN IDs are not known to the [UdsTransport], so they are provided by the user and written
+/// as they are.
+pub struct RecordingTransport {
+    inner: Box<dyn UdsTransport>,
+    tx_id: Id,
+    rx_id: Id,
+    start: Instant,
+    output: Mutex<Box<dyn Write + Send>>,
+}
+
+impl RecordingTransport {
+    pub fn new(
+        inner: impl UdsTransport + 'static,
+        tx_id: impl Into<Id>,
+        rx_id: impl Into<Id>,
+        output: impl Write + Send + 'static,
+    ) -> Result<RecordingTransport, std::io::Error> {
+        let mut output: Box<dyn Write + Send> = Box::new(output);
+        writeln!(output, "{}", RECORDING_HEADER)?;
+        output.flush()?;
+        Ok(RecordingTransport {
+            inner: Box::new(inner),
+            tx_id: tx_id.into(),
+            rx_id: rx_id.into(),
+            start: Instant::now(),
+            output: Mutex::new(output),
+        })
+    }
+
+    /// Record into newly created file
+    pub fn create(
+        inner: impl UdsTransport + 'static,
+        tx_id: impl Into<Id>,
+        rx_id: impl Into<Id>,
+        path: impl AsRef<Path>,
+    ) -> Result<RecordingTransport, std::io::Error> {
+        let file = BufWriter::new(File::create(path)?);
+        RecordingTransport::new(inner, tx_id, rx_id, file)
+    }
+
+    fn record(&self, direction: Direction, data: &[u8]) {
+        let record = RecordedPdu {
+            timestamp: self.start.elapsed(),
+            direction,
+            can_id: match direction {
+                Direction::Tx => self.tx_id,
+                Direction::Rx => self.rx_id,
+            },
+            data: data.to_vec(),
+        };
+        let mut output = self.output.lock().unwrap();
+        // the recording must never break the communication itself
+        if let Err(e) = writeln!(output, "{}", record).and_then(|_| output.flush()) {
+            warn!("Writing recording failed: {}", e);
+        }
+    }
+}
+
+impl UdsTransport for RecordingTransport {
+    fn send<'a>(&'a self, payload: &'a [u8]) -> BoxFuture<'a, Result<(), UdsCommunicationError>> {
+        Box::pin(async move {
+            self.record(Direction::Tx, payload);
+            self.inner.send(payload).await
+        })
+    }
+
+    fn receive(&self) -> BoxFuture<'_, Result<Vec<u8>, UdsCommunicationError>> {
+        Box::pin(async move {
+            let response = self.inner.receive().await?;
+            self.record(Direction::Rx, &response);
+            Ok(response)
+        })
+    }
+}
+
+/// Transport serving responses from the recording.
+///
+/// Each recorded request has to be sent by the client in the recorded order, responses recorded
+/// after it are served back. With `original_timing` responses are delayed the same way as during
+/// the recording, otherwise they are served immediately. Mismatches can be checked by
+/// [ReplayTransport::assert_done].
+#[derive(Clone)]
+pub struct ReplayTransport {
+    mock: MockTransport,
+}
+
+impl ReplayTransport {
+    pub fn new(records: &[RecordedPdu], original_timing: bool) -> ReplayTransport {
+        let mock = MockTransport::new();
+        let mut records = records.iter().peekable();
+        // responses recorded before the first request are unsolicited
+        while let Some(record) = records.next_if(|r| r.direction == Direction::Rx) {
+            mock.push(&record.data);
+        }
+        while let Some(request) = records.next() {
+            let mut expectation = mock.expect(&request.data);
+            let mut previous = request.timestamp;
+            while let Some(response) = records.next_if(|r| r.direction == Direction::Rx) {
+                let delay = if original_timing {
+                    response.timestamp.saturating_sub(previous)
+                } else {
+                    Duration::ZERO
+                };
+                previous = response.timestamp;
+                expectation = expectation.respond_after(delay, &response.data);
+            }
+        }
+        ReplayTransport { mock }
+    }
+
+    pub fn open(
+        path: impl AsRef<Path>,
+        original_timing: bool,
+    ) -> Result<ReplayTransport, std::io::Error> {
+        let records = read_recording(BufReader::new(File::open(path)?))?;
+        Ok(ReplayTransport::new(&records, original_timing))
+    }
+
+    /// Requests sent by the client, which did not match the recording
+    pub fn unexpected_requests(&self) -> Vec<Vec<u8>> {
+        self.mock.unexpected_requests()
+    }
+
+    /// Panics if the client did not send exactly the recorded requests
+    pub fn assert_done(&self) {
+        self.mock.assert_done()
+    }
+}
+
+impl UdsTransport for ReplayTransport {
+    fn send<'a>(&'a self, payload: &'a [u8]) -> BoxFuture<'a, Result<(), UdsCommunicationError>> {
+        self.mock.send(payload)
+    }
+
+    fn receive(&self) -> BoxFuture<'_, Result<Vec<u8>, UdsCommunicationError>> {
+        self.mock.receive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uds::{DataFormat, ReadDTCInformationResponse, UdsClient, UdsResponse};
+    use std::sync::Arc;
+
+    /// Writer shared with the test
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_line_roundtrip() {
+        let record = RecordedPdu {
+            timestamp: Duration::from_micros(1_012_345),
+            direction: Direction::Rx,
+            can_id: Id::Extended(ExtendedId::new(0x18daf110).unwrap()),
+            data: vec![0x62, 0xf1, 0x90],
+        };
+        let line = record.to_string();
+        assert_eq!(line, "1.012345 RX 18DAF110 62F190");
+        assert_eq!(line.parse::<RecordedPdu>().unwrap(), record);
+
+        let standard: RecordedPdu = "0.5 TX 7E0 1003".parse().unwrap();
+        assert_eq!(
+            standard.can_id,
+            Id::Standard(StandardId::new(0x7e0).unwrap())
+        );
+        assert!("0.5 TX 7E0 100".parse::<RecordedPdu>().is_err());
+        assert!("0.5 XX 7E0 1003".parse::<RecordedPdu>().is_err());
+    }
+
+    #[test]
+    fn test_malformed_line_is_rejected() {
+        assert!("-0.5 TX 7E0 1003".parse::<RecordedPdu>().is_err());
+        assert!("NaN TX 7E0 1003".parse::<RecordedPdu>().is_err());
+        assert!("inf TX 7E0 1003".parse::<RecordedPdu>().is_err());
+        assert!("0.5 TX 7E0 1é1".parse::<RecordedPdu>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_record_and_replay() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x19, 0x02, 0xff])
+            .respond_pending(0x19)
+            .respond(&[0x59, 0x02, 0xff, 0x12, 0x34, 0x56, 0x2f]);
+        let buffer = SharedBuffer::default();
+        let recorder = RecordingTransport::new(
+            mock.clone(),
+            StandardId::new(0x7e0).unwrap(),
+            StandardId::new(0x7e8).unwrap(),
+            buffer.clone(),
+        )
+        .unwrap();
+        let client = UdsClient::new_from_transport(recorder);
+        let recorded_result = client.report_dtc_by_status_mask(0xff).await;
+        assert!(matches!(
+            recorded_result,
+            Ok(UdsResponse::ReadDTCInformation(DataFormat::Parsed(
+                ReadDTCInformationResponse::ReportDTCByStatusMask(_)
+            )))
+        ));
+        drop(client);
+
+        let recording = buffer.0.lock().unwrap().clone();
+        let records = read_recording(recording.as_slice()).unwrap();
+        let directions: Vec<Direction> = records.iter().map(|r| r.direction).collect();
+        assert_eq!(
+            directions,
+            vec![Direction::Tx, Direction::Rx, Direction::Rx]
+        );
+
+        let replay = ReplayTransport::new(&records, true);
+        let client = UdsClient::new_from_transport(replay.clone());
+        let replayed_result = client.report_dtc_by_status_mask(0xff).await;
+        assert_eq!(replayed_result, recorded_result);
+        replay.assert_done();
+    }
+}
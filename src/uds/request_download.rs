@@ -0,0 +1,328 @@
+//! # Implementation of RequestDownload 0x34 service
+//!
+//! This module provides following methods for UdsClient:
+//!
+//! [UdsClient::request_download]
+//! [UdsClient::request_download_simplified]
+//! [UdsClient::download]
+//! [UdsClient::download_with_data_format]
+//!
+//! [UdsClient::download] performs the whole transfer - RequestDownload, TransferData for each
+//! block of the negotiated size and RequestTransferExit. Erasing the memory before and checking
+//! it afterwards is ECU specific, usually done by [UdsClient::start_routine] with
+//! [ROUTINE_ERASE_MEMORY] and [ROUTINE_CHECK_MEMORY].
+//!
+use super::*;
+use crate::uds::read_memory_by_address::convert_from_simple_to_normal;
+use crate::uds::transfer_data::{
+    block_data_len, compose_request_transfer_request, next_block_sequence_counter,
+    parse_max_number_of_block_length, DATA_FORMAT_UNCOMPRESSED,
+};
+use crate::uds::uds_definitions::SEND_RECEIVE_SID_OFFSET;
+
+const REQUEST_DOWNLOAD_SID: u8 = 0x34;
+
+#[derive(Debug, PartialEq)]
+pub struct RequestDownloadResponse {
+    /// Length of the whole TransferData request including SID and block sequence counter
+    pub max_number_of_block_length: u64,
+}
+
+impl UdsClient {
+    /// `data_format_identifier` - high nibble is compression method, low nibble encryption method,
+    /// 0x00 for none.
+    ///
+    /// Memory address and size are encoded in u8 slice, MSB first, their lengths are given by
+    /// `address_and_length_format_identifier`.
+    pub async fn request_download(
+        &self,
+        data_format_identifier: u8,
+        address_and_length_format_identifier: u8,
+        memory_address: &[u8],
+        memory_size: &[u8],
+    ) -> EcuResponseResult {
+        let request = compose_request_transfer_request(
+            REQUEST_DOWNLOAD_SID,
+            data_format_identifier,
+            address_and_length_format_identifier,
+            memory_address,
+            memory_size,
+        );
+        let raw_response = self.send_and_receive(&request).await?;
+        parse_request_download_response(&raw_response)
+    }
+
+    /// address_and_length_format_identifier is derived from provided arguments the same way as in
+    /// [UdsClient::read_memory_by_address_simplified]
+    pub async fn request_download_simplified(
+        &self,
+        data_format_identifier: u8,
+        memory_address: u64,
+        memory_size: u64,
+        memory_address_len: Option<u8>,
+        memory_size_len: Option<u8>,
+    ) -> EcuResponseResult {
+        let (address_and_length_format_identifier, memory_address, memory_size) =
+            convert_from_simple_to_normal(
+                memory_address,
+                memory_size,
+                memory_address_len,
+                memory_size_len,
+            )?;
+        self.request_download(
+            data_format_identifier,
+            address_and_length_format_identifier,
+            &memory_address,
+            &memory_size,
+        )
+        .await
+    }
+
+    /// Download uncompressed and unencrypted data to the memory starting at `memory_address`.
+    ///
+    /// `progress` is called after each transferred block with number of transferred bytes and
+    /// total number of bytes. When the transfer fails after RequestDownload was accepted,
+    /// [UdsError::TransferAborted] is returned.
+    pub async fn download(
+        &self,
+        memory_address: u64,
+        data: &[u8],
+        progress: impl FnMut(usize, usize),
+    ) -> Result<(), UdsError> {
+        self.download_with_data_format(DATA_FORMAT_UNCOMPRESSED, memory_address, data, progress)
+            .await
+    }
+
+    /// Same as [UdsClient::download], but the data are already compressed or encrypted as
+    /// described by `data_format_identifier`
+    pub async fn download_with_data_format(
+        &self,
+        data_format_identifier: u8,
+        memory_address: u64,
+        data: &[u8],
+        mut progress: impl FnMut(usize, usize),
+    ) -> Result<(), UdsError> {
+        let (address_and_length_format_identifier, encoded_address, encoded_size) =
+            convert_from_simple_to_normal(memory_address, data.len() as u64, None, None)?;
+        let request = compose_request_transfer_request(
+            REQUEST_DOWNLOAD_SID,
+            data_format_identifier,
+            address_and_length_format_identifier,
+            &encoded_address,
+            &encoded_size,
+        );
+        let raw_response = self.send_and_receive(&request).await?;
+        let download =
+            expect_parsed(
+                &raw_response,
+                parse_request_download_response,
+                |response| match response {
+                    UdsResponse::RequestDownload(DataFormat::Parsed(download)) => Some(download),
+                    _ => None,
+                },
+            )?;
+        let block_len = block_data_len(download.max_number_of_block_length, &raw_response)?;
+        info!(
+            "Downloading {} bytes to {:#x} in blocks of {} bytes",
+            data.len(),
+            memory_address,
+            block_len
+        );
+
+        let mut transferred = 0;
+        let mut block_sequence_counter = 0x01;
+        for block in data.chunks(block_len) {
+            if let Err(reason) = self.transfer_block(block_sequence_counter, block).await {
+                error!(
+                    "Download aborted after {} of {} bytes: {:?}",
+                    transferred,
+                    data.len(),
+                    reason
+                );
+                return Err(UdsError::TransferAborted {
+                    transferred,
+                    reason: Box::new(reason),
+                });
+            }
+            transferred += block.len();
+            progress(transferred, data.len());
+            block_sequence_counter = next_block_sequence_counter(block_sequence_counter);
+        }
+        self.request_transfer_exit(&[])
+            .await
+            .map_err(|reason| UdsError::TransferAborted {
+                transferred,
+                reason: Box::new(reason),
+            })?;
+        Ok(())
+    }
+}
+
+pub(super) fn parse_request_download_response(raw_response: &[u8]) -> EcuResponseResult {
+    let mut response_iter = raw_response.iter();
+    let sid = *response_iter.next().ok_or(UdsError::ResponseEmpty)?;
+    if sid != REQUEST_DOWNLOAD_SID + SEND_RECEIVE_SID_OFFSET {
+        return Err(UdsError::SidMismatch {
+            expected: REQUEST_DOWNLOAD_SID + SEND_RECEIVE_SID_OFFSET,
+            received: sid,
+            raw_message: raw_response.to_vec(),
+        });
+    }
+    let max_number_of_block_length =
+        parse_max_number_of_block_length(&mut response_iter, raw_response)?;
+    Ok(UdsResponse::RequestDownload(DataFormat::Parsed(
+        RequestDownloadResponse {
+            max_number_of_block_length,
+        },
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compose_request() {
+        assert_eq!(
+            compose_request_transfer_request(
+                REQUEST_DOWNLOAD_SID,
+                0x00,
+                0x44,
+                &[0x00, 0x01, 0x00, 0x00],
+                &[0, 0, 1, 0]
+            ),
+            vec![0x34, 0x00, 0x44, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_download() {
+        let data: Vec<u8> = (0..10).collect();
+        let mock = MockTransport::new();
+        mock.expect(&[0x34, 0x00, 0x13, 0x01, 0x00, 0x00, 0x0a])
+            .respond(&[0x74, 0x10, 0x06]);
+        mock.expect(&[0x36, 0x01, 0, 1, 2, 3])
+            .respond(&[0x76, 0x01]);
+        mock.expect(&[0x36, 0x02, 4, 5, 6, 7])
+            .respond(&[0x76, 0x02]);
+        mock.expect(&[0x36, 0x03, 8, 9]).respond(&[0x76, 0x03]);
+        mock.expect(&[0x37]).respond(&[0x77]);
+        let client = UdsClient::new_from_transport(mock.clone());
+
+        let mut reported = vec![];
+        let result = client
+            .download(0x010000, &data, |transferred, total| {
+                reported.push((transferred, total))
+            })
+            .await;
+        assert_eq!(result, Ok(()));
+        assert_eq!(reported, vec![(4, 10), (8, 10), (10, 10)]);
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_repeated_block_rejected_as_already_accepted() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x34, 0x00, 0x11, 0x10, 0x04])
+            .respond(&[0x74, 0x10, 0x04]);
+        mock.expect(&[0x36, 0x01, 0, 1]);
+        mock.expect(&[0x36, 0x01, 0, 1]).respond_nrc(0x36, 0x73);
+        mock.expect(&[0x36, 0x02, 2, 3]).respond(&[0x76, 0x02]);
+        mock.expect(&[0x37]).respond(&[0x77]);
+        let client = UdsClient::new_from_transport(mock.clone());
+        client.set_response_timing(ResponseTiming {
+            p2: Duration::from_millis(20),
+            p2_star: Duration::from_millis(100),
+            network_margin: Duration::ZERO,
+        });
+
+        assert_eq!(
+            client.download(0x10, &[0, 1, 2, 3], |_, _| {}).await,
+            Ok(())
+        );
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_wrong_block_sequence_counter_without_repeat() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x34, 0x00, 0x11, 0x10, 0x02])
+            .respond(&[0x74, 0x10, 0x04]);
+        mock.expect(&[0x36, 0x01, 0, 1]).respond_nrc(0x36, 0x73);
+        let client = UdsClient::new_from_transport(mock.clone());
+
+        assert_eq!(
+            client.download(0x10, &[0, 1], |_, _| {}).await,
+            Err(UdsError::TransferAborted {
+                transferred: 0,
+                reason: Box::new(UdsError::NRC {
+                    nrc: NrcData {
+                        rejected_sid: 0x36,
+                        nrc: NegativeResponseCode::WrongBlockSequenceCounter
+                    }
+                })
+            })
+        );
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_download_block_length_too_short() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x34, 0x00, 0x11, 0x10, 0x04])
+            .respond(&[0x74, 0x10, 0x02]);
+        let client = UdsClient::new_from_transport(mock.clone());
+
+        assert_eq!(
+            client.download(0x10, &[0, 1, 2, 3], |_, _| {}).await,
+            Err(UdsError::ResponseIncorrect {
+                raw_message: vec![0x74, 0x10, 0x02]
+            })
+        );
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_download_suspended() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x34, 0x00, 0x11, 0x10, 0x04])
+            .respond(&[0x74, 0x10, 0x04]);
+        mock.expect(&[0x36, 0x01, 0, 1]).respond(&[0x76, 0x01]);
+        mock.expect(&[0x36, 0x02, 2, 3]).respond_nrc(0x36, 0x71);
+        let client = UdsClient::new_from_transport(mock.clone());
+
+        let result = client.download(0x10, &[0, 1, 2, 3], |_, _| {}).await;
+        assert_eq!(
+            result,
+            Err(UdsError::TransferAborted {
+                transferred: 2,
+                reason: Box::new(UdsError::NRC {
+                    nrc: NrcData {
+                        rejected_sid: 0x36,
+                        nrc: NegativeResponseCode::TransferDataSuspended
+                    }
+                })
+            })
+        );
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_lost_block_response_is_repeated() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x34, 0x00, 0x11, 0x10, 0x02])
+            .respond(&[0x74, 0x10, 0x04]);
+        mock.expect(&[0x36, 0x01, 0, 1]);
+        mock.expect(&[0x36, 0x01, 0, 1]).respond(&[0x76, 0x01]);
+        mock.expect(&[0x37]).respond(&[0x77]);
+        let client = UdsClient::new_from_transport(mock.clone());
+        client.set_response_timing(ResponseTiming {
+            p2: Duration::from_millis(20),
+            p2_star: Duration::from_millis(100),
+            network_margin: Duration::ZERO,
+        });
+
+        assert_eq!(client.download(0x10, &[0, 1], |_, _| {}).await, Ok(()));
+        mock.assert_done();
+    }
+}
@@ -0,0 +1,347 @@
+//! # Implementation of RequestUpload 0x35 service
+//!
+//! This module provides following methods for UdsClient:
+//!
+//! [UdsClient::request_upload]
+//! [UdsClient::request_upload_simplified]
+//! [UdsClient::upload]
+//! [UdsClient::upload_into]
+//!
+//! Unlike [UdsClient::read_memory_by_address], which is limited to single response, the upload
+//! reads memory of any size block by block - RequestUpload, TransferData until all requested
+//! bytes are received and RequestTransferExit.
+//!
+use super::*;
+use crate::uds::read_memory_by_address::convert_from_simple_to_normal;
+use crate::uds::transfer_data::{
+    block_data_len, compose_request_transfer_request, next_block_sequence_counter,
+    parse_max_number_of_block_length, DATA_FORMAT_UNCOMPRESSED,
+};
+use crate::uds::uds_definitions::SEND_RECEIVE_SID_OFFSET;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+const REQUEST_UPLOAD_SID: u8 = 0x35;
+
+#[derive(Debug, PartialEq)]
+pub struct RequestUploadResponse {
+    /// Length of the whole TransferData response including SID and block sequence counter
+    pub max_number_of_block_length: u64,
+}
+
+impl UdsClient {
+    /// `data_format_identifier` - high nibble is compression method, low nibble encryption method,
+    /// 0x00 for none.
+    ///
+    /// Memory address and size are encoded in u8 slice, MSB first, their lengths are given by
+    /// `address_and_length_format_identifier`.
+    pub async fn request_upload(
+        &self,
+        data_format_identifier: u8,
+        address_and_length_format_identifier: u8,
+        memory_address: &[u8],
+        memory_size: &[u8],
+    ) -> EcuResponseResult {
+        let request = compose_request_transfer_request(
+            REQUEST_UPLOAD_SID,
+            data_format_identifier,
+            address_and_length_format_identifier,
+            memory_address,
+            memory_size,
+        );
+        let raw_response = self.send_and_receive(&request).await?;
+        parse_request_upload_response(&raw_response)
+    }
+
+    /// address_and_length_format_identifier is derived from provided arguments the same way as in
+    /// [UdsClient::read_memory_by_address_simplified]
+    pub async fn request_upload_simplified(
+        &self,
+        data_format_identifier: u8,
+        memory_address: u64,
+        memory_size: u64,
+        memory_address_len: Option<u8>,
+        memory_size_len: Option<u8>,
+    ) -> EcuResponseResult {
+        let (address_and_length_format_identifier, memory_address, memory_size) =
+            convert_from_simple_to_normal(
+                memory_address,
+                memory_size,
+                memory_address_len,
+                memory_size_len,
+            )?;
+        self.request_upload(
+            data_format_identifier,
+            address_and_length_format_identifier,
+            &memory_address,
+            &memory_size,
+        )
+        .await
+    }
+
+    /// Read `length` bytes of memory starting at `memory_address`
+    pub async fn upload(&self, memory_address: u64, length: usize) -> Result<Vec<u8>, UdsError> {
+        let mut data = Vec::with_capacity(length);
+        self.upload_into(memory_address, length, &mut data).await?;
+        Ok(data)
+    }
+
+    /// Read `length` bytes of memory starting at `memory_address` and write each received block
+    /// to `sink`. The sink is flushed before the transfer is finished.
+    ///
+    /// When the transfer fails after RequestUpload was accepted, [UdsError::TransferAborted] is
+    /// returned, bytes received up to that point were already written.
+    pub async fn upload_into(
+        &self,
+        memory_address: u64,
+        length: usize,
+        sink: &mut (impl AsyncWrite + Unpin),
+    ) -> Result<(), UdsError> {
+        let (address_and_length_format_identifier, encoded_address, encoded_size) =
+            convert_from_simple_to_normal(memory_address, length as u64, None, None)?;
+        let request = compose_request_transfer_request(
+            REQUEST_UPLOAD_SID,
+            DATA_FORMAT_UNCOMPRESSED,
+            address_and_length_format_identifier,
+            &encoded_address,
+            &encoded_size,
+        );
+        let raw_response = self.send_and_receive(&request).await?;
+        let upload =
+            expect_parsed(
+                &raw_response,
+                parse_request_upload_response,
+                |response| match response {
+                    UdsResponse::RequestUpload(DataFormat::Parsed(upload)) => Some(upload),
+                    _ => None,
+                },
+            )?;
+        let block_len = block_data_len(upload.max_number_of_block_length, &raw_response)?;
+        info!(
+            "Uploading {} bytes from {:#x} in blocks of up to {} bytes",
+            length, memory_address, block_len
+        );
+
+        let mut transferred = 0;
+        let mut block_sequence_counter = 0x01;
+        while transferred < length {
+            let result = self
+                .upload_block(
+                    block_sequence_counter,
+                    block_len,
+                    length - transferred,
+                    sink,
+                )
+                .await;
+            match result {
+                Ok(received) => transferred += received,
+                Err(reason) => {
+                    error!(
+                        "Upload aborted after {} of {} bytes: {:?}",
+                        transferred, length, reason
+                    );
+                    return Err(UdsError::TransferAborted {
+                        transferred,
+                        reason: Box::new(reason),
+                    });
+                }
+            }
+            block_sequence_counter = next_block_sequence_counter(block_sequence_counter);
+        }
+        sink.flush().await.map_err(|e| {
+            error!("Flushing uploaded data failed: {:?}", e);
+            UdsError::TransferAborted {
+                transferred,
+                reason: Box::new(UdsError::SinkError { kind: e.kind() }),
+            }
+        })?;
+        self.request_transfer_exit(&[])
+            .await
+            .map_err(|reason| UdsError::TransferAborted {
+                transferred,
+                reason: Box::new(reason),
+            })?;
+        Ok(())
+    }
+
+    /// Receive single block, check its length and write it to the sink
+    async fn upload_block(
+        &self,
+        block_sequence_counter: u8,
+        block_len: usize,
+        remaining: usize,
+        sink: &mut (impl AsyncWrite + Unpin),
+    ) -> Result<usize, UdsError> {
+        let Some(block) = self.transfer_block(block_sequence_counter, &[]).await? else {
+            error!(
+                "Response with data of block {:#x} was lost",
+                block_sequence_counter
+            );
+            return Err(UdsError::BlockLost {
+                block_sequence_counter,
+            });
+        };
+        if block.is_empty() || block.len() > block_len || block.len() > remaining {
+            error!(
+                "Block {:#x} has {} bytes, expected up to {} bytes",
+                block_sequence_counter,
+                block.len(),
+                block_len.min(remaining)
+            );
+            return Err(UdsError::InvalidLength { raw_message: block });
+        }
+        sink.write_all(&block)
+            .await
+            .map_err(|e| UdsError::SinkError { kind: e.kind() })?;
+        Ok(block.len())
+    }
+}
+
+pub(super) fn parse_request_upload_response(raw_response: &[u8]) -> EcuResponseResult {
+    let mut response_iter = raw_response.iter();
+    let sid = *response_iter.next().ok_or(UdsError::ResponseEmpty)?;
+    if sid != REQUEST_UPLOAD_SID + SEND_RECEIVE_SID_OFFSET {
+        return Err(UdsError::SidMismatch {
+            expected: REQUEST_UPLOAD_SID + SEND_RECEIVE_SID_OFFSET,
+            received: sid,
+            raw_message: raw_response.to_vec(),
+        });
+    }
+    let max_number_of_block_length =
+        parse_max_number_of_block_length(&mut response_iter, raw_response)?;
+    Ok(UdsResponse::RequestUpload(DataFormat::Parsed(
+        RequestUploadResponse {
+            max_number_of_block_length,
+        },
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_response() {
+        assert_eq!(
+            parse_request_upload_response(&[0x75, 0x20, 0x01, 0x02]),
+            Ok(UdsResponse::RequestUpload(DataFormat::Parsed(
+                RequestUploadResponse {
+                    max_number_of_block_length: 0x102
+                }
+            )))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_upload() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x35, 0x00, 0x12, 0x80, 0x00, 0x05])
+            .respond(&[0x75, 0x10, 0x05]);
+        mock.expect(&[0x36, 0x01]).respond(&[0x76, 0x01, 0, 1, 2]);
+        mock.expect(&[0x36, 0x02]).respond(&[0x76, 0x02, 3, 4]);
+        mock.expect(&[0x37]).respond(&[0x77]);
+        let client = UdsClient::new_from_transport(mock.clone());
+
+        assert_eq!(client.upload(0x8000, 5).await, Ok(vec![0, 1, 2, 3, 4]));
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_upload_into_flushes_sink() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x35, 0x00, 0x11, 0x10, 0x03])
+            .respond(&[0x75, 0x10, 0x05]);
+        mock.expect(&[0x36, 0x01]).respond(&[0x76, 0x01, 0, 1, 2]);
+        mock.expect(&[0x37]).respond(&[0x77]);
+        let client = UdsClient::new_from_transport(mock.clone());
+
+        let mut sink = tokio::io::BufWriter::new(vec![]);
+        assert_eq!(client.upload_into(0x10, 3, &mut sink).await, Ok(()));
+        assert_eq!(sink.get_ref(), &vec![0, 1, 2]);
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_upload_block_lost() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x35, 0x00, 0x11, 0x10, 0x02])
+            .respond(&[0x75, 0x10, 0x04]);
+        mock.expect(&[0x36, 0x01]);
+        mock.expect(&[0x36, 0x01]).respond_nrc(0x36, 0x73);
+        let client = UdsClient::new_from_transport(mock.clone());
+        client.set_response_timing(ResponseTiming {
+            p2: Duration::from_millis(20),
+            p2_star: Duration::from_millis(100),
+            network_margin: Duration::ZERO,
+        });
+
+        assert_eq!(
+            client.upload(0x10, 2).await,
+            Err(UdsError::TransferAborted {
+                transferred: 0,
+                reason: Box::new(UdsError::BlockLost {
+                    block_sequence_counter: 0x01
+                })
+            })
+        );
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_upload_block_sequence_counter_mismatch() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x35, 0x00, 0x11, 0x10, 0x04])
+            .respond(&[0x75, 0x10, 0x04]);
+        mock.expect(&[0x36, 0x01]).respond(&[0x76, 0x01, 0, 1]);
+        mock.expect(&[0x36, 0x02]).respond(&[0x76, 0x01, 0, 1]);
+        let client = UdsClient::new_from_transport(mock.clone());
+
+        let mut sink = vec![];
+        assert_eq!(
+            client.upload_into(0x10, 4, &mut sink).await,
+            Err(UdsError::TransferAborted {
+                transferred: 2,
+                reason: Box::new(UdsError::ResponseIncorrect {
+                    raw_message: vec![0x76, 0x01, 0, 1]
+                })
+            })
+        );
+        assert_eq!(sink, vec![0, 1]);
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_upload_block_length_too_short() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x35, 0x00, 0x11, 0x10, 0x02])
+            .respond(&[0x75, 0x10, 0x02]);
+        let client = UdsClient::new_from_transport(mock.clone());
+
+        assert_eq!(
+            client.upload(0x10, 2).await,
+            Err(UdsError::ResponseIncorrect {
+                raw_message: vec![0x75, 0x10, 0x02]
+            })
+        );
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_upload_block_too_long() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x35, 0x00, 0x11, 0x10, 0x02])
+            .respond(&[0x75, 0x10, 0x04]);
+        mock.expect(&[0x36, 0x01]).respond(&[0x76, 0x01, 0, 1, 2]);
+        let client = UdsClient::new_from_transport(mock.clone());
+
+        assert_eq!(
+            client.upload(0x10, 2).await,
+            Err(UdsError::TransferAborted {
+                transferred: 0,
+                reason: Box::new(UdsError::InvalidLength {
+                    raw_message: vec![0, 1, 2]
+                })
+            })
+        );
+        mock.assert_done();
+    }
+}
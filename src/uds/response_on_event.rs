@@ -0,0 +1,659 @@
+//! # Implementation of ResponseOnEvent 0x86 service
+//!
+//! This module provides following methods for UdsClient:
+//!
+//! [UdsClient::response_on_event]
+//! [UdsClient::response_on_event_suppressed]
+//! [UdsClient::setup_response_on_event]
+//! [UdsClient::start_response_on_event]
+//! [UdsClient::start_response_on_event_suppressed]
+//! [UdsClient::stop_response_on_event]
+//! [UdsClient::stop_response_on_event_suppressed]
+//! [UdsClient::clear_response_on_event]
+//! [UdsClient::clear_response_on_event_suppressed]
+//! [UdsClient::report_activated_events]
+//! [UdsClient::subscribe_response_on_event]
+//!
+//! When the event occurs, server sends response to the serviceToRespondTo request without any
+//! request from the client. These responses are published as unsolicited responses,
+//! [UdsClient::subscribe_response_on_event] returns [EventResponses] stream of the responses to
+//! the subscribed service only, decoded the same way as by [decode_response].
+//!
+//! The subscribed serviceToRespondTo is registered with the receive task, so when the event
+//! occurs while request to the same service is pending, responses are told apart by the echoed
+//! identifier, e.g. data identifier of ReadDataByIdentifier.
+//!
+use super::*;
+use crate::uds::dispatcher::{echoed_identifier, EventRegistration};
+use crate::uds::log_decoder::decode_response;
+use crate::uds::stop_on_drop::{StopOnDrop, StopRequest};
+use crate::uds::uds_definitions::{
+    compose_sub_function, parse_sub_function, NEGATIVE_RESPONSE_SID, SEND_RECEIVE_SID_OFFSET,
+};
+use futures::future::{BoxFuture, FutureExt};
+use futures::stream::{BoxStream, Stream, StreamExt};
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+const RESPONSE_ON_EVENT_SID: u8 = 0x86;
+/// storageState bit of the eventType, event is stored in non-volatile memory
+const STORE_EVENT_BIT: u8 = 0x40;
+const EVENT_TYPE_MASK: u8 = 0x3F;
+
+/// Event window lasts until the event is stopped or cleared
+pub const EVENT_WINDOW_INFINITE: u8 = 0x02;
+/// Event window lasts until the power cycle
+pub const EVENT_WINDOW_POWER: u8 = 0x03;
+
+#[derive(IntoPrimitive, TryFromPrimitive, Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum EventType {
+    StopResponseOnEvent = 0x00,
+    OnDtcStatusChange = 0x01,
+    OnTimerInterrupt = 0x02,
+    OnChangeOfDataIdentifier = 0x03,
+    ReportActivatedEvents = 0x04,
+    StartResponseOnEvent = 0x05,
+    ClearResponseOnEvent = 0x06,
+    OnComparisonOfValues = 0x07,
+}
+
+#[derive(IntoPrimitive, TryFromPrimitive, Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum ComparisonLogic {
+    LessThan = 0x01,
+    LargerThan = 0x02,
+    Equal = 0x03,
+    NotEqual = 0x04,
+}
+
+/// Event triggering the response, defines eventType and eventTypeRecord
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// Status of any DTC changes in the bits of the mask
+    DtcStatusChange { dtc_status_mask: u8 },
+    /// Value of the data identifier changes
+    ChangeOfDataIdentifier { data_identifier: u16 },
+    /// Value at `localization` in the data identifier compared with the reference value
+    ComparisonOfValues {
+        data_identifier: u16,
+        comparison_logic: ComparisonLogic,
+        comparison_reference_value: u32,
+        hysteresis_value: u8,
+        /// sign, length and offset of the compared value in the data record, 0 compares the
+        /// whole record as unsigned value
+        localization: u16,
+    },
+}
+
+impl Event {
+    pub fn event_type(&self) -> EventType {
+        match self {
+            Event::DtcStatusChange { .. } => EventType::OnDtcStatusChange,
+            Event::ChangeOfDataIdentifier { .. } => EventType::OnChangeOfDataIdentifier,
+            Event::ComparisonOfValues { .. } => EventType::OnComparisonOfValues,
+        }
+    }
+
+    pub fn event_type_record(&self) -> Vec<u8> {
+        match self {
+            Event::DtcStatusChange { dtc_status_mask } => vec![*dtc_status_mask],
+            Event::ChangeOfDataIdentifier { data_identifier } => {
+                data_identifier.to_be_bytes().to_vec()
+            }
+            Event::ComparisonOfValues {
+                data_identifier,
+                comparison_logic,
+                comparison_reference_value,
+                hysteresis_value,
+                localization,
+            } => {
+                let mut record = data_identifier.to_be_bytes().to_vec();
+                record.push((*comparison_logic).into());
+                record.extend_from_slice(&comparison_reference_value.to_be_bytes());
+                record.push(*hysteresis_value);
+                record.extend_from_slice(&localization.to_be_bytes());
+                record
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ResponseOnEventResponse {
+    pub event_type: EventType,
+    pub store_event: bool,
+    /// number of identified events, or of activated events for reportActivatedEvents
+    pub number_of_events: u8,
+    /// eventWindowTime followed by eventTypeRecord and serviceToRespondToRecord, or records of
+    /// all activated events for reportActivatedEvents
+    pub record: Vec<u8>,
+}
+
+/// Item of [EventResponses]
+#[derive(Debug, PartialEq)]
+pub struct EventResponse {
+    pub raw_response: Vec<u8>,
+    pub response: EcuResponseResult,
+    /// time of reception
+    pub timestamp: Instant,
+}
+
+/// Stream of responses sent by the server when the subscribed event occurs. ResponseOnEvent is
+/// stopped by [EventResponses::stop], or in background when the stream is dropped without
+/// successful stop. Stopping applies to all events set up in the server.
+#[must_use = "ResponseOnEvent is stopped when the stream is dropped"]
+pub struct EventResponses {
+    inner: BoxStream<'static, EventResponse>,
+    stop: StopOnDrop<StopResponseOnEvent>,
+    _registration: EventRegistration,
+}
+
+impl EventResponses {
+    /// Stop ResponseOnEvent and wait for the response
+    pub async fn stop(mut self) -> EcuResponseResult {
+        self.stop.stop().await
+    }
+}
+
+impl Stream for EventResponses {
+    type Item = EventResponse;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<EventResponse>> {
+        self.inner.as_mut().poll_next(cx)
+    }
+}
+
+struct StopResponseOnEvent;
+
+impl StopRequest for StopResponseOnEvent {
+    fn send<'a>(&'a self, client: &'a UdsClient) -> BoxFuture<'a, EcuResponseResult> {
+        client.stop_response_on_event(EVENT_WINDOW_INFINITE).boxed()
+    }
+
+    fn describe(&self) -> String {
+        "Stopping ResponseOnEvent".to_string()
+    }
+}
+
+impl UdsClient {
+    /// Send ResponseOnEvent with any event type. `event_window_time` is not sent with
+    /// reportActivatedEvents.
+    pub async fn response_on_event(
+        &self,
+        event_type: EventType,
+        store_event: bool,
+        event_window_time: u8,
+        event_type_record: &[u8],
+        service_to_respond_to_record: &[u8],
+    ) -> EcuResponseResult {
+        self.response_on_event_suppressible(
+            event_type,
+            store_event,
+            event_window_time,
+            event_type_record,
+            service_to_respond_to_record,
+            false,
+        )
+        .await
+    }
+
+    /// Same as [UdsClient::response_on_event] with suppressed positive response. Returns
+    /// [UdsResponse::PositiveResponseSuppressed] unless the server responds with NRC.
+    pub async fn response_on_event_suppressed(
+        &self,
+        event_type: EventType,
+        store_event: bool,
+        event_window_time: u8,
+        event_type_record: &[u8],
+        service_to_respond_to_record: &[u8],
+    ) -> EcuResponseResult {
+        self.response_on_event_suppressible(
+            event_type,
+            store_event,
+            event_window_time,
+            event_type_record,
+            service_to_respond_to_record,
+            true,
+        )
+        .await
+    }
+
+    /// Set up the event, server responds to it by `service_to_respond_to_record` request once the
+    /// events are started
+    pub async fn setup_response_on_event(
+        &self,
+        event: &Event,
+        store_event: bool,
+        event_window_time: u8,
+        service_to_respond_to_record: &[u8],
+    ) -> EcuResponseResult {
+        if service_to_respond_to_record.is_empty() {
+            error!("Service to respond to has to be provided");
+            return Err(UdsError::InvalidArgument);
+        }
+        self.response_on_event(
+            event.event_type(),
+            store_event,
+            event_window_time,
+            &event.event_type_record(),
+            service_to_respond_to_record,
+        )
+        .await
+    }
+
+    pub async fn start_response_on_event(&self, event_window_time: u8) -> EcuResponseResult {
+        self.response_on_event(
+            EventType::StartResponseOnEvent,
+            false,
+            event_window_time,
+            &[],
+            &[],
+        )
+        .await
+    }
+
+    /// Same as [UdsClient::start_response_on_event] with suppressed positive response
+    pub async fn start_response_on_event_suppressed(
+        &self,
+        event_window_time: u8,
+    ) -> EcuResponseResult {
+        self.response_on_event_suppressed(
+            EventType::StartResponseOnEvent,
+            false,
+            event_window_time,
+            &[],
+            &[],
+        )
+        .await
+    }
+
+    pub async fn stop_response_on_event(&self, event_window_time: u8) -> EcuResponseResult {
+        self.response_on_event(
+            EventType::StopResponseOnEvent,
+            false,
+            event_window_time,
+            &[],
+            &[],
+        )
+        .await
+    }
+
+    /// Same as [UdsClient::stop_response_on_event] with suppressed positive response
+    pub async fn stop_response_on_event_suppressed(
+        &self,
+        event_window_time: u8,
+    ) -> EcuResponseResult {
+        self.response_on_event_suppressed(
+            EventType::StopResponseOnEvent,
+            false,
+            event_window_time,
+            &[],
+            &[],
+        )
+        .await
+    }
+
+    pub async fn clear_response_on_event(&self, event_window_time: u8) -> EcuResponseResult {
+        self.response_on_event(
+            EventType::ClearResponseOnEvent,
+            false,
+            event_window_time,
+            &[],
+            &[],
+        )
+        .await
+    }
+
+    /// Same as [UdsClient::clear_response_on_event] with suppressed positive response
+    pub async fn clear_response_on_event_suppressed(
+        &self,
+        event_window_time: u8,
+    ) -> EcuResponseResult {
+        self.response_on_event_suppressed(
+            EventType::ClearResponseOnEvent,
+            false,
+            event_window_time,
+            &[],
+            &[],
+        )
+        .await
+    }
+
+    pub async fn report_activated_events(&self) -> EcuResponseResult {
+        self.response_on_event(EventType::ReportActivatedEvents, false, 0, &[], &[])
+            .await
+    }
+
+    /// Set up and start the event and return stream of the responses sent when the event occurs
+    pub async fn subscribe_response_on_event(
+        &self,
+        event: &Event,
+        event_window_time: u8,
+        service_to_respond_to_record: &[u8],
+    ) -> Result<EventResponses, UdsError> {
+        // subscribed before the event is started, so the first response can not be missed
+        let registration = self.dispatcher.register_event(service_to_respond_to_record);
+        let service_to_respond_to = service_to_respond_to_record.to_vec();
+        let inner =
+            self.dispatcher
+                .subscribe_timestamped()
+                .filter_map(move |(timestamp, raw_response)| {
+                    let event_response = is_event_response(&raw_response, &service_to_respond_to)
+                        .then(|| EventResponse {
+                            response: decode_response(Some(&service_to_respond_to), &raw_response),
+                            raw_response,
+                            timestamp,
+                        });
+                    futures::future::ready(event_response)
+                })
+                .boxed();
+        self.setup_response_on_event(
+            event,
+            false,
+            event_window_time,
+            service_to_respond_to_record,
+        )
+        .await?;
+        if let Err(e) = self.start_response_on_event(event_window_time).await {
+            // nothing will stop the event set up in the server otherwise
+            if let Err(clear_error) = self.clear_response_on_event(event_window_time).await {
+                error!(
+                    "Clearing ResponseOnEvent after failed start failed: {:?}",
+                    clear_error
+                );
+            }
+            return Err(e);
+        }
+        Ok(EventResponses {
+            inner,
+            stop: StopOnDrop::new(self.clone(), StopResponseOnEvent),
+            _registration: registration,
+        })
+    }
+
+    async fn response_on_event_suppressible(
+        &self,
+        event_type: EventType,
+        store_event: bool,
+        event_window_time: u8,
+        event_type_record: &[u8],
+        service_to_respond_to_record: &[u8],
+        suppress_positive_response: bool,
+    ) -> EcuResponseResult {
+        let request = compose_response_on_event_request(
+            event_type,
+            store_event,
+            event_window_time,
+            event_type_record,
+            service_to_respond_to_record,
+            suppress_positive_response,
+        );
+        let Some(raw_response) = self
+            .send_and_receive_suppressible(&request, suppress_positive_response)
+            .await?
+        else {
+            return Ok(UdsResponse::PositiveResponseSuppressed);
+        };
+        parse_response_on_event_response(&raw_response)
+    }
+}
+
+/// Response to serviceToRespondTo echoing its identifier, or final ResponseOnEvent response sent
+/// at the end of the event window
+fn is_event_response(raw_response: &[u8], service_to_respond_to_record: &[u8]) -> bool {
+    let Some(&service) = service_to_respond_to_record.first() else {
+        return false;
+    };
+    match raw_response {
+        [NEGATIVE_RESPONSE_SID, rejected_sid, ..] => *rejected_sid == service,
+        [sid, ..] if *sid == RESPONSE_ON_EVENT_SID + SEND_RECEIVE_SID_OFFSET => true,
+        [sid, ..] => {
+            *sid == service + SEND_RECEIVE_SID_OFFSET
+                && echoed_identifier(service, raw_response)
+                    == echoed_identifier(service, service_to_respond_to_record)
+        }
+        [] => false,
+    }
+}
+
+fn compose_response_on_event_request(
+    event_type: EventType,
+    store_event: bool,
+    event_window_time: u8,
+    event_type_record: &[u8],
+    service_to_respond_to_record: &[u8],
+    suppress_positive_response: bool,
+) -> Vec<u8> {
+    let mut event_type_byte: u8 = event_type.into();
+    if store_event {
+        event_type_byte |= STORE_EVENT_BIT;
+    }
+    let mut request = vec![
+        RESPONSE_ON_EVENT_SID,
+        compose_sub_function(event_type_byte, suppress_positive_response),
+    ];
+    if event_type != EventType::ReportActivatedEvents {
+        request.push(event_window_time);
+    }
+    request.extend_from_slice(event_type_record);
+    request.extend_from_slice(service_to_respond_to_record);
+    request
+}
+
+pub(super) fn parse_response_on_event_response(raw_response: &[u8]) -> EcuResponseResult {
+    let mut response_iter = raw_response.iter();
+    let sid = *response_iter.next().ok_or(UdsError::ResponseEmpty)?;
+    if sid != RESPONSE_ON_EVENT_SID + SEND_RECEIVE_SID_OFFSET {
+        return Err(UdsError::SidMismatch {
+            expected: RESPONSE_ON_EVENT_SID + SEND_RECEIVE_SID_OFFSET,
+            received: sid,
+            raw_message: raw_response.to_vec(),
+        });
+    }
+    let [event_type_byte, number_of_events, ..] = *response_iter.as_slice() else {
+        return Err(UdsError::InvalidLength {
+            raw_message: raw_response.to_vec(),
+        });
+    };
+    let event_type_byte = parse_sub_function(event_type_byte);
+    let event_type =
+        EventType::try_from_primitive(event_type_byte & EVENT_TYPE_MASK).map_err(|_| {
+            UdsError::ResponseIncorrect {
+                raw_message: raw_response.to_vec(),
+            }
+        })?;
+    Ok(UdsResponse::ResponseOnEvent(DataFormat::Parsed(
+        ResponseOnEventResponse {
+            event_type,
+            store_event: event_type_byte & STORE_EVENT_BIT != 0,
+            number_of_events,
+            record: raw_response[3..].to_vec(),
+        },
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compose_request() {
+        let event = Event::ComparisonOfValues {
+            data_identifier: 0x0105,
+            comparison_logic: ComparisonLogic::LargerThan,
+            comparison_reference_value: 0x64,
+            hysteresis_value: 0x0a,
+            localization: 0x0000,
+        };
+        assert_eq!(
+            compose_response_on_event_request(
+                event.event_type(),
+                true,
+                EVENT_WINDOW_INFINITE,
+                &event.event_type_record(),
+                &[0x22, 0x01, 0x05],
+                false
+            ),
+            vec![
+                0x86, 0x47, 0x02, 0x01, 0x05, 0x02, 0x00, 0x00, 0x00, 0x64, 0x0a, 0x00, 0x00, 0x22,
+                0x01, 0x05
+            ]
+        );
+        assert_eq!(
+            compose_response_on_event_request(
+                EventType::ReportActivatedEvents,
+                false,
+                EVENT_WINDOW_INFINITE,
+                &[],
+                &[],
+                false
+            ),
+            vec![0x86, 0x04]
+        );
+    }
+
+    #[test]
+    fn test_parse_response() {
+        assert_eq!(
+            parse_response_on_event_response(&[0xc6, 0x01, 0x00, 0x02, 0x08, 0x19, 0x0e]),
+            Ok(UdsResponse::ResponseOnEvent(DataFormat::Parsed(
+                ResponseOnEventResponse {
+                    event_type: EventType::OnDtcStatusChange,
+                    store_event: false,
+                    number_of_events: 0,
+                    record: vec![0x02, 0x08, 0x19, 0x0e],
+                }
+            )))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_response_on_event() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x86, 0x03, 0x02, 0xf1, 0x90, 0x22, 0xf1, 0x90])
+            .respond(&[0x86 + 0x40, 0x03, 0x00, 0x02, 0xf1, 0x90, 0x22, 0xf1, 0x90]);
+        mock.expect(&[0x86, 0x05, 0x02])
+            .respond(&[0xc6, 0x05, 0x00, 0x02]);
+        mock.expect(&[0x86, 0x00, 0x02])
+            .respond(&[0xc6, 0x00, 0x00, 0x02]);
+        let client = UdsClient::new_from_transport(mock.clone());
+
+        let mut events = client
+            .subscribe_response_on_event(
+                &Event::ChangeOfDataIdentifier {
+                    data_identifier: 0xf190,
+                },
+                EVENT_WINDOW_INFINITE,
+                &[0x22, 0xf1, 0x90],
+            )
+            .await
+            .unwrap();
+        mock.push(&[0x6a, 0x01, 0xaa]);
+        mock.push(&[0x62, 0xf1, 0x90, 0x41, 0x42]);
+        let event = events.next().await.unwrap();
+        assert_eq!(event.raw_response, vec![0x62, 0xf1, 0x90, 0x41, 0x42]);
+        assert_eq!(
+            event.response,
+            Ok(UdsResponse::ReadDataByIdentifier(DataFormat::Parsed(
+                ReadDataByIdentifierResponse {
+                    data_records: vec![DataRecord {
+                        data_identifier: 0xf190,
+                        data: vec![0x41, 0x42],
+                    }]
+                }
+            )))
+        );
+
+        assert!(events.stop().await.is_ok());
+        mock.assert_done();
+    }
+
+    #[test]
+    fn test_is_event_response() {
+        assert!(is_event_response(
+            &[0x62, 0xf1, 0x90, 0x41],
+            &[0x22, 0xf1, 0x90]
+        ));
+        assert!(!is_event_response(
+            &[0x62, 0xf1, 0x8c, 0x41],
+            &[0x22, 0xf1, 0x90]
+        ));
+        assert!(is_event_response(&[0x7f, 0x22, 0x31], &[0x22, 0xf1, 0x90]));
+        assert!(is_event_response(&[0xc6, 0x03, 0x00], &[0x22, 0xf1, 0x90]));
+    }
+
+    #[tokio::test]
+    async fn test_event_while_request_is_pending() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x86, 0x03, 0x02, 0xf1, 0x90, 0x22, 0xf1, 0x90])
+            .respond(&[0xc6, 0x03, 0x00, 0x02, 0xf1, 0x90, 0x22, 0xf1, 0x90]);
+        mock.expect(&[0x86, 0x05, 0x02])
+            .respond(&[0xc6, 0x05, 0x00, 0x02]);
+        mock.expect(&[0x22, 0xf1, 0x8c])
+            .respond(&[0x62, 0xf1, 0x90, 0x41])
+            .respond(&[0x62, 0xf1, 0x8c, 0x01, 0x02]);
+        mock.expect(&[0x86, 0x00, 0x02])
+            .respond(&[0xc6, 0x00, 0x00, 0x02]);
+        let client = UdsClient::new_from_transport(mock.clone());
+
+        let mut events = client
+            .subscribe_response_on_event(
+                &Event::ChangeOfDataIdentifier {
+                    data_identifier: 0xf190,
+                },
+                EVENT_WINDOW_INFINITE,
+                &[0x22, 0xf1, 0x90],
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            client.read_data_by_identifier(&[0xf18c]).await,
+            Ok(UdsResponse::ReadDataByIdentifier(DataFormat::Parsed(
+                ReadDataByIdentifierResponse {
+                    data_records: vec![DataRecord {
+                        data_identifier: 0xf18c,
+                        data: vec![0x01, 0x02],
+                    }]
+                }
+            )))
+        );
+        let event = events.next().await.unwrap();
+        assert_eq!(event.raw_response, vec![0x62, 0xf1, 0x90, 0x41]);
+
+        assert!(events.stop().await.is_ok());
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_event_cleared_when_start_fails() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x86, 0x01, 0x02, 0x08, 0x19, 0x02, 0x08])
+            .respond(&[0xc6, 0x01, 0x00, 0x02, 0x08, 0x19, 0x02, 0x08]);
+        mock.expect(&[0x86, 0x05, 0x02]).respond_nrc(0x86, 0x22);
+        mock.expect(&[0x86, 0x06, 0x02])
+            .respond(&[0xc6, 0x06, 0x00, 0x02]);
+        let client = UdsClient::new_from_transport(mock.clone());
+
+        let result = client
+            .subscribe_response_on_event(
+                &Event::DtcStatusChange {
+                    dtc_status_mask: 0x08,
+                },
+                EVENT_WINDOW_INFINITE,
+                &[0x19, 0x02, 0x08],
+            )
+            .await;
+        assert!(matches!(
+            result,
+            Err(UdsError::NRC {
+                nrc: NrcData {
+                    rejected_sid: 0x86,
+                    nrc: NegativeResponseCode::ConditionsNotCorrect
+                }
+            })
+        ));
+        mock.assert_done();
+    }
+}
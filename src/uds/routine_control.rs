@@ -0,0 +1,297 @@
+//! # Implementation of RoutineControl 0x31 service
+//!
+//! This module provides following methods for UdsClient:
+//!
+//! [UdsClient::routine_control]
+//! [UdsClient::routine_control_suppressed]
+//! [UdsClient::start_routine]
+//! [UdsClient::start_routine_suppressed]
+//! [UdsClient::stop_routine]
+//! [UdsClient::stop_routine_suppressed]
+//! [UdsClient::request_routine_results]
+//!
+//! Routines defined by ISO 14229-1 are available as constants, e.g. [ROUTINE_ERASE_MEMORY].
+//!
+use super::*;
+use crate::uds::uds_definitions::{
+    compose_sub_function, parse_sub_function, SEND_RECEIVE_SID_OFFSET,
+};
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+
+const ROUTINE_CONTROL_SID: u8 = 0x31;
+
+/// Erase memory before download, option record usually contains addressAndLengthFormatIdentifier,
+/// memory address and size
+pub const ROUTINE_ERASE_MEMORY: u16 = 0xFF00;
+/// Check that the downloaded software is consistent and the ECU can start it
+pub const ROUTINE_CHECK_PROGRAMMING_DEPENDENCIES: u16 = 0xFF01;
+/// Check integrity of the downloaded memory, e.g. by checksum or signature
+pub const ROUTINE_CHECK_MEMORY: u16 = 0x0202;
+
+#[derive(IntoPrimitive, TryFromPrimitive, Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum RoutineControlType {
+    StartRoutine = 1,
+    StopRoutine = 2,
+    RequestRoutineResults = 3,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct RoutineControlResponse {
+    pub routine_control_type: RoutineControlType,
+    pub routine_identifier: u16,
+    /// manufacturer specific, None if the server responded with routine identifier only
+    pub routine_info: Option<u8>,
+    pub routine_status_record: Vec<u8>,
+}
+
+impl UdsClient {
+    pub async fn routine_control(
+        &self,
+        routine_control_type: RoutineControlType,
+        routine_identifier: u16,
+        routine_control_option_record: &[u8],
+    ) -> EcuResponseResult {
+        self.routine_control_suppressible(
+            routine_control_type,
+            routine_identifier,
+            routine_control_option_record,
+            false,
+        )
+        .await
+    }
+
+    /// Same as [UdsClient::routine_control] with suppressed positive response. Returns
+    /// [UdsResponse::PositiveResponseSuppressed] unless the server responds with NRC.
+    pub async fn routine_control_suppressed(
+        &self,
+        routine_control_type: RoutineControlType,
+        routine_identifier: u16,
+        routine_control_option_record: &[u8],
+    ) -> EcuResponseResult {
+        self.routine_control_suppressible(
+            routine_control_type,
+            routine_identifier,
+            routine_control_option_record,
+            true,
+        )
+        .await
+    }
+
+    pub async fn start_routine(
+        &self,
+        routine_identifier: u16,
+        routine_control_option_record: &[u8],
+    ) -> EcuResponseResult {
+        self.routine_control(
+            RoutineControlType::StartRoutine,
+            routine_identifier,
+            routine_control_option_record,
+        )
+        .await
+    }
+
+    /// Same as [UdsClient::start_routine] with suppressed positive response
+    pub async fn start_routine_suppressed(
+        &self,
+        routine_identifier: u16,
+        routine_control_option_record: &[u8],
+    ) -> EcuResponseResult {
+        self.routine_control_suppressed(
+            RoutineControlType::StartRoutine,
+            routine_identifier,
+            routine_control_option_record,
+        )
+        .await
+    }
+
+    pub async fn stop_routine(
+        &self,
+        routine_identifier: u16,
+        routine_control_option_record: &[u8],
+    ) -> EcuResponseResult {
+        self.routine_control(
+            RoutineControlType::StopRoutine,
+            routine_identifier,
+            routine_control_option_record,
+        )
+        .await
+    }
+
+    /// Same as [UdsClient::stop_routine] with suppressed positive response
+    pub async fn stop_routine_suppressed(
+        &self,
+        routine_identifier: u16,
+        routine_control_option_record: &[u8],
+    ) -> EcuResponseResult {
+        self.routine_control_suppressed(
+            RoutineControlType::StopRoutine,
+            routine_identifier,
+            routine_control_option_record,
+        )
+        .await
+    }
+
+    pub async fn request_routine_results(&self, routine_identifier: u16) -> EcuResponseResult {
+        self.routine_control(
+            RoutineControlType::RequestRoutineResults,
+            routine_identifier,
+            &[],
+        )
+        .await
+    }
+
+    async fn routine_control_suppressible(
+        &self,
+        routine_control_type: RoutineControlType,
+        routine_identifier: u16,
+        routine_control_option_record: &[u8],
+        suppress_positive_response: bool,
+    ) -> EcuResponseResult {
+        let request = compose_routine_control_request(
+            routine_control_type,
+            routine_identifier,
+            routine_control_option_record,
+            suppress_positive_response,
+        );
+        let Some(raw_response) = self
+            .send_and_receive_suppressible(&request, suppress_positive_response)
+            .await?
+        else {
+            return Ok(UdsResponse::PositiveResponseSuppressed);
+        };
+        check_routine_identifier(routine_identifier, &raw_response)?;
+        parse_routine_control_response(&raw_response)
+    }
+}
+
+fn compose_routine_control_request(
+    routine_control_type: RoutineControlType,
+    routine_identifier: u16,
+    routine_control_option_record: &[u8],
+    suppress_positive_response: bool,
+) -> Vec<u8> {
+    let mut request = vec![
+        ROUTINE_CONTROL_SID,
+        compose_sub_function(routine_control_type as u8, suppress_positive_response),
+    ];
+    request.extend_from_slice(&routine_identifier.to_be_bytes());
+    request.extend_from_slice(routine_control_option_record);
+    request
+}
+
+fn check_routine_identifier(expected: u16, raw_response: &[u8]) -> Result<(), UdsError> {
+    if let [_, _, rid_hi, rid_lo, ..] = raw_response {
+        let received = u16::from_be_bytes([*rid_hi, *rid_lo]);
+        if received != expected {
+            return Err(UdsError::RidMismatch {
+                expected,
+                received,
+                raw_message: raw_response.to_vec(),
+            });
+        }
+    }
+    Ok(())
+}
+
+pub(super) fn parse_routine_control_response(raw_response: &[u8]) -> EcuResponseResult {
+    let mut response_iter = raw_response.iter();
+    let sid = *response_iter.next().ok_or(UdsError::ResponseEmpty)?;
+    if sid != ROUTINE_CONTROL_SID + SEND_RECEIVE_SID_OFFSET {
+        return Err(UdsError::SidMismatch {
+            expected: ROUTINE_CONTROL_SID + SEND_RECEIVE_SID_OFFSET,
+            received: sid,
+            raw_message: raw_response.to_vec(),
+        });
+    }
+    let routine_control_type_byte = *response_iter.next().ok_or(UdsError::InvalidLength {
+        raw_message: raw_response.to_vec(),
+    })?;
+    let routine_control_type =
+        RoutineControlType::try_from_primitive(parse_sub_function(routine_control_type_byte))
+            .map_err(|_| UdsError::ResponseIncorrect {
+                raw_message: raw_response.to_vec(),
+            })?;
+    let rid_hi = *response_iter.next().ok_or(UdsError::InvalidLength {
+        raw_message: raw_response.to_vec(),
+    })?;
+    let rid_lo = *response_iter.next().ok_or(UdsError::InvalidLength {
+        raw_message: raw_response.to_vec(),
+    })?;
+    let routine_info = response_iter.next().copied();
+    let routine_status_record = response_iter.copied().collect();
+    let response = UdsResponse::RoutineControl(DataFormat::Parsed(RoutineControlResponse {
+        routine_control_type,
+        routine_identifier: u16::from_be_bytes([rid_hi, rid_lo]),
+        routine_info,
+        routine_status_record,
+    }));
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compose_request() {
+        assert_eq!(
+            compose_routine_control_request(
+                RoutineControlType::StartRoutine,
+                ROUTINE_ERASE_MEMORY,
+                &[0x44, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x80, 0x00],
+                false
+            ),
+            vec![0x31, 0x01, 0xff, 0x00, 0x44, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x80, 0x00]
+        );
+        assert_eq!(
+            compose_routine_control_request(RoutineControlType::StopRoutine, 0x1234, &[], true),
+            vec![0x31, 0x82, 0x12, 0x34]
+        );
+    }
+
+    #[test]
+    fn test_parse_response() {
+        assert_eq!(
+            parse_routine_control_response(&[0x71, 0x03, 0x02, 0x02, 0x10, 0x00, 0x01]),
+            Ok(UdsResponse::RoutineControl(DataFormat::Parsed(
+                RoutineControlResponse {
+                    routine_control_type: RoutineControlType::RequestRoutineResults,
+                    routine_identifier: ROUTINE_CHECK_MEMORY,
+                    routine_info: Some(0x10),
+                    routine_status_record: vec![0x00, 0x01],
+                }
+            )))
+        );
+        assert_eq!(
+            parse_routine_control_response(&[0x71, 0x01, 0xff, 0x01]),
+            Ok(UdsResponse::RoutineControl(DataFormat::Parsed(
+                RoutineControlResponse {
+                    routine_control_type: RoutineControlType::StartRoutine,
+                    routine_identifier: ROUTINE_CHECK_PROGRAMMING_DEPENDENCIES,
+                    routine_info: None,
+                    routine_status_record: vec![],
+                }
+            )))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_routine_identifier_mismatch() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x31, 0x01, 0xff, 0x00])
+            .respond(&[0x71, 0x01, 0xff, 0x01]);
+        let client = UdsClient::new_from_transport(mock.clone());
+
+        let result = client.start_routine(ROUTINE_ERASE_MEMORY, &[]).await;
+        assert_eq!(
+            result,
+            Err(UdsError::RidMismatch {
+                expected: 0xff00,
+                received: 0xff01,
+                raw_message: vec![0x71, 0x01, 0xff, 0x01],
+            })
+        );
+        mock.assert_done();
+    }
+}
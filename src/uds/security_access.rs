@@ -0,0 +1,359 @@
+//! # Implementation of SecurityAccess 0x27 service
+//!
+//! This module provides following methods for UdsClient:
+//!
+//! [UdsClient::security_access_request_seed]
+//! [UdsClient::security_access_send_key]
+//! [UdsClient::unlock]
+//!
+//! Security level is identified by its requestSeed subfunction (odd value), the key is sent with
+//! the following even subfunction. Computation of the key is specific for each ECU and level, it
+//! is provided by the caller as [SeedKeyAlgorithm].
+//!
+//! NRC 0x35, 0x36 and 0x37 are reported as [UdsError::InvalidKey],
+//! [UdsError::ExceededNumberOfAttempts] and [UdsError::RequiredTimeDelayNotExpired]. Request of
+//! the seed rejected with 0x37 can be repeated automatically, see
+//! [RetryPolicy::max_security_delay_retries].
+//!
+use super::*;
+use crate::uds::uds_definitions::SEND_RECEIVE_SID_OFFSET;
+
+const SECURITY_ACCESS_SID: u8 = 0x27;
+/// Highest requestSeed subfunction, 0x43-0x5E are reserved and 0x5F-0x7E are ISO 26021-2 and
+/// system supplier specific
+const MAX_REQUEST_SEED_LEVEL: u8 = 0x7D;
+
+/// Computation of the key from the seed, implemented by the caller for each ECU and security level.
+///
+/// Implemented for closures `Fn(u8, &[u8]) -> Vec<u8>`.
+pub trait SeedKeyAlgorithm {
+    /// `level` is the requestSeed subfunction, the seed is never all zeros
+    fn compute_key(&self, level: u8, seed: &[u8]) -> Result<Vec<u8>, UdsError>;
+}
+
+impl<F> SeedKeyAlgorithm for F
+where
+    F: Fn(u8, &[u8]) -> Vec<u8>,
+{
+    fn compute_key(&self, level: u8, seed: &[u8]) -> Result<Vec<u8>, UdsError> {
+        Ok(self(level, seed))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SecurityAccessResponse {
+    pub security_access_type: u8,
+    /// empty for sendKey response
+    pub security_seed: Vec<u8>,
+}
+
+impl SecurityAccessResponse {
+    /// Server responds with seed of all zeros, when the level is already unlocked
+    pub fn is_unlocked(&self) -> bool {
+        !self.security_seed.is_empty() && self.security_seed.iter().all(|b| *b == 0)
+    }
+}
+
+impl UdsClient {
+    /// Request seed for the security level given by odd requestSeed subfunction.
+    ///
+    /// Request rejected by NRC 0x37 is repeated according to the [RetryPolicy].
+    pub async fn security_access_request_seed(
+        &self,
+        level: u8,
+        security_access_data_record: &[u8],
+    ) -> EcuResponseResult {
+        let raw_response = self
+            .send_request_seed(level, security_access_data_record)
+            .await?;
+        parse_security_access_response(&raw_response)
+    }
+
+    /// Send key for the security level given by odd requestSeed subfunction, the key is sent
+    /// with subfunction `level + 1`
+    pub async fn security_access_send_key(&self, level: u8, key: &[u8]) -> EcuResponseResult {
+        check_level(level)?;
+        let request = compose_security_access_request(level + 1, key);
+        let raw_response = map_security_error(self.send_and_receive(&request).await, level)?;
+        check_security_access_type(level + 1, &raw_response)?;
+        parse_security_access_response(&raw_response)
+    }
+
+    /// Perform the whole seed and key exchange. Returns Ok also when the level was already
+    /// unlocked and no key was sent.
+    pub async fn unlock(
+        &self,
+        level: u8,
+        algorithm: &impl SeedKeyAlgorithm,
+    ) -> Result<(), UdsError> {
+        let raw_response = self.send_request_seed(level, &[]).await?;
+        let seed =
+            expect_parsed(
+                &raw_response,
+                parse_security_access_response,
+                |response| match response {
+                    UdsResponse::SecurityAccess(DataFormat::Parsed(response)) => Some(response),
+                    _ => None,
+                },
+            )?;
+        if seed.is_unlocked() {
+            info!("Security level {:#x} is already unlocked", level);
+            return Ok(());
+        }
+        let key = algorithm.compute_key(level, &seed.security_seed)?;
+        self.security_access_send_key(level, &key).await?;
+        info!("Security level {:#x} unlocked", level);
+        Ok(())
+    }
+
+    /// Request seed, repeated when rejected by NRC 0x37, and check the echoed level
+    async fn send_request_seed(
+        &self,
+        level: u8,
+        security_access_data_record: &[u8],
+    ) -> Result<Vec<u8>, UdsError> {
+        check_level(level)?;
+        let request = compose_security_access_request(level, security_access_data_record);
+        let mut retries = 0;
+        loop {
+            let result = self.send_and_receive(&request).await;
+            let policy = self.retry_policy();
+            match map_security_error(result, level) {
+                Err(UdsError::RequiredTimeDelayNotExpired { .. })
+                    if retries < policy.max_security_delay_retries =>
+                {
+                    retries += 1;
+                    info!(
+                        "Security access delay not expired, requesting seed again in {:?} ({}/{})",
+                        policy.security_delay, retries, policy.max_security_delay_retries
+                    );
+                    tokio::time::sleep(policy.security_delay).await;
+                }
+                Ok(raw_response) => {
+                    check_security_access_type(level, &raw_response)?;
+                    return Ok(raw_response);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+fn check_level(level: u8) -> Result<(), UdsError> {
+    // requestSeed levels are odd, even ones are sendKey
+    if level % 2 != 1 || level > MAX_REQUEST_SEED_LEVEL {
+        error!(
+            "Security level {:#x} is not valid requestSeed subfunction",
+            level
+        );
+        return Err(UdsError::InvalidArgument);
+    }
+    Ok(())
+}
+
+/// Replace NRCs specific for SecurityAccess by dedicated errors
+fn map_security_error(result: Result<Vec<u8>, UdsError>, level: u8) -> Result<Vec<u8>, UdsError> {
+    result.map_err(|e| match e {
+        UdsError::NRC { nrc } => match nrc.nrc {
+            NegativeResponseCode::InvalidKey => UdsError::InvalidKey { level },
+            NegativeResponseCode::ExceededNumberOfAttempts => {
+                UdsError::ExceededNumberOfAttempts { level }
+            }
+            NegativeResponseCode::RequiredTimeDelayNotExpired => {
+                UdsError::RequiredTimeDelayNotExpired { level }
+            }
+            _ => UdsError::NRC { nrc },
+        },
+        e => e,
+    })
+}
+
+fn check_security_access_type(expected: u8, raw_response: &[u8]) -> Result<(), UdsError> {
+    if let [_, received, ..] = raw_response {
+        if *received != expected {
+            return Err(UdsError::SecurityAccessTypeMismatch {
+                expected,
+                received: *received,
+                raw_message: raw_response.to_vec(),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn compose_security_access_request(security_access_type: u8, data: &[u8]) -> Vec<u8> {
+    let mut request = vec![SECURITY_ACCESS_SID, security_access_type];
+    request.extend_from_slice(data);
+    request
+}
+
+pub(super) fn parse_security_access_response(raw_response: &[u8]) -> EcuResponseResult {
+    let mut response_iter = raw_response.iter();
+    let sid = *response_iter.next().ok_or(UdsError::ResponseEmpty)?;
+    if sid != SECURITY_ACCESS_SID + SEND_RECEIVE_SID_OFFSET {
+        return Err(UdsError::SidMismatch {
+            expected: SECURITY_ACCESS_SID + SEND_RECEIVE_SID_OFFSET,
+            received: sid,
+            raw_message: raw_response.to_vec(),
+        });
+    }
+    let security_access_type = *response_iter.next().ok_or(UdsError::InvalidLength {
+        raw_message: raw_response.to_vec(),
+    })?;
+    let security_seed: Vec<u8> = response_iter.copied().collect();
+    // requestSeed response has to contain the seed
+    if security_access_type % 2 == 1 && security_seed.is_empty() {
+        return Err(UdsError::InvalidLength {
+            raw_message: raw_response.to_vec(),
+        });
+    }
+    Ok(UdsResponse::SecurityAccess(DataFormat::Parsed(
+        SecurityAccessResponse {
+            security_access_type,
+            security_seed,
+        },
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn xor_algorithm(_level: u8, seed: &[u8]) -> Vec<u8> {
+        seed.iter().map(|b| b ^ 0xff).collect()
+    }
+
+    #[test]
+    fn test_parse_response() {
+        assert_eq!(
+            parse_security_access_response(&[0x67, 0x01, 0x12, 0x34]),
+            Ok(UdsResponse::SecurityAccess(DataFormat::Parsed(
+                SecurityAccessResponse {
+                    security_access_type: 0x01,
+                    security_seed: vec![0x12, 0x34],
+                }
+            )))
+        );
+        assert_eq!(
+            parse_security_access_response(&[0x67, 0x01]),
+            Err(UdsError::InvalidLength {
+                raw_message: vec![0x67, 0x01]
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unlock() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x27, 0x03])
+            .respond(&[0x67, 0x03, 0x12, 0x34]);
+        mock.expect(&[0x27, 0x04, 0xed, 0xcb])
+            .respond(&[0x67, 0x04]);
+        let client = UdsClient::new_from_transport(mock.clone());
+
+        assert_eq!(client.unlock(0x03, &xor_algorithm).await, Ok(()));
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_unlock_already_unlocked() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x27, 0x01])
+            .respond(&[0x67, 0x01, 0x00, 0x00]);
+        let client = UdsClient::new_from_transport(mock.clone());
+
+        assert_eq!(client.unlock(0x01, &xor_algorithm).await, Ok(()));
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_security_access_type_mismatch() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x27, 0x03])
+            .respond(&[0x67, 0x01, 0x12, 0x34]);
+        mock.expect(&[0x27, 0x04, 0xed, 0xcb])
+            .respond(&[0x67, 0x02]);
+        let client = UdsClient::new_from_transport(mock.clone());
+
+        assert_eq!(
+            client.security_access_request_seed(0x03, &[]).await,
+            Err(UdsError::SecurityAccessTypeMismatch {
+                expected: 0x03,
+                received: 0x01,
+                raw_message: vec![0x67, 0x01, 0x12, 0x34]
+            })
+        );
+        assert_eq!(
+            client.security_access_send_key(0x03, &[0xed, 0xcb]).await,
+            Err(UdsError::SecurityAccessTypeMismatch {
+                expected: 0x04,
+                received: 0x02,
+                raw_message: vec![0x67, 0x02]
+            })
+        );
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_invalid_key() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x27, 0x01]).respond(&[0x67, 0x01, 0x12]);
+        mock.expect(&[0x27, 0x02, 0x12]).respond_nrc(0x27, 0x35);
+        let client = UdsClient::new_from_transport(mock.clone());
+
+        let result = client
+            .unlock(0x01, &|_: u8, seed: &[u8]| seed.to_vec())
+            .await;
+        assert_eq!(result, Err(UdsError::InvalidKey { level: 0x01 }));
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_required_time_delay_is_retried() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x27, 0x01]).respond_nrc(0x27, 0x37);
+        mock.expect(&[0x27, 0x01]).respond_nrc(0x27, 0x37);
+        let client = UdsClient::new_from_transport(mock.clone());
+        client.set_retry_policy(RetryPolicy {
+            max_security_delay_retries: 1,
+            security_delay: Duration::from_millis(10),
+            ..RetryPolicy::default()
+        });
+
+        let result = client.security_access_request_seed(0x01, &[]).await;
+        assert_eq!(
+            result,
+            Err(UdsError::RequiredTimeDelayNotExpired { level: 0x01 })
+        );
+        mock.assert_done();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_unlock_after_required_time_delay() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x27, 0x01]).respond_nrc(0x27, 0x37);
+        mock.expect(&[0x27, 0x01])
+            .respond(&[0x67, 0x01, 0x12, 0x34]);
+        mock.expect(&[0x27, 0x02, 0xed, 0xcb])
+            .respond(&[0x67, 0x02]);
+        let client = UdsClient::new_from_transport(mock.clone());
+        client.set_retry_policy(RetryPolicy {
+            max_security_delay_retries: 1,
+            ..RetryPolicy::default()
+        });
+
+        let start = tokio::time::Instant::now();
+        assert_eq!(client.unlock(0x01, &xor_algorithm).await, Ok(()));
+        assert!(start.elapsed() >= RetryPolicy::default().security_delay);
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_even_level_is_rejected() {
+        let client = UdsClient::new_from_transport(MockTransport::new());
+        assert_eq!(
+            client.security_access_send_key(0x02, &[0x00]).await,
+            Err(UdsError::InvalidArgument)
+        );
+    }
+}
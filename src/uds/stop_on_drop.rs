@@ -0,0 +1,141 @@
+//! # Undoing of the server state
+//!
+//! Subscriptions and guards returned by the client set up state in the server (periodic data,
+//! ResponseOnEvent, control of an input or output), which has to be undone once they are not used
+//! anymore. [StopOnDrop] sends the undoing [StopRequest] when asked to, or in background when it is
+//! dropped without successful stop, e.g. when the test fails or is cancelled.
+use crate::uds::{EcuResponseResult, UdsClient};
+use futures::future::BoxFuture;
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+use tokio::runtime::Handle;
+
+/// Request undoing the state set up in the server
+pub(super) trait StopRequest: Send + 'static {
+    fn send<'a>(&'a self, client: &'a UdsClient) -> BoxFuture<'a, EcuResponseResult>;
+
+    /// Describes the request in the log, e.g. "Stopping ResponseOnEvent"
+    fn describe(&self) -> String;
+
+    /// Called once the request succeeded or its last attempt finished
+    fn finished(&self, _client: &UdsClient) {}
+}
+
+pub(super) struct StopOnDrop<S: StopRequest> {
+    client: UdsClient,
+    /// background stop is spawned on the runtime which set up the state
+    handle: Handle,
+    /// None only during drop
+    request: Option<S>,
+    stopped: bool,
+}
+
+impl<S: StopRequest> StopOnDrop<S> {
+    /// Has to be called from within tokio runtime
+    pub(super) fn new(client: UdsClient, request: S) -> StopOnDrop<S> {
+        StopOnDrop {
+            client,
+            handle: Handle::current(),
+            request: Some(request),
+            stopped: false,
+        }
+    }
+
+    /// Created before the state is set up, so [StopRequest::finished] is called also when the
+    /// setup fails or is cancelled. [StopOnDrop::mark_started] once the setup succeeds.
+    pub(super) fn new_stopped(client: UdsClient, request: S) -> StopOnDrop<S> {
+        let mut stop = StopOnDrop::new(client, request);
+        stop.stopped = true;
+        stop
+    }
+
+    pub(super) fn mark_started(&mut self) {
+        self.stopped = false;
+    }
+
+    pub(super) fn request(&self) -> &S {
+        self.request.as_ref().unwrap()
+    }
+
+    /// Send the request and wait for the response. When it fails, the request is sent once more
+    /// in background on drop.
+    pub(super) async fn stop(&mut self) -> EcuResponseResult {
+        let result = self.request().send(&self.client).await;
+        self.stopped = result.is_ok();
+        result
+    }
+}
+
+impl<S: StopRequest> Drop for StopOnDrop<S> {
+    fn drop(&mut self) {
+        let Some(request) = self.request.take() else {
+            return;
+        };
+        let client = self.client.clone();
+        if self.stopped {
+            request.finished(&client);
+            return;
+        }
+        // task spawned while the runtime is shutting down never runs
+        self.handle.spawn(async move {
+            if let Err(e) = request.send(&client).await {
+                error!("{} failed: {:?}", request.describe(), e);
+            }
+            request.finished(&client);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uds::MockTransport;
+    use futures::FutureExt;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    struct TesterPresentStop {
+        finished: Arc<AtomicUsize>,
+    }
+
+    impl StopRequest for TesterPresentStop {
+        fn send<'a>(&'a self, client: &'a UdsClient) -> BoxFuture<'a, EcuResponseResult> {
+            client.tester_present().boxed()
+        }
+
+        fn describe(&self) -> String {
+            "Sending TesterPresent".to_string()
+        }
+
+        fn finished(&self, _client: &UdsClient) {
+            self.finished.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_stop_is_retried_on_drop() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x3e, 0x00]).respond_nrc(0x3e, 0x22);
+        mock.expect(&[0x3e, 0x00]).respond(&[0x7e, 0x00]);
+        let client = UdsClient::new_from_transport(mock.clone());
+        let finished = Arc::new(AtomicUsize::new(0));
+
+        let mut stop = StopOnDrop::new(
+            client,
+            TesterPresentStop {
+                finished: finished.clone(),
+            },
+        );
+        assert!(stop.stop().await.is_err());
+        drop(stop);
+        tokio::time::timeout(Duration::from_secs(1), mock.wait_for_expectations())
+            .await
+            .unwrap();
+        mock.assert_done();
+        while finished.load(Ordering::SeqCst) == 0 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(finished.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,313 @@
+//! # Implementation of TesterPresent 0x3E service
+//!
+//! This module provides following methods for UdsClient:
+//!
+//! [UdsClient::tester_present]
+//! [UdsClient::tester_present_suppressed]
+//! [UdsClient::set_tester_present_interval]
+//!
+//! and following methods for FunctionalUdsClient:
+//!
+//! [FunctionalUdsClient::tester_present]
+//! [FunctionalUdsClient::tester_present_suppressed]
+//!
+//! Besides the single request, the client keeps non-default session alive automatically. When
+//! [UdsClient::diagnostic_session_control] enters non-default session, background task starts to
+//! send TesterPresent with suppressed positive response, whenever there was no other
+//! communication with the server for the configured interval. The task is stopped when default
+//! session is entered again or when the client is dropped.
+//!
+use crate::uds::dispatcher::Dispatcher;
+use crate::uds::uds_definitions::{
+    compose_sub_function, parse_sub_function, SEND_RECEIVE_SID_OFFSET,
+};
+use crate::uds::{
+    EcuResponseResult, FunctionalResponses, FunctionalUdsClient, UdsClient, UdsError, UdsResponse,
+};
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+const TESTER_PRESENT_SID: u8 = 0x3E;
+const ZERO_SUB_FUNCTION: u8 = 0x00;
+
+/// Server falls back to the default session, when no request is received within S3 time
+pub const S3_SERVER: Duration = Duration::from_millis(5000);
+pub const DEFAULT_TESTER_PRESENT_INTERVAL: Duration = Duration::from_millis(2000);
+
+/// State of the automatic TesterPresent
+pub(super) struct KeepAlive {
+    interval: Option<Duration>,
+    task: Option<JoinHandle<()>>,
+}
+
+impl Default for KeepAlive {
+    fn default() -> Self {
+        KeepAlive {
+            interval: Some(DEFAULT_TESTER_PRESENT_INTERVAL),
+            task: None,
+        }
+    }
+}
+
+impl KeepAlive {
+    fn start(&mut self, dispatcher: &Arc<Dispatcher>) {
+        self.stop();
+        if let Some(interval) = self.interval {
+            debug!(
+                "Starting TesterPresent keep-alive with interval {:?}",
+                interval
+            );
+            self.task = Some(tokio::spawn(keep_alive_loop(
+                Arc::downgrade(dispatcher),
+                interval,
+            )));
+        }
+    }
+
+    fn stop(&mut self) {
+        if let Some(task) = self.task.take() {
+            debug!("Stopping TesterPresent keep-alive");
+            task.abort();
+        }
+    }
+}
+
+impl Drop for KeepAlive {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+impl UdsClient {
+    pub async fn tester_present(&self) -> EcuResponseResult {
+        self.tester_present_suppressible(false).await
+    }
+
+    /// Same as [UdsClient::tester_present] with suppressed positive response. Returns
+    /// [UdsResponse::PositiveResponseSuppressed] unless the server responds with NRC.
+    pub async fn tester_present_suppressed(&self) -> EcuResponseResult {
+        self.tester_present_suppressible(true).await
+    }
+
+    async fn tester_present_suppressible(
+        &self,
+        suppress_positive_response: bool,
+    ) -> EcuResponseResult {
+        let request = compose_tester_present_request(suppress_positive_response);
+        let Some(raw_response) = self
+            .send_and_receive_suppressible(&request, suppress_positive_response)
+            .await?
+        else {
+            return Ok(UdsResponse::PositiveResponseSuppressed);
+        };
+        parse_tester_present_response(&raw_response)
+    }
+
+    /// Set interval of the automatic TesterPresent, None disables it. Interval has to be shorter
+    /// than [S3_SERVER]. If the keep-alive is already running, it is restarted with the new value.
+    pub fn set_tester_present_interval(&self, interval: Option<Duration>) -> Result<(), UdsError> {
+        if let Some(interval) = interval {
+            if interval.is_zero() || interval >= S3_SERVER {
+                error!(
+                    "TesterPresent interval {:?} has to be shorter than S3 {:?}",
+                    interval, S3_SERVER
+                );
+                return Err(UdsError::InvalidArgument);
+            }
+        }
+        let mut keep_alive = self.keep_alive.lock().unwrap();
+        keep_alive.interval = interval;
+        if keep_alive.task.is_some() {
+            keep_alive.start(&self.dispatcher);
+        }
+        Ok(())
+    }
+
+    pub fn tester_present_interval(&self) -> Option<Duration> {
+        self.keep_alive.lock().unwrap().interval
+    }
+
+    pub(super) fn start_keep_alive(&self) {
+        self.keep_alive.lock().unwrap().start(&self.dispatcher);
+    }
+
+    pub(super) fn stop_keep_alive(&self) {
+        self.keep_alive.lock().unwrap().stop();
+    }
+}
+
+impl FunctionalUdsClient {
+    pub async fn tester_present(&self) -> Result<FunctionalResponses, UdsError> {
+        self.tester_present_suppressible(false).await
+    }
+
+    /// Same as [FunctionalUdsClient::tester_present] with suppressed positive response, only the
+    /// ECUs responding with NRC are in the result
+    pub async fn tester_present_suppressed(&self) -> Result<FunctionalResponses, UdsError> {
+        self.tester_present_suppressible(true).await
+    }
+
+    async fn tester_present_suppressible(
+        &self,
+        suppress_positive_response: bool,
+    ) -> Result<FunctionalResponses, UdsError> {
+        let request = compose_tester_present_request(suppress_positive_response);
+        self.send_and_collect(&request, parse_tester_present_response)
+            .await
+    }
+}
+
+/// Sends TesterPresent whenever there was no communication for `interval`, until the
+/// dispatcher is dropped
+async fn keep_alive_loop(dispatcher: Weak<Dispatcher>, interval: Duration) {
+    let request = compose_tester_present_request(true);
+    loop {
+        let next = match dispatcher.upgrade() {
+            Some(dispatcher) => dispatcher.last_activity() + interval,
+            None => break,
+        };
+        if next > Instant::now() {
+            tokio::time::sleep_until(next).await;
+            continue;
+        }
+        let Some(dispatcher) = dispatcher.upgrade() else {
+            break;
+        };
+        let _lock = dispatcher.lock().await;
+        if dispatcher.last_activity() + interval > Instant::now() {
+            // other request was sent while waiting for the lock
+            continue;
+        }
+        trace!("Sending TesterPresent keep-alive");
+        if let Err(e) = dispatcher.send(&request).await {
+            warn!("Sending TesterPresent keep-alive failed: {:?}", e);
+        }
+    }
+}
+
+fn compose_tester_present_request(suppress_positive_response: bool) -> Vec<u8> {
+    vec![
+        TESTER_PRESENT_SID,
+        compose_sub_function(ZERO_SUB_FUNCTION, suppress_positive_response),
+    ]
+}
+
+pub(super) fn parse_tester_present_response(raw_response: &[u8]) -> EcuResponseResult {
+    let mut response_iter = raw_response.iter();
+    let sid = *response_iter.next().ok_or(UdsError::ResponseEmpty)?;
+    if sid != TESTER_PRESENT_SID + SEND_RECEIVE_SID_OFFSET {
+        error!("Raw response: {:x?}", raw_response);
+        return Err(UdsError::SidMismatch {
+            expected: TESTER_PRESENT_SID + SEND_RECEIVE_SID_OFFSET,
+            received: sid,
+            raw_message: raw_response.to_vec(),
+        });
+    }
+    let sub_function = *response_iter.next().ok_or(UdsError::InvalidLength {
+        raw_message: raw_response.to_vec(),
+    })?;
+    if parse_sub_function(sub_function) != ZERO_SUB_FUNCTION {
+        return Err(UdsError::ResponseIncorrect {
+            raw_message: raw_response.to_vec(),
+        });
+    }
+    Ok(UdsResponse::TesterPresent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uds::{MockTransport, ResetType, ResponseTiming};
+
+    #[test]
+    fn test_compose_request() {
+        assert_eq!(compose_tester_present_request(false), vec![0x3e, 0x00]);
+        assert_eq!(compose_tester_present_request(true), vec![0x3e, 0x80]);
+    }
+
+    #[test]
+    fn test_parse_response() {
+        assert_eq!(
+            parse_tester_present_response(&[0x7e, 0x00]),
+            Ok(UdsResponse::TesterPresent)
+        );
+        assert_eq!(
+            parse_tester_present_response(&[0x7e]),
+            Err(UdsError::InvalidLength {
+                raw_message: vec![0x7e]
+            })
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_keep_alive_follows_session() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x10, 0x03])
+            .respond(&[0x50, 0x03, 0x00, 0x32, 0x01, 0xf4]);
+        mock.expect(&[0x3e, 0x80]);
+        mock.expect(&[0x3e, 0x80]);
+        mock.expect(&[0x10, 0x01])
+            .respond(&[0x50, 0x01, 0x00, 0x32, 0x01, 0xf4]);
+        let client = UdsClient::new_from_transport(mock.clone());
+        client
+            .set_tester_present_interval(Some(Duration::from_millis(100)))
+            .unwrap();
+
+        client.diagnostic_session_control(0x03).await.unwrap();
+        // clock is paused and advanced only when idle, so exactly two keep-alives fit in 250 ms
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        client.diagnostic_session_control(0x01).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        mock.assert_done();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_keep_alive_stops_after_ecu_reset() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x10, 0x03])
+            .respond(&[0x50, 0x03, 0x00, 0x19, 0x00, 0xc8]);
+        mock.expect(&[0x11, 0x01]).respond(&[0x51, 0x01]);
+        mock.expect(&[0x10, 0x03])
+            .respond(&[0x50, 0x03, 0x00, 0x19, 0x00, 0xc8]);
+        mock.expect(&[0x11, 0x83]);
+        let client = UdsClient::new_from_transport(mock.clone());
+        client
+            .set_tester_present_interval(Some(Duration::from_millis(100)))
+            .unwrap();
+        let extended_timing = ResponseTiming {
+            p2: Duration::from_millis(25),
+            p2_star: Duration::from_millis(2000),
+            ..ResponseTiming::default()
+        };
+
+        client.diagnostic_session_control(0x03).await.unwrap();
+        assert_eq!(client.response_timing(), extended_timing);
+        client.ecu_reset(ResetType::HardReset).await.unwrap();
+        assert_eq!(client.response_timing(), ResponseTiming::default());
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        client.diagnostic_session_control(0x03).await.unwrap();
+        assert_eq!(client.response_timing(), extended_timing);
+        assert_eq!(
+            client.ecu_reset_suppressed(ResetType::SoftReset).await,
+            Ok(UdsResponse::PositiveResponseSuppressed)
+        );
+        assert_eq!(client.response_timing(), ResponseTiming::default());
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_interval_has_to_be_shorter_than_s3() {
+        let client = UdsClient::new_from_transport(MockTransport::new());
+        assert_eq!(
+            client.set_tester_present_interval(Some(S3_SERVER)),
+            Err(UdsError::InvalidArgument)
+        );
+        assert_eq!(client.set_tester_present_interval(None), Ok(()));
+        assert_eq!(client.tester_present_interval(), None);
+    }
+}
@@ -0,0 +1,286 @@
+//! # Implementation of TransferData 0x36 and RequestTransferExit 0x37 services
+//!
+//! This module provides following methods for UdsClient:
+//!
+//! [UdsClient::transfer_data]
+//! [UdsClient::request_transfer_exit]
+//!
+//! Both services are used after RequestDownload or RequestUpload, see [UdsClient::download] and
+//! [UdsClient::upload] for the whole transfer.
+//!
+use super::*;
+use crate::uds::uds_definitions::SEND_RECEIVE_SID_OFFSET;
+
+pub(super) const TRANSFER_DATA_SID: u8 = 0x36;
+const REQUEST_TRANSFER_EXIT_SID: u8 = 0x37;
+/// Neither compression nor encryption is used
+pub(super) const DATA_FORMAT_UNCOMPRESSED: u8 = 0x00;
+/// How many times is the block repeated, when its response does not arrive. Server responds
+/// positively to the repeated block even if it already received it.
+const MAX_BLOCK_REPEATS: u32 = 2;
+
+#[derive(Debug, PartialEq)]
+pub struct TransferDataResponse {
+    pub block_sequence_counter: u8,
+    /// uploaded data, or manufacturer specific record for download
+    pub transfer_response_parameter_record: Vec<u8>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct RequestTransferExitResponse {
+    pub transfer_response_parameter_record: Vec<u8>,
+}
+
+impl UdsClient {
+    /// Transfer single block. Block sequence counter starts at 0x01 and wraps from 0xFF to 0x00.
+    /// Echoed block sequence counter is checked.
+    pub async fn transfer_data(
+        &self,
+        block_sequence_counter: u8,
+        transfer_request_parameter_record: &[u8],
+    ) -> EcuResponseResult {
+        let raw_response = self
+            .send_transfer_data(block_sequence_counter, transfer_request_parameter_record)
+            .await?;
+        parse_transfer_data_response(&raw_response)
+    }
+
+    pub async fn request_transfer_exit(
+        &self,
+        transfer_request_parameter_record: &[u8],
+    ) -> EcuResponseResult {
+        let mut request = vec![REQUEST_TRANSFER_EXIT_SID];
+        request.extend_from_slice(transfer_request_parameter_record);
+        let raw_response = self.send_and_receive(&request).await?;
+        parse_request_transfer_exit_response(&raw_response)
+    }
+
+    /// Transfer single block, repeated when its response does not arrive. Returns
+    /// transferResponseParameterRecord, which is the uploaded data for RequestUpload.
+    ///
+    /// Server which already accepted the block, but whose response was lost, may reject the
+    /// repeated block with NRC 0x73 WrongBlockSequenceCounter. The block is transferred then, but
+    /// its response is not available, so None is returned.
+    pub(super) async fn transfer_block(
+        &self,
+        block_sequence_counter: u8,
+        block: &[u8],
+    ) -> Result<Option<Vec<u8>>, UdsError> {
+        let mut repeats = 0;
+        loop {
+            match self.send_transfer_data(block_sequence_counter, block).await {
+                Ok(raw_response) => {
+                    return expect_parsed(&raw_response, parse_transfer_data_response, |response| {
+                        match response {
+                            UdsResponse::TransferData(DataFormat::Parsed(response)) => {
+                                Some(Some(response.transfer_response_parameter_record))
+                            }
+                            _ => None,
+                        }
+                    })
+                }
+                Err(UdsError::NRC { nrc })
+                    if repeats > 0
+                        && nrc.nrc == NegativeResponseCode::WrongBlockSequenceCounter =>
+                {
+                    info!(
+                        "Repeated block {:#x} rejected, the previous attempt was accepted",
+                        block_sequence_counter
+                    );
+                    return Ok(None);
+                }
+                Err(UdsError::ResponseTimeout { .. }) if repeats < MAX_BLOCK_REPEATS => {
+                    repeats += 1;
+                    warn!(
+                        "No response to block {:#x}, repeating ({}/{})",
+                        block_sequence_counter, repeats, MAX_BLOCK_REPEATS
+                    );
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Send single block and check the echoed block sequence counter
+    async fn send_transfer_data(
+        &self,
+        block_sequence_counter: u8,
+        transfer_request_parameter_record: &[u8],
+    ) -> Result<Vec<u8>, UdsError> {
+        let request = compose_transfer_data_request(
+            block_sequence_counter,
+            transfer_request_parameter_record,
+        );
+        let raw_response = self.send_and_receive(&request).await?;
+        if let [_, received, ..] = raw_response.as_slice() {
+            if *received != block_sequence_counter {
+                error!(
+                    "Sent block {:#x}, but response is for block {:#x}",
+                    block_sequence_counter, received
+                );
+                return Err(UdsError::ResponseIncorrect {
+                    raw_message: raw_response,
+                });
+            }
+        }
+        Ok(raw_response)
+    }
+}
+
+/// Block sequence counter following `block_sequence_counter`, 0xFF wraps to 0x00
+pub(super) fn next_block_sequence_counter(block_sequence_counter: u8) -> u8 {
+    block_sequence_counter.wrapping_add(1)
+}
+
+/// Compose RequestDownload or RequestUpload request given by `sid`, both have the same format
+pub(super) fn compose_request_transfer_request(
+    sid: u8,
+    data_format_identifier: u8,
+    address_and_length_format_identifier: u8,
+    memory_address: &[u8],
+    memory_size: &[u8],
+) -> Vec<u8> {
+    let mut request = vec![
+        sid,
+        data_format_identifier,
+        address_and_length_format_identifier,
+    ];
+    request.extend_from_slice(memory_address);
+    request.extend_from_slice(memory_size);
+    request
+}
+
+/// Number of data bytes in single TransferData block, maxNumberOfBlockLength includes SID and
+/// block sequence counter. Block has to carry at least one byte.
+pub(super) fn block_data_len(
+    max_number_of_block_length: u64,
+    raw_response: &[u8],
+) -> Result<usize, UdsError> {
+    let block_len = max_number_of_block_length.saturating_sub(2);
+    if block_len == 0 {
+        error!(
+            "Server accepts blocks of {} bytes only",
+            max_number_of_block_length
+        );
+        return Err(UdsError::ResponseIncorrect {
+            raw_message: raw_response.to_vec(),
+        });
+    }
+    Ok(usize::try_from(block_len).unwrap_or(usize::MAX))
+}
+
+/// Parse lengthFormatIdentifier followed by maxNumberOfBlockLength, shared by RequestDownload and
+/// RequestUpload responses. Returned length includes SID and block sequence counter.
+pub(super) fn parse_max_number_of_block_length(
+    response_iter: &mut std::slice::Iter<u8>,
+    raw_response: &[u8],
+) -> Result<u64, UdsError> {
+    let length_format_identifier = *response_iter.next().ok_or(UdsError::InvalidLength {
+        raw_message: raw_response.to_vec(),
+    })?;
+    let length_len = (length_format_identifier >> 4) as usize;
+    if length_len == 0 || length_len > 8 {
+        return Err(UdsError::ResponseIncorrect {
+            raw_message: raw_response.to_vec(),
+        });
+    }
+    let mut max_number_of_block_length = 0u64;
+    for _ in 0..length_len {
+        let byte = *response_iter.next().ok_or(UdsError::InvalidLength {
+            raw_message: raw_response.to_vec(),
+        })?;
+        max_number_of_block_length = (max_number_of_block_length << 8) + byte as u64;
+    }
+    Ok(max_number_of_block_length)
+}
+
+fn compose_transfer_data_request(
+    block_sequence_counter: u8,
+    transfer_request_parameter_record: &[u8],
+) -> Vec<u8> {
+    let mut request = vec![TRANSFER_DATA_SID, block_sequence_counter];
+    request.extend_from_slice(transfer_request_parameter_record);
+    request
+}
+
+pub(super) fn parse_transfer_data_response(raw_response: &[u8]) -> EcuResponseResult {
+    let mut response_iter = raw_response.iter();
+    let sid = *response_iter.next().ok_or(UdsError::ResponseEmpty)?;
+    if sid != TRANSFER_DATA_SID + SEND_RECEIVE_SID_OFFSET {
+        return Err(UdsError::SidMismatch {
+            expected: TRANSFER_DATA_SID + SEND_RECEIVE_SID_OFFSET,
+            received: sid,
+            raw_message: raw_response.to_vec(),
+        });
+    }
+    let block_sequence_counter = *response_iter.next().ok_or(UdsError::InvalidLength {
+        raw_message: raw_response.to_vec(),
+    })?;
+    Ok(UdsResponse::TransferData(DataFormat::Parsed(
+        TransferDataResponse {
+            block_sequence_counter,
+            transfer_response_parameter_record: response_iter.copied().collect(),
+        },
+    )))
+}
+
+pub(super) fn parse_request_transfer_exit_response(raw_response: &[u8]) -> EcuResponseResult {
+    let mut response_iter = raw_response.iter();
+    let sid = *response_iter.next().ok_or(UdsError::ResponseEmpty)?;
+    if sid != REQUEST_TRANSFER_EXIT_SID + SEND_RECEIVE_SID_OFFSET {
+        return Err(UdsError::SidMismatch {
+            expected: REQUEST_TRANSFER_EXIT_SID + SEND_RECEIVE_SID_OFFSET,
+            received: sid,
+            raw_message: raw_response.to_vec(),
+        });
+    }
+    Ok(UdsResponse::RequestTransferExit(DataFormat::Parsed(
+        RequestTransferExitResponse {
+            transfer_response_parameter_record: response_iter.copied().collect(),
+        },
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_sequence_counter_wraps_to_zero() {
+        assert_eq!(next_block_sequence_counter(0x01), 0x02);
+        assert_eq!(next_block_sequence_counter(0xff), 0x00);
+    }
+
+    #[test]
+    fn test_parse_max_number_of_block_length() {
+        let raw_response = [0x74, 0x20, 0x0f, 0xfa];
+        let mut response_iter = raw_response[1..].iter();
+        assert_eq!(
+            parse_max_number_of_block_length(&mut response_iter, &raw_response),
+            Ok(0xffa)
+        );
+        let raw_response = [0x74, 0x20, 0x0f];
+        let mut response_iter = raw_response[1..].iter();
+        assert_eq!(
+            parse_max_number_of_block_length(&mut response_iter, &raw_response),
+            Err(UdsError::InvalidLength {
+                raw_message: raw_response.to_vec()
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_block_sequence_counter_mismatch() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x36, 0x02, 0xaa]).respond(&[0x76, 0x01]);
+        let client = UdsClient::new_from_transport(mock.clone());
+
+        assert_eq!(
+            client.transfer_data(0x02, &[0xaa]).await,
+            Err(UdsError::ResponseIncorrect {
+                raw_message: vec![0x76, 0x01]
+            })
+        );
+        mock.assert_done();
+    }
+}
@@ -6,6 +6,23 @@ pub const SEND_RECEIVE_SID_OFFSET: u8 = 0x40;
 
 pub const NEGATIVE_RESPONSE_SID: u8 = 0x7F;
 
+/// Bit 7 of the sub-function byte. When set, the server does not send positive response.
+pub const SUPPRESS_POSITIVE_RESPONSE_BIT: u8 = 0x80;
+
+/// Returns sub-function byte with the suppress positive response bit set if requested
+pub fn compose_sub_function(sub_function: u8, suppress_positive_response: bool) -> u8 {
+    if suppress_positive_response {
+        sub_function | SUPPRESS_POSITIVE_RESPONSE_BIT
+    } else {
+        sub_function
+    }
+}
+
+/// Returns sub-function byte echoed by the server without the suppress positive response bit
+pub fn parse_sub_function(sub_function: u8) -> u8 {
+    sub_function & !SUPPRESS_POSITIVE_RESPONSE_BIT
+}
+
 pub fn to_received_sid(sid: u8) -> u8 {
     sid + SEND_RECEIVE_SID_OFFSET
 }
@@ -23,8 +23,7 @@ impl UdsClient {
     ) -> EcuResponseResult {
         let request = compose_write_data_by_identifier_request(data_identifier, data_record);
         let raw_response = self.send_and_receive(&request).await?;
-        let response = parse_write_data_by_identifier_response(&raw_response);
-        response
+        parse_write_data_by_identifier_response(&raw_response)
     }
 }
 
@@ -38,7 +37,7 @@ fn compose_write_data_by_identifier_request(data_identifier: u16, data_record: &
     ret
 }
 
-fn parse_write_data_by_identifier_response(raw_response: &[u8]) -> EcuResponseResult {
+pub(super) fn parse_write_data_by_identifier_response(raw_response: &[u8]) -> EcuResponseResult {
     let mut response_iter = raw_response.iter();
     let sid = *response_iter.next().ok_or(UdsError::ResponseEmpty)?;
     if sid != WRITE_DATA_BY_IDENTIFIER_SID + SEND_RECEIVE_SID_OFFSET {
@@ -0,0 +1,320 @@
+//! # Implementation of WriteMemoryByAddress 0x3D service
+//!
+//! This module provides following methods for UdsClient:
+//!
+//! [UdsClient::write_memory_by_address]
+//! [UdsClient::write_memory_by_address_simplified]
+//! [UdsClient::write_memory_by_address_chunked]
+//!
+//! Server echoes address and size of the written memory, both are compared with the request.
+//! Writes larger than single request are split by [UdsClient::write_memory_by_address_chunked].
+//!
+use super::*;
+use crate::uds::read_memory_by_address::convert_from_simple_to_normal;
+use crate::uds::uds_definitions::SEND_RECEIVE_SID_OFFSET;
+
+const WRITE_MEMORY_BY_ADDRESS_SID: u8 = 0x3D;
+
+#[derive(Debug, PartialEq)]
+pub struct WriteMemoryByAddressResponse {
+    pub address_and_memory_length_format_identifier: u8,
+    pub memory_address: Vec<u8>,
+    pub memory_size: Vec<u8>,
+}
+
+impl UdsClient {
+    /// Takes memory address and byte size encoded in u8 slice, MSB is at position 0. Their
+    /// lengths are given by `address_and_memory_length_format_identifier`, memory size has to
+    /// correspond to the length of `data_record`.
+    pub async fn write_memory_by_address(
+        &self,
+        address_and_memory_length_format_identifier: u8,
+        memory_address: &[u8],
+        memory_size: &[u8],
+        data_record: &[u8],
+    ) -> EcuResponseResult {
+        let request = compose_write_memory_by_address_request(
+            address_and_memory_length_format_identifier,
+            memory_address,
+            memory_size,
+            data_record,
+        );
+        let raw_response = self.send_and_receive(&request).await?;
+        let response = parse_write_memory_by_address_response(&raw_response)?;
+        // echo is the request without SID and data record
+        let echo_len = request.len() - data_record.len();
+        if raw_response.get(1..) != request.get(1..echo_len) {
+            error!(
+                "Written memory {:x?} does not match requested {:x?}",
+                &raw_response[1..],
+                &request[1..echo_len]
+            );
+            return Err(UdsError::ResponseIncorrect {
+                raw_message: raw_response,
+            });
+        }
+        Ok(response)
+    }
+
+    /// address_and_memory_length_format_identifier is derived from provided arguments the same
+    /// way as in [UdsClient::read_memory_by_address_simplified], memory size is the length of
+    /// `data_record`.
+    pub async fn write_memory_by_address_simplified(
+        &self,
+        memory_address: u64,
+        data_record: &[u8],
+        memory_address_len: Option<u8>,
+        memory_size_len: Option<u8>,
+    ) -> EcuResponseResult {
+        let (address_and_memory_length_format_identifier, memory_address, memory_size) =
+            convert_from_simple_to_normal(
+                memory_address,
+                data_record.len() as u64,
+                memory_address_len,
+                memory_size_len,
+            )?;
+        self.write_memory_by_address(
+            address_and_memory_length_format_identifier,
+            &memory_address,
+            &memory_size,
+            data_record,
+        )
+        .await
+    }
+
+    /// Write `data` split into requests of at most `chunk_size` bytes. All requests use the same
+    /// address_and_memory_length_format_identifier.
+    ///
+    /// When any of the requests fails, [UdsError::TransferAborted] with number of already written
+    /// bytes is returned.
+    pub async fn write_memory_by_address_chunked(
+        &self,
+        memory_address: u64,
+        data: &[u8],
+        chunk_size: usize,
+    ) -> Result<(), UdsError> {
+        if chunk_size == 0 {
+            error!("Chunk size has to be at least 1 byte");
+            return Err(UdsError::InvalidArgument);
+        }
+        // address of the last written byte
+        let Some(last_address) = memory_address.checked_add(data.len().saturating_sub(1) as u64)
+        else {
+            error!(
+                "{} bytes starting at {:#x} exceed the address space",
+                data.len(),
+                memory_address
+            );
+            return Err(UdsError::InvalidArgument);
+        };
+        // lengths are derived from the largest address and size
+        let (address_and_memory_length_format_identifier, _, _) = convert_from_simple_to_normal(
+            last_address,
+            chunk_size.min(data.len()) as u64,
+            None,
+            None,
+        )?;
+        let memory_address_len = Some(address_and_memory_length_format_identifier & 0x0f);
+        let memory_size_len = Some(address_and_memory_length_format_identifier >> 4);
+
+        let mut written = 0;
+        for chunk in data.chunks(chunk_size) {
+            let result = self
+                .write_memory_by_address_simplified(
+                    memory_address + written as u64,
+                    chunk,
+                    memory_address_len,
+                    memory_size_len,
+                )
+                .await;
+            if let Err(reason) = result {
+                error!(
+                    "Write of memory aborted after {} of {} bytes: {:?}",
+                    written,
+                    data.len(),
+                    reason
+                );
+                return Err(UdsError::TransferAborted {
+                    transferred: written,
+                    reason: Box::new(reason),
+                });
+            }
+            written += chunk.len();
+        }
+        Ok(())
+    }
+}
+
+fn compose_write_memory_by_address_request(
+    address_and_memory_length_format_identifier: u8,
+    memory_address: &[u8],
+    memory_size: &[u8],
+    data_record: &[u8],
+) -> Vec<u8> {
+    let mut request = vec![
+        WRITE_MEMORY_BY_ADDRESS_SID,
+        address_and_memory_length_format_identifier,
+    ];
+    request.extend_from_slice(memory_address);
+    request.extend_from_slice(memory_size);
+    request.extend_from_slice(data_record);
+    request
+}
+
+pub(super) fn parse_write_memory_by_address_response(raw_response: &[u8]) -> EcuResponseResult {
+    let mut response_iter = raw_response.iter();
+    let sid = *response_iter.next().ok_or(UdsError::ResponseEmpty)?;
+    if sid != WRITE_MEMORY_BY_ADDRESS_SID + SEND_RECEIVE_SID_OFFSET {
+        return Err(UdsError::SidMismatch {
+            expected: WRITE_MEMORY_BY_ADDRESS_SID + SEND_RECEIVE_SID_OFFSET,
+            received: sid,
+            raw_message: raw_response.to_vec(),
+        });
+    }
+    let address_and_memory_length_format_identifier =
+        *response_iter.next().ok_or(UdsError::InvalidLength {
+            raw_message: raw_response.to_vec(),
+        })?;
+    let address_len = (address_and_memory_length_format_identifier & 0x0f) as usize;
+    let size_len = (address_and_memory_length_format_identifier >> 4) as usize;
+    let rest = response_iter.as_slice();
+    if rest.len() != address_len + size_len {
+        return Err(UdsError::InvalidLength {
+            raw_message: raw_response.to_vec(),
+        });
+    }
+    Ok(UdsResponse::WriteMemoryByAddress(DataFormat::Parsed(
+        WriteMemoryByAddressResponse {
+            address_and_memory_length_format_identifier,
+            memory_address: rest[..address_len].to_vec(),
+            memory_size: rest[address_len..].to_vec(),
+        },
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_response() {
+        assert_eq!(
+            parse_write_memory_by_address_response(&[0x7d, 0x14, 0x20, 0x48, 0x13, 0x00, 0x05]),
+            Ok(UdsResponse::WriteMemoryByAddress(DataFormat::Parsed(
+                WriteMemoryByAddressResponse {
+                    address_and_memory_length_format_identifier: 0x14,
+                    memory_address: vec![0x20, 0x48, 0x13, 0x00],
+                    memory_size: vec![0x05],
+                }
+            )))
+        );
+        assert_eq!(
+            parse_write_memory_by_address_response(&[0x7d, 0x12, 0x20, 0x48]),
+            Err(UdsError::InvalidLength {
+                raw_message: vec![0x7d, 0x12, 0x20, 0x48]
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_echo_mismatch() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x3d, 0x12, 0x20, 0x48, 0x02, 0xaa, 0xbb])
+            .respond(&[0x7d, 0x12, 0x20, 0x49, 0x02]);
+        let client = UdsClient::new_from_transport(mock.clone());
+
+        assert_eq!(
+            client
+                .write_memory_by_address_simplified(0x2048, &[0xaa, 0xbb], None, None)
+                .await,
+            Err(UdsError::ResponseIncorrect {
+                raw_message: vec![0x7d, 0x12, 0x20, 0x49, 0x02]
+            })
+        );
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_write_chunked() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x3d, 0x12, 0x00, 0xfe, 0x02, 0, 1])
+            .respond(&[0x7d, 0x12, 0x00, 0xfe, 0x02]);
+        mock.expect(&[0x3d, 0x12, 0x01, 0x00, 0x02, 2, 3])
+            .respond(&[0x7d, 0x12, 0x01, 0x00, 0x02]);
+        mock.expect(&[0x3d, 0x12, 0x01, 0x02, 0x01, 4])
+            .respond_nrc(0x3d, 0x72);
+        let client = UdsClient::new_from_transport(mock.clone());
+
+        assert_eq!(
+            client
+                .write_memory_by_address_chunked(0xfe, &[0, 1, 2, 3, 4], 2)
+                .await,
+            Err(UdsError::TransferAborted {
+                transferred: 4,
+                reason: Box::new(UdsError::NRC {
+                    nrc: NrcData {
+                        rejected_sid: 0x3d,
+                        nrc: NegativeResponseCode::GeneralProgrammingFailure
+                    }
+                })
+            })
+        );
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_write_chunked_beyond_address_space() {
+        let mock = MockTransport::new();
+        let client = UdsClient::new_from_transport(mock.clone());
+
+        assert_eq!(
+            client
+                .write_memory_by_address_chunked(u64::MAX - 1, &[0, 1, 2], 2)
+                .await,
+            Err(UdsError::InvalidArgument)
+        );
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_write_chunked_address_length_from_last_byte() {
+        let mock = MockTransport::new();
+        mock.expect(&[0x3d, 0x14, 0xff, 0xff, 0xff, 0xfe, 0x02, 0, 1])
+            .respond(&[0x7d, 0x14, 0xff, 0xff, 0xff, 0xfe, 0x02]);
+        let client = UdsClient::new_from_transport(mock.clone());
+
+        assert_eq!(
+            client
+                .write_memory_by_address_chunked(0xffff_fffe, &[0, 1], 2)
+                .await,
+            Ok(())
+        );
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_write_chunked_up_to_end_of_address_space() {
+        let mock = MockTransport::new();
+        mock.expect(&[
+            0x3d, 0x18, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe, 0x01, 0,
+        ])
+        .respond(&[
+            0x7d, 0x18, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe, 0x01,
+        ]);
+        mock.expect(&[
+            0x3d, 0x18, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01, 1,
+        ])
+        .respond(&[
+            0x7d, 0x18, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01,
+        ]);
+        let client = UdsClient::new_from_transport(mock.clone());
+
+        assert_eq!(
+            client
+                .write_memory_by_address_chunked(u64::MAX - 1, &[0, 1], 1)
+                .await,
+            Ok(())
+        );
+        mock.assert_done();
+    }
+}